@@ -0,0 +1,180 @@
+use sanctum::ipc::{EventReport, Reply, Request, Response, StatusReport, TrackReport};
+use sanctum::{PlayerCommand, PlayerHandle, PlayerState, Playlist, Search};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Requests for the app itself rather than the player, from MPRIS or anywhere off the UI thread.
+#[derive(Debug, Clone)]
+pub enum AppCommand {
+    SwitchPlaylist(usize),
+    /// Brings the window to the front, unminimizing it if needed.
+    Raise,
+    /// Saves the config and closes the window.
+    Quit,
+    /// Plays a file, adding it to the current song list if it isn't in it yet.
+    OpenFile(PathBuf),
+    /// Queues a file, adding it to the current song list if it isn't in it yet.
+    EnqueueFile(PathBuf),
+}
+
+/// The playlists as remotes see them, kept current by the UI.
+#[derive(Default)]
+pub struct PlaylistsSnapshot {
+    pub playlists: Vec<Playlist>,
+    pub active: usize,
+}
+
+/// Sends commands to the UI thread and wakes it up, since it idles between inputs.
+#[derive(Clone)]
+pub struct AppHandle {
+    commands: Sender<AppCommand>,
+    ctx: egui::Context,
+}
+
+impl AppHandle {
+    pub fn new(commands: Sender<AppCommand>, ctx: egui::Context) -> Self {
+        Self { commands, ctx }
+    }
+
+    pub fn send(&self, command: AppCommand) {
+        match self.commands.send(command) {
+            Ok(()) => self.ctx.request_repaint(),
+            Err(e) => eprintln!("App is gone, dropping command: {:?}", e.0),
+        }
+    }
+}
+
+/// Everything the control socket needs to answer requests off the UI thread.
+pub struct Remote {
+    pub player: PlayerHandle,
+    pub app: AppHandle,
+    pub state: Arc<Mutex<PlayerState>>,
+    pub playlists: Arc<Mutex<PlaylistsSnapshot>>,
+}
+
+impl Remote {
+    /// Carries out a control socket request, from a second launch or `sanctumctl`.
+    pub fn handle(&self, request: Request) -> Reply {
+        let response = match request {
+            Request::Open { paths, enqueue } => {
+                for (i, path) in paths.into_iter().enumerate() {
+                    let path = PathBuf::from(path);
+                    if enqueue || i > 0 {
+                        self.app.send(AppCommand::EnqueueFile(path));
+                    } else {
+                        self.app.send(AppCommand::OpenFile(path));
+                    }
+                }
+                Response::Ok
+            }
+            Request::Status => Response::Player(self.status()),
+            Request::Play => self.command(PlayerCommand::Play),
+            Request::Pause => self.command(PlayerCommand::Pause),
+            Request::PlayPause => self.command(PlayerCommand::PlayPause),
+            Request::Next => self.command(PlayerCommand::Next),
+            Request::Previous => self.command(PlayerCommand::Previous),
+            Request::Seek { position_ms } => {
+                self.command(PlayerCommand::Seek(Duration::from_millis(position_ms)))
+            }
+            Request::SeekBy { offset_ms } => {
+                let offset = Duration::from_millis(offset_ms.unsigned_abs());
+                if offset_ms < 0 {
+                    self.command(PlayerCommand::SeekBackward(offset))
+                } else {
+                    self.command(PlayerCommand::SeekForward(offset))
+                }
+            }
+            Request::Volume { volume } if volume <= 100 => {
+                self.command(PlayerCommand::SetVolume(volume))
+            }
+            Request::Volume { volume } => {
+                Response::error(format!("Volume goes from 0 to 100, not {volume}"))
+            }
+            Request::Enqueue { query } => self.enqueue(query),
+            Request::Playlist { name } => self.switch_playlist(&name),
+            Request::Raise => {
+                self.app.send(AppCommand::Raise);
+                Response::Ok
+            }
+            Request::Watch => return Reply::Stream(self.watch()),
+        };
+
+        Reply::Once(response)
+    }
+
+    fn command(&self, command: PlayerCommand) -> Response {
+        self.player.send(command);
+        Response::Ok
+    }
+
+    fn status(&self) -> StatusReport {
+        let playlist = self.playlists.lock().ok().and_then(|snapshot| {
+            snapshot
+                .playlists
+                .get(snapshot.active)
+                .map(|playlist| playlist.name.clone())
+        });
+        let state = self.state.lock().expect("Player state poisoned!");
+
+        StatusReport::new(&state, playlist)
+    }
+
+    fn enqueue(&self, query: String) -> Response {
+        let songs = Arc::clone(&self.state.lock().expect("Player state poisoned!").songs);
+        let mut search = Search {
+            query: query.clone(),
+            ..Search::default()
+        };
+
+        let best = search.search_query(&songs).first().map(|&(id, _)| id);
+        match best.and_then(|id| songs.get(id)) {
+            Some(song) => {
+                self.player.send(PlayerCommand::Enqueue(song.id));
+                Response::Enqueued {
+                    track: TrackReport::from(song),
+                }
+            }
+            None => Response::error(format!("Nothing matches {query}")),
+        }
+    }
+
+    fn switch_playlist(&self, name: &str) -> Response {
+        let index = self.playlists.lock().ok().and_then(|snapshot| {
+            snapshot
+                .playlists
+                .iter()
+                .position(|playlist| playlist.name.eq_ignore_ascii_case(name))
+        });
+
+        match index {
+            Some(index) => {
+                self.app.send(AppCommand::SwitchPlaylist(index));
+                Response::Ok
+            }
+            None => Response::error(format!("No playlist called {name}")),
+        }
+    }
+
+    /// Player events as they happen, until the watching client hangs up.
+    fn watch(&self) -> Receiver<Response> {
+        let (tx, rx) = channel();
+        let events = self.player.subscribe();
+        let state = Arc::clone(&self.state);
+
+        std::thread::spawn(move || {
+            for event in events {
+                let report = match state.lock() {
+                    Ok(state) => EventReport::new(&event, &state),
+                    Err(_) => break,
+                };
+                if tx.send(Response::Event(report)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+}
@@ -0,0 +1,181 @@
+//! Remote control for a running Sanctum, over the control socket described in `sanctum::ipc`.
+
+use sanctum::ipc::{Client, Request, Response, StatusReport, socket_path};
+use sanctum::utils::format_timestamp;
+
+const USAGE: &str = "\
+Usage: sanctumctl [--json] COMMAND
+
+Commands:
+  status               Show the current track, position and mode
+  play | pause | toggle
+  next | prev
+  seek POSITION        Jump to seconds or mm:ss, +N/-N to move relative
+  volume LEVEL         Set the volume, 0 to 100
+  enqueue QUERY...     Queue the best search match
+  playlist NAME...     Switch playlists
+  raise                Bring the window to the front
+  watch                Print player events as JSON lines until interrupted
+
+--json prints the player's responses as they come instead of summarising them.";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json = match args.iter().position(|arg| arg == "--json") {
+        Some(flag) => {
+            args.remove(flag);
+            true
+        }
+        None => false,
+    };
+
+    let request = match parse_command(&args) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let mut client = match socket_path().and_then(|path| Client::connect(&path)) {
+        Ok(Some(client)) => client,
+        Ok(None) => {
+            eprintln!("Sanctum isn't running");
+            std::process::exit(3);
+        }
+        Err(e) => {
+            eprintln!("Can't reach Sanctum: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = client.send(&request) {
+        eprintln!("Can't reach Sanctum: {e}");
+        std::process::exit(1);
+    }
+
+    // Watch never finishes on its own, every other command gets a single reply
+    let watching = request == Request::Watch;
+    if watching && let Err(e) = client.wait_indefinitely() {
+        eprintln!("Can't reach Sanctum: {e}");
+        std::process::exit(1);
+    }
+    loop {
+        let response = match client.receive() {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Lost Sanctum: {e}");
+                std::process::exit(1);
+            }
+        };
+
+        if json || watching {
+            println!(
+                "{}",
+                serde_json::to_string(&response).expect("Can't export response!")
+            );
+        } else {
+            print_response(&response);
+        }
+
+        if !watching {
+            let failed = matches!(response, Response::Error { .. });
+            std::process::exit(if failed { 1 } else { 0 });
+        }
+    }
+}
+
+fn parse_command(args: &[String]) -> Result<Request, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err("Missing command".to_string());
+    };
+    let rest = rest.join(" ");
+
+    let request = match command.as_str() {
+        "status" => Request::Status,
+        "play" => Request::Play,
+        "pause" => Request::Pause,
+        "toggle" => Request::PlayPause,
+        "next" => Request::Next,
+        "prev" | "previous" => Request::Previous,
+        "raise" => Request::Raise,
+        "watch" => Request::Watch,
+        "seek" => parse_seek(&rest)?,
+        "volume" => Request::Volume {
+            volume: rest
+                .parse()
+                .map_err(|_| format!("Volume should be 0 to 100, not {rest:?}"))?,
+        },
+        "enqueue" if !rest.is_empty() => Request::Enqueue { query: rest },
+        "playlist" if !rest.is_empty() => Request::Playlist { name: rest },
+        "enqueue" | "playlist" => return Err(format!("{command} needs a name")),
+        _ => return Err(format!("Unknown command {command}")),
+    };
+
+    Ok(request)
+}
+
+/// `90`, `1:30` and `12.5` are absolute, a leading `+` or `-` makes them relative.
+fn parse_seek(position: &str) -> Result<Request, String> {
+    let (sign, time) = match position.split_at_checked(1) {
+        Some(("+", time)) => (Some(1), time),
+        Some(("-", time)) => (Some(-1), time),
+        _ => (None, position),
+    };
+
+    let seconds = match time.split_once(':') {
+        Some((minutes, seconds)) => minutes
+            .parse::<u64>()
+            .ok()
+            .zip(seconds.parse::<f64>().ok())
+            .map(|(minutes, seconds)| minutes as f64 * 60. + seconds),
+        None => time.parse::<f64>().ok(),
+    };
+    let millis = match seconds {
+        Some(seconds) if seconds.is_finite() && seconds >= 0. => (seconds * 1000.) as i64,
+        _ => return Err(format!("Can't seek to {position:?}")),
+    };
+
+    Ok(match sign {
+        Some(sign) => Request::SeekBy {
+            offset_ms: sign * millis,
+        },
+        None => Request::Seek {
+            position_ms: millis as u64,
+        },
+    })
+}
+
+fn print_response(response: &Response) {
+    match response {
+        Response::Ok => {}
+        Response::Error { message } => eprintln!("{message}"),
+        Response::Player(status) => print_status(status),
+        Response::Enqueued { track } => println!("Queued {} - {}", track.artist, track.title),
+        Response::Event(_) => {}
+    }
+}
+
+fn print_status(status: &StatusReport) {
+    match &status.track {
+        Some(track) => {
+            println!("{} - {}", track.artist, track.title);
+            println!("{}", track.album);
+            println!(
+                "[{}] {} / {}",
+                status.state,
+                format_timestamp(status.position_ms / 1000),
+                format_timestamp(track.duration_ms / 1000)
+            );
+        }
+        None => println!("[{}] Nothing loaded", status.state),
+    }
+
+    println!(
+        "mode: {:?}  volume: {}%  queued: {}  playlist: {}",
+        status.mode,
+        status.volume,
+        status.queued,
+        status.playlist.as_deref().unwrap_or("-")
+    );
+}
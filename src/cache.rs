@@ -1,13 +1,14 @@
 use base64::{Engine as _, engine::general_purpose::URL_SAFE};
-use image::ImageReader;
+use image::{DynamicImage, ImageReader};
 use lofty::file::TaggedFileExt;
 use lofty::picture::Picture;
 use lofty::probe::Probe;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::Cursor;
+use std::sync::{Arc, Mutex};
 
-use crate::songs::Song;
 use rayon::ThreadPool;
 use rayon::ThreadPoolBuilder;
 use std::sync::mpsc::{Receiver, Sender, channel};
@@ -16,32 +17,56 @@ pub struct SancCache {
     pub path: String,
     pub covers: HashMap<String, String>,
     pub loading_covers: HashSet<String>,
+    // Accent colors as RGB keyed by album, `None` when the cover has no color worth using
+    pub accents: HashMap<String, Option<[u8; 3]>>,
+    pub loading_accents: HashSet<String>,
+    // Waveform peaks keyed by song path
+    pub peaks: HashMap<String, Arc<Vec<f32>>>,
+    // The song whose peaks were asked for last, work for any other is skipped
+    pub wanted_peaks: Arc<Mutex<Option<String>>>,
 
     // Rayon custom bounded pool
     pub pool: ThreadPool,
+    // Waveforms decode whole songs, so they get their own thread and leave `pool` to covers
+    pub peaks_pool: ThreadPool,
 
     // Communication channel back to egui main thread
-    pub tx: Sender<(String, String)>,
-    pub rx: Receiver<(String, String)>,
+    pub tx: Sender<(String, String, Option<[u8; 3]>)>,
+    pub rx: Receiver<(String, String, Option<[u8; 3]>)>,
+    pub peaks_tx: Sender<(String, Vec<f32>)>,
+    pub peaks_rx: Receiver<(String, Vec<f32>)>,
 }
 
 impl SancCache {
     pub fn new(cache_path: String) -> Self {
         let (tx, rx) = channel();
+        let (peaks_tx, peaks_rx) = channel();
 
         // Strict limit: Maximum 2 threads decoding images at once
         let pool = ThreadPoolBuilder::new()
             .num_threads(2)
             .build()
             .expect("Failed to create Rayon pool");
+        let peaks_pool = ThreadPoolBuilder::new()
+            .num_threads(1)
+            .thread_name(|_| "sanctum-peaks".to_string())
+            .build()
+            .expect("Failed to create Rayon pool");
 
         Self {
             path: cache_path,
             covers: std::collections::HashMap::new(),
             loading_covers: std::collections::HashSet::new(),
+            accents: HashMap::new(),
+            loading_accents: HashSet::new(),
+            peaks: HashMap::new(),
+            wanted_peaks: Arc::new(Mutex::new(None)),
             pool,
+            peaks_pool,
             tx,
             rx,
+            peaks_tx,
+            peaks_rx,
         }
     }
 }
@@ -73,6 +98,100 @@ fn get_cover(song_path: &str) -> Option<Picture> {
     }
 }
 
+pub fn cover_path(cache_dir: &str, album: &str) -> String {
+    format!("{}/{}.jpg", cache_dir, hash_album(album))
+}
+
+/// Where a cover's accent color is kept, next to the cover itself.
+pub fn accent_path(cache_dir: &str, album: &str) -> String {
+    format!("{}/{}.accent", cache_dir, hash_album(album))
+}
+
+/// The most common vivid color in a cover as RGB. Greys, near blacks and colors only a
+/// few pixels have don't count, those covers get `None`.
+pub fn dominant_color(image: &DynamicImage) -> Option<[u8; 3]> {
+    let small = image.thumbnail(32, 32).to_rgb8();
+    let total = small.pixels().len() as u32;
+
+    // Similar colors share a bucket, which keeps their count and their sum
+    let mut buckets: BTreeMap<[u8; 3], (u32, [u32; 3])> = BTreeMap::new();
+    for pixel in small.pixels() {
+        let [r, g, b] = pixel.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        if max < 48 || max - min < 48 {
+            continue;
+        }
+
+        let (count, sum) = buckets.entry([r >> 5, g >> 5, b >> 5]).or_default();
+        *count += 1;
+        for (sum, channel) in sum.iter_mut().zip([r, g, b]) {
+            *sum += channel as u32;
+        }
+    }
+
+    let (count, [r, g, b]) = buckets.into_values().max_by_key(|(count, _)| *count)?;
+    if count * 20 < total {
+        return None;
+    }
+    Some([(r / count) as u8, (g / count) as u8, (b / count) as u8])
+}
+
+// An empty file records that the cover has no accent, so it isn't worked out again
+fn write_accent(cache_dir: &str, album: &str, accent: Option<[u8; 3]>) {
+    let text = accent
+        .map(|[r, g, b]| format!("#{r:02x}{g:02x}{b:02x}"))
+        .unwrap_or_default();
+    let _ = std::fs::write(accent_path(cache_dir, album), text);
+}
+
+fn read_accent(text: &str) -> Option<[u8; 3]> {
+    let hex = text.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Works out the accent of a cover cached before accents were, saving it beside it.
+///
+/// Blocking, so call it from a worker thread.
+pub fn save_accent(cache_dir: &str, album: &str) -> Option<[u8; 3]> {
+    let image = image::open(cover_path(cache_dir, album)).ok()?;
+    let accent = dominant_color(&image);
+    write_accent(cache_dir, album, accent);
+    accent
+}
+
+/// Extracts and resizes a song's embedded cover into the cache along with its accent,
+/// returning the image path and the accent.
+///
+/// Blocking, so call it from a worker thread.
+pub fn save_cover(
+    song_path: &str,
+    album: &str,
+    cache_dir: &str,
+) -> Option<(String, Option<[u8; 3]>)> {
+    // Step A: Extract Picture struct (if present)
+    let picture = get_cover(song_path)?;
+
+    // Step B: Decode using Cursor over picture.data()
+    let reader = ImageReader::new(Cursor::new(picture.data()))
+        .with_guessed_format()
+        .ok()?;
+    let image_data = reader.decode().ok()?;
+
+    // Keep 256x256 for MPRIS!
+    let cover_data = image_data.resize_exact(256, 256, image::imageops::Nearest);
+    let image_path = cover_path(cache_dir, album);
+
+    cover_data.save(&image_path).ok()?;
+    let accent = dominant_color(&cover_data);
+    write_accent(cache_dir, album, accent);
+    Some((image_path, accent))
+}
+
 pub fn load_cache(cache: &mut SancCache) {
     let cache_path = std::path::Path::new(&cache.path);
 
@@ -81,54 +200,56 @@ pub fn load_cache(cache: &mut SancCache) {
     } else if let Ok(entries) = std::fs::read_dir(cache_path) {
         for entry in entries.flatten() {
             let path = entry.path();
+
+            // Only covers and their accents sit at the top level, waveforms have their own folder
+            let Some(extension) = path.extension().filter(|e| *e == "jpg" || *e == "accent") else {
+                continue;
+            };
+
             if let Some(file_name) = path.file_stem() {
                 let album_name = dehash_album(file_name.to_string_lossy().to_string());
-                cache.covers.insert(album_name, path.display().to_string());
+                if extension == "jpg" {
+                    cache.covers.insert(album_name, path.display().to_string());
+                } else if let Ok(text) = std::fs::read_to_string(&path) {
+                    cache.accents.insert(album_name, read_accent(&text));
+                }
             }
         }
     }
 }
 
-pub fn load_cover_art(ui: &mut egui::Ui, cache: &mut SancCache, song: &Song) {
-    let album = &song.album;
-
-    let response = if let Some(cover_art) = cache.covers.get(album) {
-        ui.add_sized([48., 48.], egui::Image::new(format!("file://{cover_art}")))
-    } else {
-        ui.allocate_response(egui::vec2(48., 48.), egui::Sense::hover())
-    };
-
-    let is_visible = response.rect.intersects(ui.clip_rect());
-
-    if is_visible && !cache.covers.contains_key(album) && !cache.loading_covers.contains(album) {
-        cache.loading_covers.insert(album.clone());
-
-        let album_key = album.clone();
-        let song_path = song.path.clone();
-        let cache_dir = cache.path.clone();
-        let tx = cache.tx.clone();
-
-        // Offload to our bounded 2-thread Rayon pool
-        cache.pool.spawn(move || {
-            // Step A: Extract Picture struct (if present)
-            if let Some(picture) = get_cover(&song_path) {
-                // Step B: Decode using Cursor over picture.data()
-                if let Ok(reader) =
-                    ImageReader::new(Cursor::new(picture.data())).with_guessed_format()
-                {
-                    if let Ok(image_data) = reader.decode() {
-                        // Keep 256x256 for MPRIS!
-                        let cover_data =
-                            image_data.resize_exact(256, 256, image::imageops::Nearest);
-                        let image_path = format!("{}/{}.jpg", cache_dir, hash_album(&album_key));
-
-                        if cover_data.save(&image_path).is_ok() {
-                            // Notify main egui thread
-                            let _ = tx.send((album_key, image_path));
-                        }
-                    }
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn the_accent_is_the_most_common_vivid_color() {
+        // Mostly black with a big orange block and a sliver of blue
+        let image = RgbImage::from_fn(64, 64, |x, y| match (x, y) {
+            (0..32, 0..32) => Rgb([230, 120, 20]),
+            (60.., _) => Rgb([20, 40, 220]),
+            _ => Rgb([5, 5, 5]),
         });
+        let accent = dominant_color(&DynamicImage::ImageRgb8(image)).unwrap();
+        assert_eq!(accent, [230, 120, 20]);
+
+        let grey = RgbImage::from_pixel(64, 64, Rgb([128, 128, 128]));
+        assert_eq!(dominant_color(&DynamicImage::ImageRgb8(grey)), None);
+    }
+
+    #[test]
+    fn accents_are_read_back_including_covers_without_one() {
+        let dir = std::env::temp_dir().join("sanctum-cache-accents");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.display().to_string();
+
+        write_accent(&dir, "Blue Album", Some([1, 92, 128]));
+        let text = std::fs::read_to_string(accent_path(&dir, "Blue Album")).unwrap();
+        assert_eq!(text, "#015c80");
+        assert_eq!(read_accent(&text), Some([1, 92, 128]));
+
+        assert_eq!(read_accent(""), None);
+        assert_eq!(read_accent("#01é80"), None);
     }
 }
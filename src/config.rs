@@ -1,21 +1,204 @@
-use crate::Playlist;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+use crate::http::{HttpSettings, generate_token};
+use crate::keymap::Keymap;
+use crate::mpd::MpdSettings;
+use crate::playlist::Playlist;
+use crate::session::Session;
+use crate::theme::Theme;
+use crate::utils::write_atomic;
+use crate::visualizer::VisualizerSettings;
+
+/// Bumped whenever the layout changes, with a step in [`migrate`] bringing older files up.
+/// Files from before versioning count as version 1.
+pub const CONFIG_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    current_playlist: usize,
+    version: u32,
     playlists: Vec<Playlist>,
-    last_track_index: usize,
     volume: u32,
     pub cache_path: String,
+    visualizer: VisualizerSettings,
+    play_counts: HashMap<String, u32>,
+    http: HttpSettings,
+    mpd: MpdSettings,
+    keys: Keymap,
+    /// Name of the theme in use, one of the built-ins or a file in [`themes_dir`].
+    theme: String,
+    /// Take the accent from the playing track's cover instead of the theme.
+    cover_accent: bool,
+    /// What version 2 and older kept of the session, for the session file to start from.
+    #[serde(skip_serializing)]
+    session: Option<Session>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
 }
 
+/// Why a config file couldn't be used, pointing at the spot when there is one.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// Dotted path to the offending field, like `playlists[1].sort_order`.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(path: &Path, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line: None,
+            column: None,
+            field: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, ":{line}:{column}")?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, " in {field}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
-    pub fn get_playlists(&self) -> &Vec<Playlist> {
-        &self.playlists
+    /// A fresh config for first runs.
+    pub fn new(playlists: Vec<Playlist>) -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            playlists,
+            volume: 50,
+            cache_path: cache_dir().display().to_string(),
+            visualizer: VisualizerSettings::default(),
+            play_counts: HashMap::new(),
+            http: HttpSettings::default(),
+            mpd: MpdSettings::default(),
+            keys: Keymap::default(),
+            theme: Theme::dark().name,
+            cover_accent: false,
+            session: None,
+        }
+    }
+
+    /// Reads the config at `path`, bringing older layouts up to date and fixing values
+    /// that are out of range. `None` when there isn't one yet.
+    pub fn load(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigError::new(path, format!("Can't read it: {e}"))),
+        };
+
+        let mut config = Self::parse(path, &text)?;
+        for fix in config.repair() {
+            eprintln!("{}: {fix}", path.display());
+        }
+        Ok(Some(config))
+    }
+
+    fn parse(path: &Path, text: &str) -> Result<Self, ConfigError> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| ConfigError {
+            line: Some(e.line()),
+            column: Some(e.column()),
+            ..ConfigError::new(path, e.to_string())
+        })?;
+
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| ConfigError {
+                    field: Some("version".to_string()),
+                    ..ConfigError::new(path, "should be a whole number")
+                })?,
+        };
+        if version > CONFIG_VERSION {
+            return Err(ConfigError::new(
+                path,
+                format!(
+                    "written by a newer Sanctum (config version {version}, this one reads up to {CONFIG_VERSION})"
+                ),
+            ));
+        }
+
+        // Up to date files are read from the text itself, so errors can say which line
+        if version == CONFIG_VERSION {
+            let mut deserializer = serde_json::Deserializer::from_str(text);
+            return serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+                let field = e.path().to_string();
+                let inner = e.into_inner();
+                ConfigError {
+                    line: Some(inner.line()),
+                    column: Some(inner.column()),
+                    field: (field != ".").then_some(field),
+                    ..ConfigError::new(path, strip_position(&inner))
+                }
+            });
+        }
+
+        let value = migrate(value, version);
+        serde_path_to_error::deserialize(value).map_err(|e| {
+            let field = e.path().to_string();
+            ConfigError {
+                field: (field != ".").then_some(field),
+                ..ConfigError::new(path, e.into_inner().to_string())
+            }
+        })
     }
 
-    pub fn get_last_track(&self) -> usize {
-        self.last_track_index
+    /// Pulls values that can't be right back into range, describing each fix.
+    pub fn repair(&mut self) -> Vec<String> {
+        let mut fixes = Vec::new();
+
+        if self.volume > 100 {
+            fixes.push(format!("volume {} is above 100, using 100", self.volume));
+            self.volume = 100;
+        }
+        if self.cache_path.trim().is_empty() {
+            fixes.push("cache_path is empty, using the default".to_string());
+            self.cache_path = cache_dir().display().to_string();
+        }
+        if self.theme.trim().is_empty() {
+            fixes.push("theme is empty, using the default".to_string());
+            self.theme = Theme::dark().name;
+        }
+        fixes.extend(self.keys.resolve_conflicts());
+
+        fixes
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).expect("Can't export config!");
+        write_atomic(path, text.as_bytes())
+    }
+
+    /// The session an older config carried, only handed out once.
+    pub fn take_session(&mut self) -> Option<Session> {
+        self.session.take()
+    }
+
+    pub fn get_playlists(&self) -> &Vec<Playlist> {
+        &self.playlists
     }
 
     pub fn get_volume(&self) -> u32 {
@@ -26,23 +209,174 @@ impl Config {
         self.volume = new_volume;
     }
 
-    // pub fn add_playlist(&mut self, new_playlist: Playlist) {
-    //     self.playlists.push(new_playlist);
-    // }
+    pub fn get_visualizer(&self) -> &VisualizerSettings {
+        &self.visualizer
+    }
+
+    pub fn set_visualizer(&mut self, settings: VisualizerSettings) {
+        self.visualizer = settings;
+    }
+
+    pub fn get_http(&self) -> &HttpSettings {
+        &self.http
+    }
+
+    /// Gives a turned on HTTP API a token if it doesn't have one, returning whether it did.
+    pub fn ensure_http_token(&mut self) -> bool {
+        if !self.http.enabled || self.http.problem().is_none() {
+            return false;
+        }
+        self.http.token = Some(generate_token());
+        true
+    }
+
+    pub fn get_mpd(&self) -> &MpdSettings {
+        &self.mpd
+    }
+
+    pub fn get_keys(&self) -> &Keymap {
+        &self.keys
+    }
+
+    pub fn set_keys(&mut self, keys: Keymap) {
+        self.keys = keys;
+    }
+
+    pub fn get_theme(&self) -> &str {
+        &self.theme
+    }
+
+    pub fn set_theme(&mut self, name: String) {
+        self.theme = name;
+    }
+
+    pub fn get_cover_accent(&self) -> bool {
+        self.cover_accent
+    }
+
+    pub fn set_cover_accent(&mut self, enabled: bool) {
+        self.cover_accent = enabled;
+    }
+
+    pub fn get_play_counts(&self) -> &HashMap<String, u32> {
+        &self.play_counts
+    }
+
+    pub fn set_play_counts(&mut self, counts: HashMap<String, u32>) {
+        self.play_counts = counts;
+    }
+
+    pub fn add_playlist(&mut self, new_playlist: Playlist) {
+        self.playlists.push(new_playlist);
+    }
+
+    pub fn update_playlist(&mut self, index: usize, playlist: Playlist) {
+        if let Some(slot) = self.playlists.get_mut(index) {
+            *slot = playlist;
+        }
+    }
+}
 
-    pub fn current_playlist(&self) -> usize {
-        self.current_playlist
+/// Runs every migration step from `version` up to [`CONFIG_VERSION`].
+fn migrate(mut value: serde_json::Value, version: u32) -> serde_json::Value {
+    if version < 2 {
+        // Version 1 lived in the working directory, so a relative cache folder meant one
+        // there. The config has since moved, keep pointing at the same folder.
+        if let Some(cache_path) = value.get_mut("cache_path")
+            && let Some(relative) = cache_path
+                .as_str()
+                .filter(|path| Path::new(path).is_relative())
+            && let Ok(cwd) = std::env::current_dir()
+        {
+            *cache_path = cwd.join(relative).display().to_string().into();
+        }
     }
 
-    pub fn set_playlist(&mut self, new_playlist: usize) {
-        self.current_playlist = new_playlist;
+    if version < 3
+        && let Some(object) = value.as_object_mut()
+    {
+        // The session moved into its own file, keep it around until that's written
+        let mut session = serde_json::Map::new();
+        for (old, new) in [
+            ("current_playlist", "playlist"),
+            ("last_track_index", "track_index"),
+            ("mode", "mode"),
+        ] {
+            if let Some(field) = object.remove(old) {
+                session.insert(new.to_string(), field);
+            }
+        }
+        object.insert("session".to_string(), session.into());
     }
 
-    pub fn set_track(&mut self, last_index: usize) {
-        self.last_track_index = last_index
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), CONFIG_VERSION.into());
     }
+    value
+}
+
+/// Moves a config that can't be read out of the way, returning where it went.
+pub fn back_up_broken(path: &Path) -> io::Result<PathBuf> {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".broken-{stamp}"));
 
-    pub fn update_playlist(&mut self, playlist: Playlist) {
-        self.playlists[self.current_playlist] = playlist;
+    let backup = PathBuf::from(backup);
+    std::fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+// serde_json appends " at line X column Y", which ConfigError already shows
+fn strip_position(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    match message.rfind(" at line ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
     }
 }
+
+/// `$XDG_CONFIG_HOME/sanctum`, or `~/.config/sanctum`.
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("sanctum")
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.json")
+}
+
+/// Where user themes live, one JSON file each.
+pub fn themes_dir() -> PathBuf {
+    config_dir().join("themes")
+}
+
+/// `$XDG_CACHE_HOME/sanctum`, or `~/.cache/sanctum`.
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", ".cache").join("sanctum")
+}
+
+/// `$XDG_STATE_HOME/sanctum`, or `~/.local/state/sanctum`.
+pub fn state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state").join("sanctum")
+}
+
+/// Where music most likely lives, suggested on first run.
+pub fn music_dir() -> PathBuf {
+    std::env::var_os("XDG_MUSIC_DIR")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join("Music"))
+}
+
+// The spec says relative values are invalid and should be ignored
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    std::env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join(fallback))
+}
+
+fn home_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::Duration;
+
+use mpris_server::PlaybackStatus;
+
+use crate::library::{EntryId, Library, SongId};
+use crate::player::PlaybackMode;
+
+/// Requests sent into the player by the UI, keyboard, MPRIS or any other remote.
+#[derive(Debug, Clone)]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    PlaySong(SongId),
+    /// Swaps in another library, keeping the current and queued songs it still has.
+    SetSongs(Arc<Library>),
+    /// Swaps in a library that extends the current one, without interrupting playback.
+    AppendSongs(Arc<Library>),
+    Enqueue(SongId),
+    /// Inserts a song at a position in the queue, clamped to its end.
+    EnqueueAt(usize, SongId),
+    /// Like `EnqueueAt`, with the entry's id picked by the sender so it can hand it out.
+    EnqueueEntry(usize, SongId, EntryId),
+    /// Drops the queue entry at a position.
+    Dequeue(usize),
+    /// Plays the queue entry at a position, dropping everything queued before it.
+    SkipToQueued(usize),
+    Seek(Duration),
+    /// Relative seek, running past the end of the track skips to the next one.
+    SeekForward(Duration),
+    SeekBackward(Duration),
+    SetVolume(u32),
+    /// Moves the volume by this many percent, staying between 0 and 100.
+    AdjustVolume(i8),
+    SetShuffle(bool),
+    /// Turns shuffle on with a saved order, if it still matches the song list.
+    RestoreShuffle(Vec<SongId>),
+    ToggleShuffle,
+    SetRepeat(bool),
+    ToggleRepeat,
+}
+
+/// Changes broadcast by the player after a command or a track finishing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    StatusChanged(PlaybackStatus),
+    /// Empty only when the library is.
+    TrackChanged(Option<SongId>),
+    Seeked(Duration),
+    ModeChanged(PlaybackMode),
+    VolumeChanged(u32),
+    QueueChanged,
+}
+
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<PlayerEvent>>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<PlayerEvent> {
+        let (tx, rx) = channel();
+
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+
+        rx
+    }
+
+    pub fn emit(&self, event: PlayerEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            // Dropped receivers unsubscribe themselves
+            subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// Cheap, cloneable entry point for controlling the player from anywhere.
+#[derive(Clone)]
+pub struct PlayerHandle {
+    commands: Sender<PlayerCommand>,
+    events: EventBus,
+}
+
+impl PlayerHandle {
+    pub fn new(commands: Sender<PlayerCommand>, events: EventBus) -> Self {
+        Self { commands, events }
+    }
+
+    pub fn send(&self, command: PlayerCommand) {
+        if let Err(e) = self.commands.send(command) {
+            eprintln!("Player is gone, dropping command: {:?}", e.0);
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<PlayerEvent> {
+        self.events.subscribe()
+    }
+}
@@ -0,0 +1,643 @@
+//! Small HTTP/1.1 server with WebSocket upgrades, enough for a local JSON API.
+//!
+//! One request per connection, no chunked bodies, no TLS. Parsing is left to `httparse`
+//! and WebSocket framing to `tungstenite`, this only glues them to a thread per client.
+//! Anything reachable from outside the machine should sit behind the token in
+//! [`HttpSettings`].
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use tungstenite::protocol::{Role, WebSocketConfig};
+use tungstenite::{Message, WebSocket};
+
+use crate::utils::constant_time_eq;
+
+// Requests past these are refused rather than buffered
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+const MAX_BODY: usize = 1024 * 1024;
+
+// How long a WebSocket waits on its client before checking for messages to send
+const WEBSOCKET_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+    /// Required as `Authorization: Bearer <token>` or `?token=` on every request. One is
+    /// generated the first time the API is turned on without it.
+    pub token: Option<String>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 7837,
+            token: None,
+        }
+    }
+}
+
+impl HttpSettings {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// Why these settings shouldn't be served, if there's a reason.
+    pub fn problem(&self) -> Option<String> {
+        if self.token().is_none() {
+            return Some("it needs a token, so other programs and web pages can't use it".into());
+        }
+        None
+    }
+
+    fn token(&self) -> Option<&str> {
+        self.token
+            .as_deref()
+            .filter(|token| !token.trim().is_empty())
+    }
+
+    /// Why `request` shouldn't be answered, as the response that refuses it.
+    ///
+    /// Web pages can send requests to localhost too, so besides the token this only takes
+    /// requests for our own host name, from our own origin if they come from a browser at
+    /// all, and with JSON bodies, which a page can't send elsewhere without asking first.
+    pub fn refusal(&self, request: &HttpRequest) -> Option<HttpResponse> {
+        let host = request.header("host").unwrap_or("");
+        if !self.serves_host(host) {
+            return Some(HttpResponse::error(
+                403,
+                format!("Not serving host \"{host}\""),
+            ));
+        }
+
+        if let Some(origin) = request.header("origin")
+            && !origin.eq_ignore_ascii_case(&format!("http://{host}"))
+        {
+            return Some(HttpResponse::error(
+                403,
+                format!("Requests from {origin} aren't allowed"),
+            ));
+        }
+
+        if !request.authorized(self.token().unwrap_or("")) {
+            return Some(HttpResponse::error(401, "Missing or wrong token"));
+        }
+
+        let media_type = request
+            .header("content-type")
+            .and_then(|value| value.split(';').next())
+            .map(str::trim);
+        if request.method == "POST"
+            && !media_type.is_some_and(|media| media.eq_ignore_ascii_case("application/json"))
+        {
+            return Some(HttpResponse::error(
+                415,
+                "Send Content-Type: application/json",
+            ));
+        }
+
+        None
+    }
+
+    // Names like attacker.example pointed at 127.0.0.1 are how DNS rebinding gets in
+    fn serves_host(&self, host: &str) -> bool {
+        // Browsers add the port whenever it isn't 80
+        let name = match host.strip_prefix('[') {
+            Some(rest) => rest.split_once(']').map(|(ip, _)| format!("[{ip}]")),
+            None => host.split(':').next().map(str::to_string),
+        };
+        let Some(name) = name.filter(|name| !name.is_empty()) else {
+            return false;
+        };
+
+        let configured = match self.address {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{ip}]"),
+        };
+        // Listening everywhere, clients may know the machine by any name
+        self.address.is_unspecified()
+            || ["localhost", "127.0.0.1", "[::1]", &configured]
+                .iter()
+                .any(|allowed| name.eq_ignore_ascii_case(allowed))
+    }
+}
+
+/// A fresh random token for [`HttpSettings::token`].
+pub fn generate_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 24]>())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Why a request couldn't be read.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The client sent something we won't take, to be answered with this status.
+    Refused(u16, &'static str),
+    Io(io::Error),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl HttpRequest {
+    /// Reads one request, `None` if the client closed the connection before sending any.
+    pub fn read(reader: &mut impl BufRead) -> Result<Option<Self>, ReadError> {
+        let Some(head) = read_head(reader)? else {
+            return Ok(None);
+        };
+
+        let mut slots = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut slots);
+        match parsed.parse(&head) {
+            Ok(httparse::Status::Complete(_)) => {}
+            Ok(httparse::Status::Partial) => {
+                return Err(ReadError::Refused(400, "Request cut short"));
+            }
+            Err(httparse::Error::TooManyHeaders) => {
+                return Err(ReadError::Refused(431, "Too many headers"));
+            }
+            Err(_) => return Err(ReadError::Refused(400, "Malformed request")),
+        }
+        let (Some(method), Some(target)) = (parsed.method, parsed.path) else {
+            return Err(ReadError::Refused(400, "Malformed request"));
+        };
+
+        let mut headers = HashMap::new();
+        for header in parsed.headers.iter() {
+            let value = std::str::from_utf8(header.value)
+                .map_err(|_| ReadError::Refused(400, "Header isn't UTF-8"))?;
+            headers.insert(header.name.to_ascii_lowercase(), value.trim().to_string());
+        }
+
+        if headers.contains_key("transfer-encoding") {
+            return Err(ReadError::Refused(
+                411,
+                "Send a Content-Length, chunked bodies aren't supported",
+            ));
+        }
+        // No length means no body, whatever follows isn't read as one
+        let length = match headers.get("content-length") {
+            Some(length) => length
+                .parse::<usize>()
+                .map_err(|_| ReadError::Refused(400, "Malformed Content-Length"))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(ReadError::Refused(413, "Body too large"));
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, parse_query(query)),
+            None => (target, HashMap::new()),
+        };
+
+        Ok(Some(Self {
+            method: method.to_string(),
+            path: percent_decode(path),
+            query,
+            headers,
+            body,
+        }))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// The path split on `/`, without empty segments.
+    pub fn segments(&self) -> Vec<&str> {
+        self.path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_slice(&self.body).map_err(|e| format!("Malformed JSON body: {e}"))
+    }
+
+    pub fn is_websocket(&self) -> bool {
+        self.header("upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+            && self.header("sec-websocket-key").is_some()
+    }
+
+    /// Whether the request carries `token`, as a bearer header or a query parameter
+    /// (browsers can't set headers on WebSockets). An empty token lets nobody in.
+    pub fn authorized(&self, token: &str) -> bool {
+        if token.is_empty() {
+            return false;
+        }
+
+        let bearer = self
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let given = bearer.or(self.query.get("token").map(String::as_str));
+
+        given.is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn json(value: &impl serde::Serialize) -> Self {
+        Self::json_with_status(200, value)
+    }
+
+    pub fn json_with_status(status: u16, value: &impl serde::Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).expect("Can't export response!"),
+        }
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json_with_status(status, &serde_json::json!({ "error": message.into() }))
+    }
+
+    pub fn not_found() -> Self {
+        Self::error(404, "No such endpoint")
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        )?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+/// Produces a WebSocket's messages. Sending fails once the client is gone, which is the
+/// feed's cue to return.
+pub type Feed = Box<dyn FnOnce(Sender<String>) + Send>;
+
+/// How a request gets answered: a plain response, or an upgrade to a WebSocket that
+/// runs the feed on a thread of its own and sends what it passes on until either side
+/// is done. The connection waits for the feed to return before letting go.
+pub enum HttpReply {
+    Response(HttpResponse),
+    WebSocket(Feed),
+}
+
+/// Answers every connection on its own thread, passing each request to `handler`.
+pub fn serve(
+    listener: TcpListener,
+    handler: impl Fn(&HttpRequest) -> HttpReply + Send + Sync + 'static,
+) {
+    let handler = Arc::new(handler);
+
+    std::thread::Builder::new()
+        .name("sanctum-http".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("HTTP accept error: {e}");
+                        continue;
+                    }
+                };
+
+                let handler = Arc::clone(&handler);
+                std::thread::spawn(move || {
+                    if let Err(e) = answer(stream, handler.as_ref())
+                        && !matches!(
+                            e.kind(),
+                            io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+                        )
+                    {
+                        eprintln!("HTTP client dropped: {e}");
+                    }
+                });
+            }
+        })
+        .expect("Can't start HTTP thread!");
+}
+
+fn answer(stream: TcpStream, handler: &impl Fn(&HttpRequest) -> HttpReply) -> io::Result<()> {
+    // Slow or idle clients shouldn't hold a thread forever while we wait for their request
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let request = match HttpRequest::read(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(ReadError::Refused(status, message)) => {
+            HttpResponse::error(status, message).write_to(&mut writer)?;
+            // Closing with the rest of the request unread resets the connection, which
+            // can lose the response on its way out
+            writer.shutdown(Shutdown::Write)?;
+            io::copy(&mut reader.take(MAX_BODY as u64), &mut io::sink())?;
+            return Ok(());
+        }
+        Err(ReadError::Io(e)) => return Err(e),
+    };
+
+    match handler(&request) {
+        HttpReply::Response(response) => response.write_to(&mut writer),
+        HttpReply::WebSocket(feed) => {
+            let Some(key) = request.header("sec-websocket-key") else {
+                return HttpResponse::error(400, "Missing Sec-WebSocket-Key").write_to(&mut writer);
+            };
+            write!(
+                writer,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                tungstenite::handshake::derive_accept_key(key.as_bytes())
+            )?;
+            writer.flush()?;
+
+            let config = WebSocketConfig::default()
+                .max_message_size(Some(MAX_BODY))
+                .max_frame_size(Some(MAX_BODY));
+            // Frames the client sent straight after the handshake may already be buffered,
+            // and the socket should close as soon as the pump is done with it
+            let buffered = reader.buffer().to_vec();
+            drop(reader);
+            let socket =
+                WebSocket::from_partially_read(writer, buffered, Role::Server, Some(config));
+
+            let (tx, messages) = channel();
+            let feed = std::thread::spawn(move || feed(tx));
+            let pumped = pump_websocket(socket, messages);
+            // The receiver went with the pump, so the feed's next send tells it to stop
+            let _ = feed.join();
+            pumped
+        }
+    }
+}
+
+/// Sends messages until the receiver runs dry or the client leaves. What the client
+/// sends is only read to answer its pings and notice it closing.
+fn pump_websocket(mut socket: WebSocket<TcpStream>, messages: Receiver<String>) -> io::Result<()> {
+    // Reads give up after a moment, so messages waiting to go out aren't held up
+    socket.get_ref().set_read_timeout(Some(WEBSOCKET_POLL))?;
+
+    loop {
+        loop {
+            match messages.try_recv() {
+                Ok(message) => {
+                    if let Err(e) = socket.send(Message::text(message)) {
+                        return closed(e);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return Ok(());
+                }
+            }
+        }
+
+        // Pongs and the reply to a close go out with the flush
+        if let Err(e) = socket.read().and_then(|_| socket.flush())
+            && !is_timeout(&e)
+        {
+            return closed(e);
+        }
+    }
+}
+
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(e)
+        if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut))
+}
+
+// A client that closed properly isn't an error, anything else is passed on
+fn closed(error: tungstenite::Error) -> io::Result<()> {
+    match error {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Ok(()),
+        tungstenite::Error::Io(e) => Err(e),
+        e => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+/// Reads up to the blank line that ends the headers, `None` if nothing came at all.
+fn read_head(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>, ReadError> {
+    let mut head = Vec::new();
+    loop {
+        let limit = (MAX_HEAD + 1 - head.len()) as u64;
+        let read = reader.take(limit).read_until(b'\n', &mut head)?;
+
+        if read == 0 {
+            return match head.is_empty() {
+                true => Ok(None),
+                false => Err(ReadError::Refused(400, "Request cut short")),
+            };
+        }
+        if head.len() > MAX_HEAD {
+            return Err(ReadError::Refused(431, "Headers too large"));
+        }
+        // Stray line breaks before a request are allowed by RFC 9112
+        if head == b"\r\n" || head == b"\n" {
+            head.clear();
+            continue;
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            return Ok(Some(head));
+        }
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (
+                percent_decode(&key.replace('+', " ")),
+                percent_decode(&value.replace('+', " ")),
+            )
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        431 => "Request Header Fields Too Large",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_parse_with_query_and_body() {
+        let raw = b"POST /api/search%20x?q=aurora+vale&limit=5 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\nAuthorization: Bearer secret\r\n\r\n{}";
+        let request = HttpRequest::read(&mut raw.as_slice()).unwrap().unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/search x");
+        assert_eq!(request.segments(), vec!["api", "search x"]);
+        assert_eq!(request.query["q"], "aurora vale");
+        assert_eq!(request.query["limit"], "5");
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.body, b"{}");
+
+        assert!(request.authorized("secret"));
+        assert!(!request.authorized("other"));
+        assert!(!request.authorized(""));
+    }
+
+    fn refusal(raw: &[u8]) -> u16 {
+        match HttpRequest::read(&mut &raw[..]) {
+            Err(ReadError::Refused(status, _)) => status,
+            other => panic!("{raw:?} wasn't refused: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn malformed_requests_are_refused() {
+        assert_eq!(refusal(b"GARBAGE\r\n\r\n"), 400);
+        assert_eq!(refusal(b"GET / HTTP/1.1\r\nHost"), 400);
+        assert_eq!(
+            refusal(b"GET / HTTP/1.1\r\nContent-Length: ten\r\n\r\n"),
+            400
+        );
+        assert_eq!(
+            refusal(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n"),
+            411
+        );
+
+        assert!(HttpRequest::read(&mut b"".as_slice()).unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_requests_are_refused_before_being_buffered() {
+        let raw = b"GET / HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n";
+        assert_eq!(refusal(raw), 413);
+
+        let long = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_HEAD));
+        assert_eq!(refusal(long.as_bytes()), 431);
+
+        let many: String = (0..=MAX_HEADERS).map(|i| format!("X-{i}: 1\r\n")).collect();
+        let raw = format!("GET / HTTP/1.1\r\n{many}\r\n");
+        assert_eq!(refusal(raw.as_bytes()), 431);
+    }
+
+    #[test]
+    fn requests_without_a_length_have_no_body() {
+        let raw = b"POST /api/next HTTP/1.1\r\nHost: localhost\r\n\r\n{\"ignored\": true}";
+        let mut reader = raw.as_slice();
+        let request = HttpRequest::read(&mut reader).unwrap().unwrap();
+
+        assert!(request.body.is_empty());
+        assert_eq!(reader, br#"{"ignored": true}"#);
+    }
+
+    #[test]
+    fn servers_need_a_token() {
+        let mut settings = HttpSettings::default();
+        assert!(settings.problem().is_some());
+
+        settings.token = Some("  ".to_string());
+        assert!(settings.problem().is_some());
+
+        settings.token = Some(generate_token());
+        assert_eq!(settings.problem(), None);
+        assert_ne!(settings.token, Some(generate_token()));
+    }
+
+    #[test]
+    fn only_our_own_host_names_are_served() {
+        let mut settings = HttpSettings::default();
+        for host in ["localhost:7837", "127.0.0.1", "[::1]:7837", "LOCALHOST"] {
+            assert!(settings.serves_host(host), "{host}");
+        }
+        for host in [
+            "",
+            "attacker.example",
+            "127.0.0.1.attacker.example:7837",
+            "[::1",
+        ] {
+            assert!(!settings.serves_host(host), "{host}");
+        }
+
+        settings.address = "192.168.1.20".parse().unwrap();
+        assert!(settings.serves_host("192.168.1.20:7837"));
+        assert!(!settings.serves_host("music.local:7837"));
+
+        settings.address = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        assert!(settings.serves_host("music.local:7837"));
+    }
+}
@@ -0,0 +1,496 @@
+//! Local control socket, used to keep Sanctum to a single instance and by `sanctumctl`.
+//!
+//! A running player listens on a Unix socket at `$XDG_RUNTIME_DIR/sanctum.sock`, or in
+//! a `sanctum-<uid>` folder of the temp folder that only its user can get into.
+//! Launching it again connects there instead, forwards the command line and exits.
+//!
+//! Every message is a JSON object on its own line. Clients send requests tagged with
+//! `"command"` and get exactly one response per request, in order, tagged with `"status"`:
+//!
+//! ```text
+//! > {"command":"status"}
+//! < {"status":"player","state":"playing","track":{...},"position_ms":81250,...}
+//! > {"command":"seek_by","offset_ms":-10000}
+//! < {"status":"ok"}
+//! > {"command":"playlist","name":"Nope"}
+//! < {"status":"error","message":"No playlist called Nope"}
+//! ```
+//!
+//! `{"command":"watch"}` is the exception: it's answered with a stream of
+//! `{"status":"event",...}` lines, one per player event, until the client disconnects.
+//! See [`Request`] and [`Response`] for every command and field.
+
+use std::fs::{DirBuilder, File};
+use std::io::{self, BufRead, BufReader, Lines, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use mpris_server::PlaybackStatus;
+
+use crate::events::PlayerEvent;
+use crate::player::{PlaybackMode, PlayerState};
+use crate::songs::Song;
+
+pub const USAGE: &str = "\
+Usage: sanctum [OPTIONS] [FILES...]
+
+Files are played, or queued with --enqueue, in the running player if there is one.
+
+Options:
+      --play-pause       Toggle playback
+      --next             Skip to the next track
+      --playlist NAME    Switch to the playlist called NAME
+      --enqueue          Queue the given files instead of playing them";
+
+/// Something a second launch, `sanctumctl` or any other local client asks the player to do.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Plays the first file and queues the rest, or queues all of them.
+    Open {
+        paths: Vec<String>,
+        enqueue: bool,
+    },
+    /// Answered with [`Response::Player`].
+    Status,
+    Play,
+    Pause,
+    PlayPause,
+    Next,
+    Previous,
+    /// Jumps to a position in the current track.
+    Seek {
+        position_ms: u64,
+    },
+    /// Moves relative to the current position, negative goes back.
+    SeekBy {
+        offset_ms: i64,
+    },
+    /// Sets the volume, 0 to 100.
+    Volume {
+        volume: u32,
+    },
+    /// Queues the best search match, answered with [`Response::Enqueued`].
+    Enqueue {
+        query: String,
+    },
+    /// Switches to a playlist by name, ignoring case.
+    Playlist {
+        name: String,
+    },
+    /// Brings the window to the front.
+    Raise,
+    /// Streams [`Response::Event`]s until the client disconnects.
+    Watch,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Player(StatusReport),
+    Enqueued { track: TrackReport },
+    Event(EventReport),
+}
+
+impl Response {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::Error {
+            message: message.into(),
+        }
+    }
+}
+
+/// How a request is answered: once, or with a stream that lasts as long as the sender.
+pub enum Reply {
+    Once(Response),
+    Stream(Receiver<Response>),
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TrackReport {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub path: String,
+    pub duration_ms: u64,
+}
+
+impl From<&Song> for TrackReport {
+    fn from(song: &Song) -> Self {
+        Self {
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            album: song.album.clone(),
+            path: song.path.clone(),
+            duration_ms: song.duration.as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct StatusReport {
+    /// `playing`, `paused` or `stopped`.
+    pub state: String,
+    pub track: Option<TrackReport>,
+    pub position_ms: u64,
+    pub mode: PlaybackMode,
+    pub volume: u32,
+    /// Number of tracks queued after the current one.
+    pub queued: usize,
+    pub playlist: Option<String>,
+}
+
+impl StatusReport {
+    pub fn new(state: &PlayerState, playlist: Option<String>) -> Self {
+        Self {
+            state: status_name(state.status).to_string(),
+            track: state.current_song().map(TrackReport::from),
+            position_ms: state.position().as_millis() as u64,
+            mode: state.mode,
+            volume: state.volume,
+            queued: state.queue.len(),
+            playlist,
+        }
+    }
+}
+
+/// A [`PlayerEvent`] as `watch` reports it, with the track spelled out.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventReport {
+    State {
+        state: String,
+    },
+    Track {
+        track: Option<TrackReport>,
+    },
+    Seeked {
+        position_ms: u64,
+    },
+    Mode {
+        mode: PlaybackMode,
+    },
+    Volume {
+        volume: u32,
+    },
+    Queue {
+        queued: usize,
+    },
+    /// Sent every second by the HTTP event stream only.
+    Position {
+        position_ms: u64,
+    },
+}
+
+impl EventReport {
+    /// `state` is the snapshot taken after the event, for the details it doesn't carry.
+    pub fn new(event: &PlayerEvent, state: &PlayerState) -> Self {
+        match event {
+            PlayerEvent::StatusChanged(status) => Self::State {
+                state: status_name(*status).to_string(),
+            },
+            PlayerEvent::TrackChanged(id) => Self::Track {
+                track: id.and_then(|id| state.songs.get(id)).map(TrackReport::from),
+            },
+            PlayerEvent::Seeked(position) => Self::Seeked {
+                position_ms: position.as_millis() as u64,
+            },
+            PlayerEvent::ModeChanged(mode) => Self::Mode { mode: *mode },
+            PlayerEvent::VolumeChanged(volume) => Self::Volume { volume: *volume },
+            PlayerEvent::QueueChanged => Self::Queue {
+                queued: state.queue.len(),
+            },
+        }
+    }
+}
+
+fn status_name(status: PlaybackStatus) -> &'static str {
+    match status {
+        PlaybackStatus::Playing => "playing",
+        PlaybackStatus::Paused => "paused",
+        PlaybackStatus::Stopped => "stopped",
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/sanctum.sock`. Without a runtime dir it goes in a folder of the temp
+/// folder only this user can get into, made here if it's missing.
+pub fn socket_path() -> io::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("sanctum.sock"));
+    }
+
+    let uid = std::fs::metadata("/proc/self")?.uid();
+    let dir = std::env::temp_dir().join(format!("sanctum-{uid}"));
+    private_dir(&dir, uid)?;
+    Ok(dir.join("sanctum.sock"))
+}
+
+// Anyone could have made it first, to listen in or pose as the player
+fn private_dir(dir: &Path, uid: u32) -> io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} should be a folder only you can open", dir.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Turns the command line into requests. File paths are made absolute here, since
+/// the running instance has its own working directory.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Vec<Request>, String> {
+    let mut requests = Vec::new();
+    let mut paths = Vec::new();
+    let mut enqueue = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--play-pause" => requests.push(Request::PlayPause),
+            "--next" => requests.push(Request::Next),
+            "--enqueue" => enqueue = true,
+            "--playlist" => match args.next() {
+                Some(name) => requests.push(Request::Playlist { name }),
+                None => return Err("--playlist needs a playlist name".to_string()),
+            },
+            option if option.starts_with("--") => {
+                return Err(format!("Unknown option {option}"));
+            }
+            path => match std::fs::canonicalize(path) {
+                Ok(path) => paths.push(path.display().to_string()),
+                Err(e) => return Err(format!("Can't open {path}: {e}")),
+            },
+        }
+    }
+
+    if !paths.is_empty() {
+        requests.insert(0, Request::Open { paths, enqueue });
+    }
+
+    Ok(requests)
+}
+
+/// How long a client waits on the player. Requests are answered right away once it's up,
+/// but a player still onboarding holds the socket without answering.
+pub const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One connection to a running player.
+pub struct Client {
+    stream: UnixStream,
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl Client {
+    /// `None` means nothing is listening at `path`.
+    pub fn connect(path: &Path) -> io::Result<Option<Self>> {
+        let stream = match UnixStream::connect(path) {
+            Ok(stream) => stream,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let lines = BufReader::new(stream.try_clone()?).lines();
+        Ok(Some(Self { stream, lines }))
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        self.stream
+            .write_all(&line)
+            .and_then(|()| self.stream.flush())
+            .map_err(no_answer)
+    }
+
+    /// Waits for the next line from the player, a reply or a `watch` event.
+    pub fn receive(&mut self) -> io::Result<Response> {
+        let line = self
+            .lines
+            .next()
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+            .map_err(no_answer)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Stops `receive` from giving up, for `watch` streams that stay quiet while nothing
+    /// happens. Requests sent after this wait as long as it takes too.
+    pub fn wait_indefinitely(&mut self) -> io::Result<()> {
+        self.stream.set_read_timeout(None)
+    }
+
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        self.send(request)?;
+        self.receive()
+    }
+}
+
+// Timeouts show up as "resource temporarily unavailable" otherwise
+fn no_answer(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for the player")
+        }
+        _ => e,
+    }
+}
+
+/// Sends requests to the instance listening at `path`, returning its responses in order.
+///
+/// `None` means nothing is listening there, so this process should become the player.
+pub fn forward(path: &Path, requests: &[Request]) -> io::Result<Option<Vec<Response>>> {
+    let Some(mut client) = Client::connect(path)? else {
+        return Ok(None);
+    };
+
+    requests
+        .iter()
+        .map(|request| client.request(request))
+        .collect::<io::Result<_>>()
+        .map(Some)
+}
+
+/// Claims the socket at `path`. A leftover file from a crashed instance gets replaced,
+/// one that another instance is listening on fails with `AddrInUse`.
+pub fn listen(path: &Path) -> io::Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Two launches at once would both see no instance and take turns replacing the socket
+    let lock = File::create(path.with_extension("lock"))?;
+    lock.lock()?;
+
+    match UnixStream::connect(path) {
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("Another instance is listening on {}", path.display()),
+            ));
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) => {}
+        Err(e) => return Err(e),
+    }
+
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    UnixListener::bind(path)
+}
+
+/// Answers every client on its own thread, passing each request to `handler`.
+pub fn serve(listener: UnixListener, handler: impl Fn(Request) -> Reply + Send + Sync + 'static) {
+    let handler = Arc::new(handler);
+
+    std::thread::Builder::new()
+        .name("sanctum-ipc".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("Control socket error: {e}");
+                        continue;
+                    }
+                };
+
+                let handler = Arc::clone(&handler);
+                std::thread::spawn(move || {
+                    // Clients hanging up is how watch ends, so that's not worth reporting
+                    if let Err(e) = answer(&stream, handler.as_ref())
+                        && e.kind() != io::ErrorKind::BrokenPipe
+                    {
+                        eprintln!("Control client dropped: {e}");
+                    }
+                });
+            }
+        })
+        .expect("Can't start control socket thread!");
+}
+
+fn answer(stream: &UnixStream, handler: &impl Fn(Request) -> Reply) -> io::Result<()> {
+    let mut writer = stream;
+    let mut write = |response: &Response| -> io::Result<()> {
+        serde_json::to_writer(&mut writer, response)?;
+        writer.write_all(b"\n")
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str(&line) {
+            Ok(request) => handler(request),
+            Err(e) => Reply::Once(Response::error(format!("Malformed request: {e}"))),
+        };
+
+        match reply {
+            Reply::Once(response) => write(&response)?,
+            Reply::Stream(responses) => {
+                for response in responses {
+                    write(&response)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn socket_folders_have_to_be_private() {
+        let uid = std::fs::metadata("/proc/self").unwrap().uid();
+        let dir = std::env::temp_dir().join(format!("sanctum-ipc-private-{uid}"));
+        let _ = std::fs::remove_dir(&dir);
+
+        private_dir(&dir, uid).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // Already there and still ours
+        private_dir(&dir, uid).unwrap();
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&dir, uid).is_err());
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(private_dir(&dir, uid + 1).is_err());
+
+        let file = dir.join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(private_dir(&file, uid).is_err());
+    }
+}
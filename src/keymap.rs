@@ -0,0 +1,359 @@
+//! Keyboard shortcuts: named actions and the keys bound to them.
+//!
+//! The config only has to list the actions it changes, everything else keeps its default.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use egui::Key;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    PlayPause,
+    Next,
+    Previous,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    ToggleShuffle,
+    ToggleRepeat,
+    Search,
+    JumpToPlaying,
+    Visualizer,
+    Shortcuts,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::PlayPause,
+        Action::Next,
+        Action::Previous,
+        Action::SeekForward,
+        Action::SeekBackward,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::ToggleShuffle,
+        Action::ToggleRepeat,
+        Action::Search,
+        Action::JumpToPlaying,
+        Action::Visualizer,
+        Action::Shortcuts,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::PlayPause => "Play or pause",
+            Action::Next => "Next track",
+            Action::Previous => "Previous track",
+            Action::SeekForward => "Seek forward 5s",
+            Action::SeekBackward => "Seek back 5s",
+            Action::VolumeUp => "Volume up",
+            Action::VolumeDown => "Volume down",
+            Action::ToggleShuffle => "Toggle shuffle",
+            Action::ToggleRepeat => "Toggle repeat",
+            Action::Search => "Search",
+            Action::JumpToPlaying => "Jump to the playing track",
+            Action::Visualizer => "Fullscreen visualizer",
+            Action::Shortcuts => "Keyboard shortcuts",
+        }
+    }
+
+    /// Whether holding the key down keeps doing it.
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::SeekForward | Action::SeekBackward | Action::VolumeUp | Action::VolumeDown
+        )
+    }
+
+    fn defaults(self) -> Vec<KeyBinding> {
+        let binding = match self {
+            Action::PlayPause => KeyBinding::new(Key::Space),
+            Action::Next => KeyBinding::ctrl(Key::ArrowRight),
+            Action::Previous => KeyBinding::ctrl(Key::ArrowLeft),
+            Action::SeekForward => KeyBinding::shift(Key::ArrowRight),
+            Action::SeekBackward => KeyBinding::shift(Key::ArrowLeft),
+            Action::VolumeUp => KeyBinding::ctrl(Key::ArrowUp),
+            Action::VolumeDown => KeyBinding::ctrl(Key::ArrowDown),
+            Action::ToggleShuffle => KeyBinding::ctrl(Key::S),
+            Action::ToggleRepeat => KeyBinding::ctrl(Key::R),
+            Action::Search => KeyBinding::ctrl(Key::F),
+            Action::JumpToPlaying => KeyBinding::ctrl(Key::J),
+            Action::Visualizer => KeyBinding::new(Key::F11),
+            Action::Shortcuts => KeyBinding::ctrl(Key::Comma),
+        };
+        vec![binding]
+    }
+}
+
+/// A key and the modifiers held with it, written like `Ctrl+Shift+Left` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    pub fn new(key: Key) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    pub fn ctrl(key: Key) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::new(key)
+        }
+    }
+
+    pub fn shift(key: Key) -> Self {
+        Self {
+            shift: true,
+            ..Self::new(key)
+        }
+    }
+
+    /// The binding for a key pressed with `modifiers`, Cmd counting as Ctrl.
+    pub fn pressed(key: Key, modifiers: egui::Modifiers) -> Self {
+        Self {
+            key,
+            ctrl: modifiers.ctrl || modifiers.mac_cmd,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+        }
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+        ] {
+            if held {
+                write!(f, "{name}+")?;
+            }
+        }
+        f.write_str(self.key.name())
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let key = Key::from_name(key).ok_or_else(|| format!("unknown key \"{key}\" in {text}"))?;
+
+        let mut binding = KeyBinding::new(key);
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" | "cmd" => binding.ctrl = true,
+                "alt" => binding.alt = true,
+                "shift" => binding.shift = true,
+                _ => return Err(format!("unknown modifier \"{modifier}\" in {text}")),
+            }
+        }
+        Ok(binding)
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(binding: KeyBinding) -> Self {
+        binding.to_string()
+    }
+}
+
+/// Which keys trigger which actions. An action can have several keys, or none.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "BTreeMap<String, Vec<KeyBinding>>")]
+pub struct Keymap {
+    #[serde(flatten)]
+    bindings: BTreeMap<Action, Vec<KeyBinding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.defaults()))
+                .collect(),
+        }
+    }
+}
+
+// Read with string keys so errors in a binding can name its action. Actions the config
+// leaves out keep their default keys.
+impl TryFrom<BTreeMap<String, Vec<KeyBinding>>> for Keymap {
+    type Error = serde::de::value::Error;
+
+    fn try_from(bindings: BTreeMap<String, Vec<KeyBinding>>) -> Result<Self, Self::Error> {
+        use serde::Deserialize;
+        use serde::de::IntoDeserializer;
+
+        let mut keymap = Self::default();
+        for (name, bindings) in bindings {
+            let action = Action::deserialize(name.as_str().into_deserializer())?;
+            keymap.bindings.insert(action, bindings);
+        }
+        Ok(keymap)
+    }
+}
+
+impl Keymap {
+    /// What `binding` does, if anything.
+    pub fn action(&self, binding: &KeyBinding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, bindings)| bindings.contains(binding))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn bindings(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Binds `binding` to `action`, taking it away from whatever had it before.
+    /// Returns that action, so the user can be told.
+    pub fn bind(&mut self, action: Action, binding: KeyBinding) -> Option<Action> {
+        let previous = self.action(&binding).filter(|&other| other != action);
+        if let Some(other) = previous {
+            self.unbind(other, &binding);
+        }
+
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+        previous
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: &KeyBinding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|bound| bound != binding);
+        }
+    }
+
+    /// Keys bound to more than one action, with every action they're bound to.
+    pub fn conflicts(&self) -> Vec<(KeyBinding, Vec<Action>)> {
+        let mut actions: Vec<(KeyBinding, Vec<Action>)> = Vec::new();
+
+        for (&action, bindings) in &self.bindings {
+            for binding in bindings {
+                match actions.iter_mut().find(|(bound, _)| bound == binding) {
+                    Some((_, list)) => {
+                        if !list.contains(&action) {
+                            list.push(action);
+                        }
+                    }
+                    None => actions.push((*binding, vec![action])),
+                }
+            }
+        }
+
+        actions.retain(|(_, list)| list.len() > 1);
+        actions
+    }
+
+    /// Leaves each conflicting key with the first action it's bound to, describing each fix.
+    pub fn resolve_conflicts(&mut self) -> Vec<String> {
+        let mut fixes = Vec::new();
+
+        for (binding, actions) in self.conflicts() {
+            for &action in &actions[1..] {
+                self.unbind(action, &binding);
+            }
+            let dropped: Vec<&str> = actions[1..].iter().map(|action| action.label()).collect();
+            fixes.push(format!(
+                "{binding} is bound more than once, keeping it for {} and dropping {}",
+                actions[0].label(),
+                dropped.join(", ")
+            ));
+        }
+
+        fixes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_read_and_write_like_the_config_has_them() {
+        let binding: KeyBinding = "ctrl+shift+Left".parse().unwrap();
+        assert_eq!(
+            binding,
+            KeyBinding {
+                shift: true,
+                ..KeyBinding::ctrl(Key::ArrowLeft)
+            }
+        );
+        assert_eq!(binding.to_string(), "Ctrl+Shift+Left");
+        assert_eq!("Space".parse(), Ok(KeyBinding::new(Key::Space)));
+
+        assert!("Ctrl+Nope".parse::<KeyBinding>().is_err());
+        assert!("Hyper+S".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn rebinding_takes_the_key_from_its_old_action() {
+        let mut keymap = Keymap::default();
+        let ctrl_s = KeyBinding::ctrl(Key::S);
+        assert_eq!(keymap.action(&ctrl_s), Some(Action::ToggleShuffle));
+
+        assert_eq!(
+            keymap.bind(Action::Search, ctrl_s),
+            Some(Action::ToggleShuffle)
+        );
+        assert_eq!(keymap.action(&ctrl_s), Some(Action::Search));
+        assert!(keymap.bindings(Action::ToggleShuffle).is_empty());
+        assert_eq!(keymap.bindings(Action::Search).len(), 2);
+        assert!(keymap.conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicts_keep_the_first_action() {
+        let config = r#"{ "next": ["Space"], "toggle_repeat": ["Ctrl+S"] }"#;
+        let mut keymap: Keymap = serde_json::from_str(config).unwrap();
+
+        // Everything the config leaves out keeps its default
+        assert_eq!(keymap.bindings(Action::Search), [KeyBinding::ctrl(Key::F)]);
+        assert_eq!(keymap.conflicts().len(), 2);
+
+        let fixes = keymap.resolve_conflicts();
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(
+            keymap.action(&KeyBinding::new(Key::Space)),
+            Some(Action::PlayPause)
+        );
+        assert!(keymap.bindings(Action::Next).is_empty());
+        assert_eq!(
+            keymap.action(&KeyBinding::ctrl(Key::S)),
+            Some(Action::ToggleShuffle)
+        );
+    }
+}
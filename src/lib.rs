@@ -0,0 +1,35 @@
+//! Sanctum's playback engine and music library, without any UI.
+//!
+//! The `sanctum` binary is an egui front end over this crate, but the scanner,
+//! playlists, search and player can be driven from any other tool the same way.
+
+pub mod cache;
+pub mod config;
+pub mod events;
+pub mod http;
+pub mod ipc;
+pub mod keymap;
+pub mod library;
+pub mod mpd;
+pub mod output;
+pub mod peaks;
+pub mod player;
+pub mod playlist;
+pub mod search;
+pub mod session;
+pub mod songs;
+pub mod theme;
+pub mod utils;
+pub mod visualizer;
+
+pub use config::Config;
+pub use events::{PlayerCommand, PlayerEvent, PlayerHandle};
+pub use library::{EntryId, Library, SongId};
+pub use output::{AudioOutput, CaptureOutput, NullOutput, OutputError, RodioOutput};
+pub use player::{PlaybackMode, Player, PlayerState};
+pub use playlist::{Playlist, Sort, sort_songs};
+pub use search::Search;
+pub use session::Session;
+pub use songs::{PlayCounts, Song, load_song, load_songs};
+pub use theme::Theme;
+pub use visualizer::{AudioTap, VisualizerSettings, VisualizerStyle};
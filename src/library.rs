@@ -0,0 +1,164 @@
+//! Songs addressed by a stable [`SongId`] rather than by where they sit in a list, so
+//! the player, queue and remotes keep pointing at the same track across re-sorts,
+//! rescans and playlist switches.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use crate::songs::Song;
+
+// Every path gets its id the first time it's seen and keeps it until Sanctum exits
+static IDS: LazyLock<Mutex<HashMap<String, SongId>>> = LazyLock::new(Default::default);
+
+/// Identifies a song by its path for as long as Sanctum runs. It's a small number so
+/// remotes like MPD clients can hold on to it; what's saved to disk is the path itself.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+#[serde(transparent)]
+pub struct SongId(u32);
+
+impl SongId {
+    /// The id for `path`, handing out a new one if it hasn't been seen before.
+    pub fn of(path: &str) -> Self {
+        let mut ids = IDS.lock().expect("Song ids poisoned!");
+        if let Some(&id) = ids.get(path) {
+            return id;
+        }
+
+        let id = SongId(ids.len() as u32);
+        ids.insert(path.to_string(), id);
+        id
+    }
+
+    /// The id `path` already has, without handing one out.
+    pub fn lookup(path: &str) -> Option<Self> {
+        IDS.lock().ok()?.get(path).copied()
+    }
+}
+
+impl fmt::Display for SongId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for SongId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.parse().map(SongId)
+    }
+}
+
+/// One place in the play queue, the current track included. A song that's queued twice
+/// has one [`SongId`] but two of these, and every time a song is queued it gets a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntryId(u32);
+
+impl EntryId {
+    /// An id no entry has had before.
+    pub fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        EntryId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for EntryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for EntryId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.parse().map(EntryId)
+    }
+}
+
+/// A song list in folder order, with a map from id to position that's built along with it.
+///
+/// Lists are swapped whole rather than edited, so the map can't fall out of date.
+#[derive(Debug, Clone, Default)]
+pub struct Library {
+    songs: Vec<Song>,
+    positions: HashMap<SongId, usize>,
+}
+
+impl Library {
+    /// Indexes `songs`, dropping any path that's listed twice.
+    pub fn new(songs: Vec<Song>) -> Self {
+        let mut library = Self::default();
+        for song in songs {
+            library.push(song);
+        }
+        library
+    }
+
+    fn push(&mut self, song: Song) {
+        if !self.positions.contains_key(&song.id) {
+            self.positions.insert(song.id, self.songs.len());
+            self.songs.push(song);
+        }
+    }
+
+    /// A copy with `song` added at the end, every other song keeps its place.
+    pub fn with(&self, song: Song) -> Self {
+        let mut library = self.clone();
+        library.push(song);
+        library
+    }
+
+    pub fn get(&self, id: SongId) -> Option<&Song> {
+        self.position(id).map(|position| &self.songs[position])
+    }
+
+    /// Where the song sits in folder order.
+    pub fn position(&self, id: SongId) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+
+    pub fn contains(&self, id: SongId) -> bool {
+        self.positions.contains_key(&id)
+    }
+
+    /// The song at `path`, if it's part of this library.
+    pub fn find(&self, path: &str) -> Option<SongId> {
+        SongId::lookup(path).filter(|&id| self.contains(id))
+    }
+
+    pub fn first(&self) -> Option<SongId> {
+        self.songs.first().map(|song| song.id)
+    }
+
+    /// The song `step` places away from `id` in folder order, wrapping around the ends.
+    pub fn step(&self, id: SongId, step: isize) -> Option<SongId> {
+        let position = self.position(id)? as isize;
+        let len = self.songs.len() as isize;
+        Some(self.songs[(position + step).rem_euclid(len) as usize].id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = SongId> + '_ {
+        self.songs.iter().map(|song| song.id)
+    }
+}
+
+impl Deref for Library {
+    type Target = [Song];
+
+    fn deref(&self) -> &[Song] {
+        &self.songs
+    }
+}
+
+impl From<Vec<Song>> for Library {
+    fn from(songs: Vec<Song>) -> Self {
+        Self::new(songs)
+    }
+}
@@ -1,40 +1,119 @@
 use eframe::egui;
 
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
-
-pub mod config;
-use config::Config;
-
-pub mod cache;
-use cache::{SancCache, load_cache};
-
-pub mod mpris;
-use mpris::MprisHandler;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::{Duration, Instant};
+
+use sanctum::cache::{SancCache, load_cache};
+use sanctum::config::{ConfigError, back_up_broken, config_path};
+use sanctum::ipc::{self, Reply, Request, Response};
+use sanctum::keymap::{Action, KeyBinding};
+use sanctum::mpd;
+use sanctum::session::{Autosave, AutosaveUpdate, Autosaver, Session, session_path};
+use sanctum::{
+    AudioTap, Config, Library, PlayCounts, Player, PlayerCommand, PlayerEvent, PlayerHandle,
+    PlayerState, Playlist, RodioOutput, Search, SongId, Sort, Theme, load_song, load_songs,
+    sort_songs,
+};
+
+mod app;
+use app::{AppCommand, AppHandle, PlaylistsSnapshot, Remote};
+
+mod web;
+
+mod mpris;
+use mpris::{MprisHandler, MprisUpdate, spawn_bridge};
 use mpris_server::Server;
 
-pub mod ui;
-pub mod utils;
-
-pub mod player;
-use player::Player;
-use player::PlayerState;
-
-pub mod playlist;
-use playlist::{Playlist, sort_songs};
-
-pub mod search;
-use search::Search;
-
-pub mod songs;
-use songs::Song;
+mod ui;
+use ui::onboarding::Onboarding;
+use ui::shortcuts::ShortcutsView;
+use ui::visualizer::VisualizerView;
 
 use mimalloc::MiMalloc;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+// How far the seek shortcuts jump
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
 fn main() -> eframe::Result {
+    let requests = match ipc::parse_args(std::env::args().skip(1)) {
+        Ok(requests) => requests,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", ipc::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // A second launch hands its arguments over to the running player and bows out
+    let socket = match ipc::socket_path() {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Can't find a place for the control socket: {e}");
+            std::process::exit(1);
+        }
+    };
+    let forwarded = if requests.is_empty() {
+        vec![Request::Raise]
+    } else {
+        requests.clone()
+    };
+    // Whoever loses a simultaneous launch finds the winner on its next try
+    let listener = loop {
+        match ipc::forward(&socket, &forwarded) {
+            Ok(Some(responses)) => {
+                let mut failed = false;
+                for response in responses {
+                    if let Response::Error { message } = response {
+                        eprintln!("{message}");
+                        failed = true;
+                    }
+                }
+                std::process::exit(if failed { 1 } else { 0 });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Sanctum is already running but didn't answer: {e}");
+                std::process::exit(1);
+            }
+        }
+
+        match ipc::listen(&socket) {
+            Ok(listener) => break listener,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => {
+                eprintln!("Can't open the control socket at {}: {e}", socket.display());
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // A broken config is set aside rather than crashing, onboarding starts over with defaults
+    let (config, notice) = match load_config() {
+        Ok(config) => (config, None),
+        Err(e) => {
+            eprintln!("{e}");
+            match back_up_broken(&e.path) {
+                Ok(backup) => (
+                    None,
+                    Some(format!(
+                        "Your config couldn't be read, so Sanctum is starting fresh. The old one was saved as {}.\n\n{e}",
+                        backup.display()
+                    )),
+                ),
+                Err(backup_error) => {
+                    eprintln!("Can't back up {}: {backup_error}", e.path.display());
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_maximized(true),
         ..Default::default()
@@ -43,111 +122,505 @@ fn main() -> eframe::Result {
     eframe::run_native(
         "Sanctum Player",
         options,
-        Box::new(|cc| Ok(Box::new(Sanctum::new(cc)))),
+        Box::new(|cc| {
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+            let screen = match config {
+                Some(config) if !config.get_playlists().is_empty() => Screen::Player(Box::new(
+                    Sanctum::new(&cc.egui_ctx, config, listener, requests),
+                )),
+                config => Screen::Onboarding(
+                    Box::new(Onboarding::new(
+                        &cc.egui_ctx,
+                        config.unwrap_or_default(),
+                        notice,
+                    )),
+                    Some((listener, requests)),
+                ),
+            };
+            Ok(Box::new(screen))
+        }),
     )
 }
 
+/// The config from the XDG config folder. One left in the working directory by older
+/// versions gets moved there, `None` means this is a first run.
+fn load_config() -> Result<Option<Config>, ConfigError> {
+    let path = config_path();
+    if let Some(config) = Config::load(&path)? {
+        return Ok(Some(config));
+    }
+
+    let legacy = PathBuf::from("config.json");
+    let Some(config) = Config::load(&legacy)? else {
+        return Ok(None);
+    };
+    match config.save(&path) {
+        Ok(()) => eprintln!("Moved {} to {}", legacy.display(), path.display()),
+        Err(e) => eprintln!("Can't move the config to {}: {e}", path.display()),
+    }
+    Ok(Some(config))
+}
+
+/// Onboarding runs until there's a config to start the player with.
+enum Screen {
+    // The control socket is only served once the player exists, until then clients wait
+    Onboarding(Box<Onboarding>, Option<(UnixListener, Vec<Request>)>),
+    Player(Box<Sanctum>),
+}
+
+impl eframe::App for Screen {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        match self {
+            Screen::Player(sanc) => sanc.update(ctx, frame),
+            Screen::Onboarding(onboarding, pending) => {
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    let _ = ipc::socket_path().and_then(std::fs::remove_file);
+                    return;
+                }
+
+                let Some(config) = onboarding.show(ctx) else {
+                    return;
+                };
+                if let Err(e) = config.save(&config_path()) {
+                    eprintln!("Can't save config: {e}");
+                }
+                if let Some((listener, requests)) = pending.take() {
+                    *self = Screen::Player(Box::new(Sanctum::new(ctx, config, listener, requests)));
+                }
+            }
+        }
+    }
+}
+
 pub struct Sanctum {
-    player: Player,
+    player: PlayerState,
+    state: Arc<Mutex<PlayerState>>,
+    handle: PlayerHandle,
+    events: Receiver<PlayerEvent>,
     volume: u32,
     config: Config,
+    /// Index of `current_playlist` in `playlists`.
+    active_playlist: usize,
     current_playlist: Playlist,
     playlists: Vec<Playlist>,
-    songs: Vec<Song>,
-    song_view: Vec<usize>,
+    songs: Arc<Library>,
+    /// The library in the playlist's sort order.
+    song_view: Vec<SongId>,
     cache: SancCache,
     search: Search,
-    mpris: Server<MprisHandler>,
+    visualizer: VisualizerView,
+    shortcuts: ShortcutsView,
+    themes: Vec<Theme>,
+    /// The theme in use, with the cover's accent when that's turned on.
+    theme: Theme,
+    /// The cover accent `theme` carries, if any.
+    cover_accent: Option<egui::Color32>,
+    /// Set by the jump to playing shortcut, the track list scrolls there on its next draw.
+    scroll_to_playing: bool,
+    app_commands: Receiver<AppCommand>,
+    mpris: Sender<MprisUpdate>,
+    shared_playlists: Arc<Mutex<PlaylistsSnapshot>>,
+    autosave: Sender<AutosaveUpdate>,
+    /// Why the playlist's folder couldn't be read, shown above its empty track list.
+    library_error: Option<String>,
+}
+
+/// The library for a playlist's folder, empty along with the reason when the folder
+/// is missing or unreadable.
+fn open_library(playlist: &Playlist) -> (Arc<Library>, Option<String>) {
+    match load_songs(playlist.path.clone()) {
+        Ok(songs) => (Arc::new(Library::new(songs)), None),
+        Err(e) => {
+            eprintln!("Can't open {}: {e}", playlist.path);
+            let message = format!("Can't open the folder {}: {e}", playlist.path);
+            (Arc::new(Library::new(Vec::new())), Some(message))
+        }
+    }
 }
 
 impl Sanctum {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        egui_extras::install_image_loaders(&cc.egui_ctx);
-        let config_file = std::fs::read_to_string("config.json").expect("Can't find config file!");
-        let config: Config = serde_json::from_str(config_file.as_str()).expect("Can't parse JSON!");
+    fn new(
+        ctx: &egui::Context,
+        mut config: Config,
+        listener: UnixListener,
+        requests: Vec<Request>,
+    ) -> Self {
         let playlists = config.get_playlists().clone();
 
-        let current_playlist = (config.get_playlists())[config.current_playlist()].clone();
+        // Configs from before the session file carry the session themselves
+        let mut autosave = Autosave::new(Instant::now());
+        let mut session = match config.take_session() {
+            Some(session) => {
+                autosave.config_changed(Instant::now());
+                autosave.session_changed(Instant::now());
+                session
+            }
+            None => Session::load(&session_path()),
+        };
+        session.repair(playlists.len());
+
+        let active_playlist = session.playlist;
+        let current_playlist = playlists[active_playlist].clone();
 
         let cache_path = config.cache_path.clone();
 
-        let mut sanc_cache = SancCache::new(cache_path);
+        let mut sanc_cache = SancCache::new(cache_path.clone());
 
         load_cache(&mut sanc_cache);
 
-        let songs = songs::load_songs(current_playlist.path.clone());
+        let (songs, library_error) = open_library(&current_playlist);
 
-        let mut song_view: Vec<usize> = (0..songs.len()).collect();
+        let mut song_view: Vec<SongId> = songs.ids().collect();
         sort_songs(current_playlist.clone(), &mut song_view, &songs);
 
-        let shared_state = Arc::new(Mutex::new(PlayerState::default()));
+        let shared_state = Arc::new(Mutex::new(PlayerState {
+            play_counts: PlayCounts::new(config.get_play_counts().clone()),
+            ..PlayerState::default()
+        }));
         let mpris_state = Arc::clone(&shared_state);
         let player_state = Arc::clone(&shared_state);
 
-        let mpris_handler = MprisHandler { state: mpris_state };
-
-        let mut player: Player = Player::new(config.get_last_track(), player_state);
+        let tap = AudioTap::default();
+        let output_tap = tap.clone();
 
         let volume = config.get_volume();
-        player.volume(volume);
+        let handle = Player::spawn(
+            move || {
+                let output = RodioOutput::open_default().expect("Can't find speaker!");
+                Box::new(output.with_tap(output_tap))
+            },
+            session.track_in(&songs),
+            volume,
+            Arc::clone(&songs),
+            player_state,
+        );
+        let events = handle.subscribe();
+        let autosave_events = handle.subscribe();
+
+        // Picks up the mode, queue and position, the track itself was cued above
+        for command in session.commands(&songs) {
+            handle.send(command);
+        }
 
-        player.sink.pause();
+        // Wake the UI up whenever the player changes, it otherwise idles between inputs
+        let repaint = handle.subscribe();
+        let repaint_ctx = ctx.clone();
+        std::thread::Builder::new()
+            .name("sanctum-repaint".to_string())
+            .spawn(move || {
+                for _ in repaint {
+                    repaint_ctx.request_repaint();
+                }
+            })
+            .expect("Can't start repaint thread!");
+
+        let (app_tx, app_commands) = channel();
+        let shared_playlists = Arc::new(Mutex::new(PlaylistsSnapshot {
+            playlists: playlists.clone(),
+            active: active_playlist,
+        }));
+
+        let app = AppHandle::new(app_tx, ctx.clone());
+
+        let remote = Arc::new(Remote {
+            player: handle.clone(),
+            app: app.clone(),
+            state: Arc::clone(&shared_state),
+            playlists: Arc::clone(&shared_playlists),
+        });
 
-        let mpris = futures::executor::block_on(Server::new("Sanctum.Player", mpris_handler))
-            .expect("Can't make server!");
+        // Arguments this instance was started with go through the same path as forwarded ones
+        for request in requests {
+            if let Reply::Once(Response::Error { message }) = remote.handle(request) {
+                eprintln!("{message}");
+            }
+        }
+        if config.ensure_http_token() {
+            eprintln!(
+                "Generated a token for the HTTP API, it's under http in {}",
+                config_path().display()
+            );
+            autosave.config_changed(Instant::now());
+        }
+        let autosave = Autosaver {
+            config: config.clone(),
+            config_path: config_path(),
+            session_path: session_path(),
+            playlist: active_playlist,
+            state: Arc::clone(&shared_state),
+            autosave,
+        }
+        .spawn(autosave_events);
+
+        web::start(config.get_http(), Arc::clone(&remote));
+        mpd::start(config.get_mpd(), handle.clone(), Arc::clone(&shared_state));
+        ipc::serve(listener, move |request| remote.handle(request));
+
+        let mpris_handler = MprisHandler {
+            state: mpris_state,
+            player: handle.clone(),
+            app,
+            playlists: Arc::clone(&shared_playlists),
+            cache_dir: cache_path.clone(),
+            covers: sanc_cache.tx.clone(),
+            ctx: ctx.clone(),
+        };
+
+        // Without a session bus or with the name taken the player just goes without MPRIS
+        let mpris = match futures::executor::block_on(Server::new_with_all(
+            "Sanctum.Player",
+            mpris_handler,
+        )) {
+            Ok(server) => spawn_bridge(server, handle.subscribe()),
+            Err(e) => {
+                eprintln!("Can't register with MPRIS: {e}");
+                channel().0
+            }
+        };
+
+        let player = shared_state.lock().expect("Player state poisoned!").clone();
+        let (themes, theme) = ui::theme::load(ctx, &config);
 
         Self {
             config,
             player,
+            state: shared_state,
+            handle,
+            events,
             volume,
+            active_playlist,
             current_playlist,
             playlists,
             songs,
             song_view,
             cache: sanc_cache,
             search: Search::default(),
+            visualizer: VisualizerView::new(tap),
+            shortcuts: ShortcutsView::default(),
+            themes,
+            theme,
+            cover_accent: None,
+            scroll_to_playing: false,
+            app_commands,
             mpris,
+            shared_playlists,
+            autosave,
+            library_error,
+        }
+    }
+
+    /// Loads another playlist's folder and starts playing it from the top of its view.
+    pub fn switch_playlist(&mut self, index: usize) {
+        let Some(playlist) = self.playlists.get(index).cloned() else {
+            return;
+        };
+
+        // Keep whatever sort the playlist we're leaving had
+        self.config
+            .update_playlist(self.active_playlist, self.current_playlist.clone());
+        self.playlists[self.active_playlist] = self.current_playlist.clone();
+        self.active_playlist = index;
+
+        let (songs, library_error) = open_library(&playlist);
+        let mut song_view: Vec<SongId> = songs.ids().collect();
+        sort_songs(playlist.clone(), &mut song_view, &songs);
+
+        self.handle
+            .send(PlayerCommand::SetSongs(Arc::clone(&songs)));
+        if let Some(&first) = song_view.first() {
+            self.handle.send(PlayerCommand::PlaySong(first));
+        }
+
+        self.songs = songs;
+        self.song_view = song_view;
+        self.library_error = library_error;
+        self.current_playlist = playlist;
+        self.config_changed();
+        let _ = self.autosave.send(AutosaveUpdate::Playlist(index));
+        self.search.query.clear();
+        self.search.results.clear();
+
+        if let Ok(mut snapshot) = self.shared_playlists.lock() {
+            snapshot.playlists = self.playlists.clone();
+            snapshot.active = index;
+        }
+        let _ = self.mpris.send(MprisUpdate::PlaylistsChanged);
+    }
+
+    /// The library's id for a file. Files outside the playlist's folder join the
+    /// library for this session only.
+    fn song_id(&mut self, path: PathBuf) -> Option<SongId> {
+        let song_path = path.display().to_string();
+        if let Some(id) = self.songs.find(&song_path) {
+            return Some(id);
+        }
+
+        let song = match load_song(path) {
+            Ok(song) => song,
+            Err(e) => {
+                eprintln!("Can't open {song_path}: {e}");
+                return None;
+            }
+        };
+
+        let id = song.id;
+        self.songs = Arc::new(self.songs.with(song));
+        self.song_view.push(id);
+
+        self.handle
+            .send(PlayerCommand::AppendSongs(Arc::clone(&self.songs)));
+        Some(id)
+    }
+
+    /// Plays a file from anywhere on disk.
+    pub fn open_file(&mut self, path: PathBuf) {
+        if let Some(id) = self.song_id(path) {
+            self.handle.send(PlayerCommand::PlaySong(id));
+        }
+    }
+
+    /// Queues a file from anywhere on disk.
+    pub fn enqueue_file(&mut self, path: PathBuf) {
+        if let Some(id) = self.song_id(path) {
+            self.handle.send(PlayerCommand::Enqueue(id));
+        }
+    }
+
+    /// Sorts the song list and remembers the order for the playlist.
+    pub fn set_sort(&mut self, sort: Sort) {
+        self.current_playlist.set_sort(sort);
+        sort_songs(
+            self.current_playlist.clone(),
+            &mut self.song_view,
+            &self.songs,
+        );
+        self.config_changed();
+    }
+
+    /// Hands the config over to be saved once it settles.
+    pub fn config_changed(&mut self) {
+        self.config
+            .update_playlist(self.active_playlist, self.current_playlist.clone());
+        let _ = self
+            .autosave
+            .send(AutosaveUpdate::Config(Box::new(self.config.clone())));
+    }
+
+    /// Writes the config and session before closing, waiting a little for the disk.
+    fn save_now(&mut self) {
+        self.config_changed();
+        let (done, saved) = channel();
+        if self.autosave.send(AutosaveUpdate::Flush(done)).is_ok()
+            && saved.recv_timeout(Duration::from_secs(5)).is_err()
+        {
+            eprintln!("Gave up waiting for the config and session to save");
+        }
+    }
+
+    fn handle_app_commands(&mut self, ctx: &egui::Context) {
+        while let Ok(command) = self.app_commands.try_recv() {
+            match command {
+                AppCommand::SwitchPlaylist(index) => self.switch_playlist(index),
+                AppCommand::Raise => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                // Closing goes through close_requested below, which saves the config
+                AppCommand::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                AppCommand::OpenFile(path) => self.open_file(path),
+                AppCommand::EnqueueFile(path) => self.enqueue_file(path),
+            }
+        }
+    }
+}
+
+impl Sanctum {
+    fn handle_keybinds(&mut self, event: &egui::Event) {
+        let egui::Event::Key {
+            key,
+            pressed: true,
+            repeat,
+            modifiers,
+            ..
+        } = event
+        else {
+            return;
+        };
+
+        let binding = KeyBinding::pressed(*key, *modifiers);
+        let Some(action) = self.config.get_keys().action(&binding) else {
+            return;
+        };
+        if *repeat && !action.repeats() {
+            return;
+        }
+
+        match action {
+            Action::PlayPause => self.handle.send(PlayerCommand::PlayPause),
+            Action::Next => self.handle.send(PlayerCommand::Next),
+            Action::Previous => self.handle.send(PlayerCommand::Previous),
+            Action::SeekForward => self.handle.send(PlayerCommand::SeekForward(SEEK_STEP)),
+            Action::SeekBackward => self.handle.send(PlayerCommand::SeekBackward(SEEK_STEP)),
+            // Relative, so held keys don't repeat a volume the player hasn't reported yet
+            Action::VolumeUp => self.handle.send(PlayerCommand::AdjustVolume(1)),
+            Action::VolumeDown => self.handle.send(PlayerCommand::AdjustVolume(-1)),
+            Action::ToggleShuffle => self.handle.send(PlayerCommand::ToggleShuffle),
+            Action::ToggleRepeat => self.handle.send(PlayerCommand::ToggleRepeat),
+            Action::Search => self.search.open_modal(),
+            Action::JumpToPlaying => self.scroll_to_playing = true,
+            Action::Visualizer => {
+                // Only shows up while the visualizer is on, it turns itself back off otherwise
+                self.visualizer.fullscreen =
+                    !self.visualizer.fullscreen && self.visualizer.tap.is_active();
+            }
+            Action::Shortcuts => self.shortcuts.open = !self.shortcuts.open,
         }
     }
 }
 
 impl eframe::App for Sanctum {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        ctx.request_repaint();
+        if let Ok(state) = self.state.lock() {
+            self.player = state.clone();
+        }
 
-        while let Ok((album, image_path)) = self.cache.rx.try_recv() {
+        // Only the seek bar moves on its own, everything else repaints on events or input
+        if self.player.is_playing() {
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+
+        self.handle_app_commands(ctx);
+
+        while let Ok((album, image_path, accent)) = self.cache.rx.try_recv() {
             self.cache.covers.insert(album.clone(), image_path);
+            self.cache.accents.insert(album.clone(), accent);
             self.cache.loading_covers.remove(&album);
+            self.cache.loading_accents.remove(&album);
         }
-        if !self.search.modal {
-            ctx.input(|i| {
-                for event in &i.events {
-                    self.player.handle_keybinds(
-                        i,
-                        event,
-                        &mut self.volume,
-                        &mut self.config,
-                        &self.songs,
-                    );
-
-                    if i.modifiers.ctrl {
-                        if let egui::Event::Key {
-                            key: egui::Key::F,
-                            pressed: true,
-                            repeat: false,
-                            ..
-                        } = event
-                        {
-                            self.search.open_modal();
-                        }
-                    }
-                }
-            });
+        ui::theme::follow_cover(ctx, self);
+
+        while let Ok((song_path, peaks)) = self.cache.peaks_rx.try_recv() {
+            self.cache.peaks.insert(song_path, Arc::new(peaks));
+        }
+        if !self.search.modal && !self.shortcuts.capturing() {
+            let events = ctx.input(|i| i.events.clone());
+            for event in &events {
+                self.handle_keybinds(event);
+            }
+        }
+
+        // Keeps the volume in step whichever remote changed it
+        while let Ok(event) = self.events.try_recv() {
+            if let PlayerEvent::VolumeChanged(volume) = event {
+                self.volume = volume;
+                self.config.set_volume(volume);
+            }
         }
 
-        self.player.process(&self.songs);
-        self.player
-            .update_state(&self.mpris, &self.cache, &self.songs);
+        let style = self.config.get_visualizer().style;
+        self.visualizer.update_visibility(ctx, style);
+
+        ui::shortcuts::shortcuts(ctx, self);
 
         egui::TopBottomPanel::bottom("play_bar").show(ctx, |ui| {
             ui::playbar::playbar(ui, self.player.is_playing(), self);
@@ -160,10 +633,20 @@ impl eframe::App for Sanctum {
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.visualizer.fullscreen {
+                let size = ui.available_size();
+                ui::visualizer::visualizer(ui, self, size);
+                return;
+            }
+
             ui.horizontal(|ui| {
                 ui::searchbar::search_bar(ui, self);
             });
 
+            if let Some(error) = &self.library_error {
+                ui.colored_label(ui.visuals().warn_fg_color, error);
+            }
+
             egui::ScrollArea::vertical().show(ui, |ui| {
                 ui::tracklist::playlist(ui, self);
             });
@@ -172,11 +655,8 @@ impl eframe::App for Sanctum {
         let close = ctx.input(|i| i.viewport().close_requested());
 
         if close {
-            self.config.set_track(self.player.current_index);
-            self.config.update_playlist(self.current_playlist.clone());
-            let new_config =
-                serde_json::to_string_pretty(&self.config).expect("Can't export config!");
-            std::fs::write("config.json", new_config).expect("Can't update config!");
+            self.save_now();
+            let _ = ipc::socket_path().and_then(std::fs::remove_file);
         }
     }
 }
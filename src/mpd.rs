@@ -0,0 +1,1005 @@
+//! A subset of the MPD protocol, so existing MPD clients can drive the player.
+//!
+//! MPD's play queue maps onto the current track followed by our queue, the same way
+//! MPRIS sees it, and its database onto the loaded songs, each addressed by file name.
+//! Song ids are the queue's [`EntryId`]s, so they stay put while the queue moves and a
+//! song queued twice has two.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use mpris_server::PlaybackStatus;
+
+use crate::events::{PlayerCommand, PlayerEvent, PlayerHandle};
+use crate::library::{EntryId, SongId};
+use crate::player::{PlaybackMode, PlayerState};
+use crate::songs::Song;
+use crate::utils::constant_time_eq;
+
+// The protocol version we claim, old enough that clients don't expect filter expressions
+const VERSION: &str = "0.21.0";
+
+// Clients sending more than this are dropped rather than buffered
+const MAX_LINE: usize = 8 * 1024;
+const MAX_LIST: usize = 4096;
+
+const COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "commands",
+    "consume",
+    "currentsong",
+    "delete",
+    "deleteid",
+    "find",
+    "getvol",
+    "idle",
+    "listall",
+    "listallinfo",
+    "lsinfo",
+    "next",
+    "noidle",
+    "notcommands",
+    "password",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "previous",
+    "random",
+    "repeat",
+    "search",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "single",
+    "stats",
+    "status",
+    "stop",
+    "tagtypes",
+    "urlhandlers",
+    "volume",
+];
+
+const TAG_TYPES: &[&str] = &[
+    "Artist",
+    "Album",
+    "AlbumArtist",
+    "Title",
+    "Track",
+    "Genre",
+    "Date",
+    "Composer",
+    "Disc",
+];
+
+// Error codes from MPD's ack.h
+const ACK_ARG: u32 = 2;
+const ACK_PASSWORD: u32 = 3;
+const ACK_PERMISSION: u32 = 4;
+const ACK_UNKNOWN: u32 = 5;
+const ACK_NO_EXIST: u32 = 50;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct MpdSettings {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+    /// Sent by clients with the `password` command, mandatory off localhost.
+    pub password: Option<String>,
+}
+
+impl Default for MpdSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 6600,
+            password: None,
+        }
+    }
+}
+
+impl MpdSettings {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// Why these settings shouldn't be served, if there's a reason.
+    pub fn problem(&self) -> Option<String> {
+        let password = self.password.as_deref().unwrap_or("");
+        if !self.address.is_loopback() && password.trim().is_empty() {
+            return Some(format!(
+                "listening on {} needs a password, since anyone on the network could reach it",
+                self.address
+            ));
+        }
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Ack {
+    code: u32,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn arg(message: impl Into<String>) -> Self {
+        Self::new(ACK_ARG, message)
+    }
+}
+
+enum Input {
+    Line(String),
+    Event(PlayerEvent),
+    TooLong,
+    Closed,
+}
+
+enum Flow {
+    Continue,
+    Close,
+}
+
+struct Shared {
+    player: PlayerHandle,
+    state: Arc<Mutex<PlayerState>>,
+    password: Option<String>,
+    /// Bumped whenever the play queue changes, clients diff against it.
+    version: AtomicU32,
+    started: Instant,
+}
+
+/// Starts the MPD server if it's turned on and safe to expose where it's configured.
+pub fn start(settings: &MpdSettings, player: PlayerHandle, state: Arc<Mutex<PlayerState>>) {
+    if !settings.enabled {
+        return;
+    }
+    if let Some(problem) = settings.problem() {
+        eprintln!("MPD server not started: {problem}");
+        return;
+    }
+
+    match TcpListener::bind(settings.socket_addr()) {
+        Ok(listener) => serve(listener, player, state, settings.password.clone()),
+        Err(e) => eprintln!("MPD server not started on {}: {e}", settings.socket_addr()),
+    }
+}
+
+/// Answers MPD clients on `listener`, each on its own thread.
+pub fn serve(
+    listener: TcpListener,
+    player: PlayerHandle,
+    state: Arc<Mutex<PlayerState>>,
+    password: Option<String>,
+) {
+    let shared = Arc::new(Shared {
+        player,
+        state,
+        password: password.filter(|password| !password.is_empty()),
+        version: AtomicU32::new(1),
+        started: Instant::now(),
+    });
+
+    let events = shared.player.subscribe();
+    let versions = Arc::clone(&shared);
+    std::thread::Builder::new()
+        .name("sanctum-mpd-version".to_string())
+        .spawn(move || {
+            for event in events {
+                if matches!(
+                    event,
+                    PlayerEvent::TrackChanged(_) | PlayerEvent::QueueChanged
+                ) {
+                    versions.version.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        })
+        .expect("Can't start MPD thread!");
+
+    std::thread::Builder::new()
+        .name("sanctum-mpd".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("MPD accept error: {e}");
+                        continue;
+                    }
+                };
+
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || {
+                    if let Err(e) = Session::new(shared).run(stream)
+                        && !matches!(
+                            e.kind(),
+                            io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+                        )
+                    {
+                        eprintln!("MPD client dropped: {e}");
+                    }
+                });
+            }
+        })
+        .expect("Can't start MPD thread!");
+}
+
+struct Session {
+    shared: Arc<Shared>,
+    authorized: bool,
+    /// Subsystems that changed since the client last idled.
+    changed: BTreeSet<&'static str>,
+    /// The subsystems the client is idling on, if it is.
+    idling: Option<Vec<String>>,
+    /// Commands collected between `command_list_begin` and `command_list_end`.
+    list: Option<(Vec<String>, bool)>,
+    /// Sent more than we take, the connection closes once that's been said.
+    refused: bool,
+}
+
+impl Session {
+    fn new(shared: Arc<Shared>) -> Self {
+        Self {
+            authorized: shared.password.is_none(),
+            shared,
+            changed: BTreeSet::new(),
+            idling: None,
+            list: None,
+            refused: false,
+        }
+    }
+
+    fn run(mut self, stream: TcpStream) -> io::Result<()> {
+        let (tx, rx) = channel();
+        read_lines(stream.try_clone()?, tx.clone());
+        forward_events(&self.shared.player, tx);
+
+        let mut writer = stream;
+        writer.write_all(format!("OK MPD {VERSION}\n").as_bytes())?;
+
+        for input in rx {
+            let mut out = String::new();
+            let flow = match input {
+                Input::Line(line) => self.line(&line, &mut out),
+                Input::Event(event) => {
+                    self.changed.extend(subsystems(&event));
+                    self.wake(&mut out, false);
+                    Flow::Continue
+                }
+                Input::TooLong => {
+                    out.push_str(&format!("ACK [{ACK_ARG}@0] {{}} Line too long\n"));
+                    self.refused = true;
+                    Flow::Close
+                }
+                Input::Closed => Flow::Close,
+            };
+
+            if !out.is_empty() {
+                writer.write_all(out.as_bytes())?;
+                writer.flush()?;
+            }
+            if let Flow::Close = flow {
+                break;
+            }
+        }
+
+        // Closing with the rest of the line unread would reset the connection, and the
+        // client could lose the ACK with it
+        if self.refused {
+            writer.shutdown(Shutdown::Write)?;
+            writer.set_read_timeout(Some(Duration::from_secs(1)))?;
+            let _ = io::copy(&mut (&writer).take(1024 * 1024), &mut io::sink());
+        }
+
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str, out: &mut String) -> Flow {
+        if self.idling.is_some() {
+            // Only noidle is allowed while idling, it ends the wait with whatever changed
+            if line.trim() == "noidle" {
+                self.wake(out, true);
+            }
+            return Flow::Continue;
+        }
+
+        if let Some((commands, with_ok)) = &mut self.list {
+            match line.trim() {
+                "command_list_end" => {
+                    let (commands, with_ok) = (std::mem::take(commands), *with_ok);
+                    self.list = None;
+                    return self.run_list(&commands, with_ok, out);
+                }
+                _ if commands.len() >= MAX_LIST => {
+                    out.push_str(&format!("ACK [{ACK_ARG}@0] {{}} Command list too long\n"));
+                    self.refused = true;
+                    return Flow::Close;
+                }
+                _ => commands.push(line.to_string()),
+            }
+            return Flow::Continue;
+        }
+
+        match line.trim() {
+            "command_list_begin" => self.list = Some((Vec::new(), false)),
+            "command_list_ok_begin" => self.list = Some((Vec::new(), true)),
+            _ => return self.run_list(&[line.to_string()], false, out),
+        }
+        Flow::Continue
+    }
+
+    fn run_list(&mut self, lines: &[String], with_ok: bool, out: &mut String) -> Flow {
+        for (i, line) in lines.iter().enumerate() {
+            let args = match tokenize(line) {
+                Ok(args) => args,
+                Err(e) => {
+                    out.push_str(&format!("ACK [{ACK_ARG}@{i}] {{}} {e}\n"));
+                    return Flow::Continue;
+                }
+            };
+            let Some((command, args)) = args.split_first() else {
+                out.push_str(&format!("ACK [{ACK_UNKNOWN}@{i}] {{}} No command given\n"));
+                return Flow::Continue;
+            };
+
+            match command.as_str() {
+                "close" => return Flow::Close,
+                // Answered later, once something changes or the client says noidle
+                "idle" if lines.len() == 1 => {
+                    self.idling = Some(args.iter().map(|arg| arg.to_lowercase()).collect());
+                    self.wake(out, false);
+                    return Flow::Continue;
+                }
+                _ => {}
+            }
+
+            if let Err(ack) = self.execute(command, args, out) {
+                out.push_str(&format!(
+                    "ACK [{}@{i}] {{{command}}} {}\n",
+                    ack.code, ack.message
+                ));
+                return Flow::Continue;
+            }
+            if with_ok {
+                out.push_str("list_OK\n");
+            }
+        }
+
+        out.push_str("OK\n");
+        Flow::Continue
+    }
+
+    /// Ends an idle if something the client waits on changed, or regardless when forced.
+    fn wake(&mut self, out: &mut String, force: bool) {
+        let Some(wanted) = &self.idling else {
+            return;
+        };
+
+        let matching: Vec<&'static str> = self
+            .changed
+            .iter()
+            .copied()
+            .filter(|subsystem| wanted.is_empty() || wanted.iter().any(|want| want == subsystem))
+            .collect();
+        if matching.is_empty() && !force {
+            return;
+        }
+
+        for subsystem in matching {
+            self.changed.remove(subsystem);
+            out.push_str(&format!("changed: {subsystem}\n"));
+        }
+        out.push_str("OK\n");
+        self.idling = None;
+    }
+
+    fn execute(&mut self, command: &str, args: &[String], out: &mut String) -> Result<(), Ack> {
+        match command {
+            "ping" => return Ok(()),
+            "password" => {
+                let given = args.first().ok_or_else(|| Ack::arg("Missing password"))?;
+                return match &self.shared.password {
+                    Some(password) if !constant_time_eq(password.as_bytes(), given.as_bytes()) => {
+                        Err(Ack::new(ACK_PASSWORD, "incorrect password"))
+                    }
+                    _ => {
+                        self.authorized = true;
+                        Ok(())
+                    }
+                };
+            }
+            "commands" => {
+                for command in COMMANDS {
+                    out.push_str(&format!("command: {command}\n"));
+                }
+                return Ok(());
+            }
+            "notcommands" => return Ok(()),
+            _ => {}
+        }
+
+        if !self.authorized {
+            return Err(Ack::new(
+                ACK_PERMISSION,
+                format!("you don't have permission for \"{command}\""),
+            ));
+        }
+
+        let player = &self.shared.player;
+        match command {
+            "status" => self.status(out),
+            "currentsong" => {
+                let state = self.state();
+                if let Some(song) = state.current_song()
+                    && let Some(entry) = state.current_entry
+                {
+                    write_song(out, song, Some((0, entry)));
+                }
+            }
+            "stats" => {
+                let state = self.state();
+                let playtime: Duration = state.songs.iter().map(|song| song.duration).sum();
+                let artists: BTreeSet<&str> = state
+                    .songs
+                    .iter()
+                    .map(|song| song.artist.as_str())
+                    .collect();
+                let albums: BTreeSet<&str> =
+                    state.songs.iter().map(|song| song.album.as_str()).collect();
+                out.push_str(&format!(
+                    "artists: {}\nalbums: {}\nsongs: {}\nuptime: {}\ndb_playtime: {}\n",
+                    artists.len(),
+                    albums.len(),
+                    state.songs.len(),
+                    self.shared.started.elapsed().as_secs(),
+                    playtime.as_secs(),
+                ));
+            }
+
+            "play" => match args.first() {
+                Some(position) => {
+                    let position = number(position)?;
+                    self.play_position(position)?;
+                }
+                None => player.send(PlayerCommand::Play),
+            },
+            "playid" => match args.first() {
+                Some(id) => {
+                    let position = self.position_of(number(id)?)?;
+                    self.play_position(position)?;
+                }
+                None => player.send(PlayerCommand::Play),
+            },
+            "pause" => match args.first().map(String::as_str) {
+                Some("1") => player.send(PlayerCommand::Pause),
+                Some("0") => player.send(PlayerCommand::Play),
+                Some(other) => return Err(Ack::arg(format!("Boolean (0/1) expected: {other}"))),
+                None => player.send(PlayerCommand::PlayPause),
+            },
+            "stop" => player.send(PlayerCommand::Stop),
+            "next" => player.send(PlayerCommand::Next),
+            "previous" => player.send(PlayerCommand::Previous),
+
+            "seekcur" => {
+                let time = args.first().ok_or_else(|| Ack::arg("Missing time"))?;
+                player.send(seek_command(time)?);
+            }
+            "seek" | "seekid" => {
+                let [target, time] = args else {
+                    return Err(Ack::arg("Expected a song and a time"));
+                };
+                let position = match command {
+                    "seek" => number(target)?,
+                    _ => self.position_of(number(target)?)?,
+                };
+                if position != 0 {
+                    self.play_position(position)?;
+                }
+                player.send(PlayerCommand::Seek(seconds(time)?));
+            }
+
+            "setvol" => {
+                let volume = args.first().ok_or_else(|| Ack::arg("Missing volume"))?;
+                let volume: u32 = number(volume)?;
+                if volume > 100 {
+                    return Err(Ack::arg("Invalid volume value"));
+                }
+                player.send(PlayerCommand::SetVolume(volume));
+            }
+            "volume" => {
+                let change = args.first().ok_or_else(|| Ack::arg("Missing change"))?;
+                let change: i64 = change
+                    .parse()
+                    .map_err(|_| Ack::arg(format!("Integer expected: {change}")))?;
+                // Relative, so changes sent before the state catches up all count
+                player.send(PlayerCommand::AdjustVolume(change.clamp(-100, 100) as i8));
+            }
+            "getvol" => out.push_str(&format!("volume: {}\n", self.state().volume)),
+            "random" => player.send(PlayerCommand::SetShuffle(flag(args)?)),
+            "repeat" => player.send(PlayerCommand::SetRepeat(flag(args)?)),
+            // Our queue is always consumed and never single, but clients toggle these
+            "single" | "consume" => {
+                flag(args)?;
+            }
+
+            "playlistinfo" | "playlistid" | "plchanges" => {
+                let state = self.state();
+                let entries = play_queue(&state);
+                let wanted = match (command, args.first()) {
+                    ("playlistinfo", Some(position)) => Some(number(position)?),
+                    ("playlistid", Some(id)) => {
+                        let id: EntryId = number(id)?;
+                        Some(
+                            entries
+                                .iter()
+                                .position(|&(entry, _)| entry == id)
+                                .ok_or_else(|| Ack::new(ACK_NO_EXIST, "No such song"))?,
+                        )
+                    }
+                    _ => None,
+                };
+
+                for (position, &(entry, id)) in entries.iter().enumerate() {
+                    if wanted.is_none_or(|wanted| wanted == position)
+                        && let Some(song) = state.songs.get(id)
+                    {
+                        write_song(out, song, Some((position, entry)));
+                    }
+                }
+            }
+
+            "add" | "addid" => {
+                let uri = args.first().ok_or_else(|| Ack::arg("Missing URI"))?;
+                let ids = self.resolve(uri);
+                if ids.is_empty() {
+                    return Err(Ack::new(ACK_NO_EXIST, "No such song"));
+                }
+
+                let position = match args.get(1) {
+                    Some(position) => number::<usize>(position)?.saturating_sub(1),
+                    None => usize::MAX,
+                };
+                // Picked here rather than by the player, so addid can answer with it
+                let entries: Vec<EntryId> = ids.iter().map(|_| EntryId::next()).collect();
+                for (offset, (&id, &entry)) in ids.iter().zip(&entries).enumerate() {
+                    player.send(PlayerCommand::EnqueueEntry(
+                        position.saturating_add(offset),
+                        id,
+                        entry,
+                    ));
+                }
+                if command == "addid" {
+                    out.push_str(&format!("Id: {}\n", entries[0]));
+                }
+            }
+            "delete" => {
+                let position = args.first().ok_or_else(|| Ack::arg("Missing position"))?;
+                self.delete(number(position)?)?;
+            }
+            "deleteid" => {
+                let id = args.first().ok_or_else(|| Ack::arg("Missing id"))?;
+                let position = self.position_of(number(id)?)?;
+                self.delete(position)?;
+            }
+            "clear" => {
+                let queued = self.state().queue.len();
+                for _ in 0..queued {
+                    player.send(PlayerCommand::Dequeue(0));
+                }
+                player.send(PlayerCommand::Stop);
+            }
+
+            "lsinfo" | "listallinfo" | "listall" => {
+                let state = self.state();
+                let only = args.first().filter(|uri| !uri.is_empty() && *uri != "/");
+                for song in state.songs.iter() {
+                    if only.is_some_and(|uri| *uri != file_name(song)) {
+                        continue;
+                    }
+                    match command {
+                        "listall" => out.push_str(&format!("file: {}\n", file_name(song))),
+                        _ => write_song(out, song, None),
+                    }
+                }
+            }
+            "search" | "find" => {
+                if args.len() == 1 && args[0].starts_with('(') {
+                    return Err(Ack::arg("Filter expressions aren't supported"));
+                }
+                if args.is_empty() || !args.len().is_multiple_of(2) {
+                    return Err(Ack::arg("Expected tag and value pairs"));
+                }
+
+                let exact = command == "find";
+                let state = self.state();
+                for song in state.songs.iter() {
+                    let matched = args
+                        .chunks_exact(2)
+                        .all(|pair| matches_tag(song, &pair[0], &pair[1], exact));
+                    if matched {
+                        write_song(out, song, None);
+                    }
+                }
+            }
+
+            "tagtypes" => {
+                for tag in TAG_TYPES {
+                    out.push_str(&format!("tagtype: {tag}\n"));
+                }
+            }
+            "urlhandlers" => {}
+
+            _ => {
+                return Err(Ack::new(
+                    ACK_UNKNOWN,
+                    format!("unknown command \"{command}\""),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, PlayerState> {
+        self.shared.state.lock().expect("Player state poisoned!")
+    }
+
+    fn status(&self, out: &mut String) {
+        let state = self.state();
+        let entries = play_queue(&state);
+        let flag = |on: bool| if on { 1 } else { 0 };
+
+        out.push_str(&format!(
+            "volume: {}\nrepeat: {}\nrandom: {}\nsingle: 0\nconsume: 1\nplaylist: {}\nplaylistlength: {}\nstate: {}\n",
+            state.volume,
+            flag(state.mode == PlaybackMode::Repeat),
+            flag(state.mode == PlaybackMode::Shuffled),
+            self.shared.version.load(Ordering::Relaxed),
+            entries.len(),
+            match state.status {
+                PlaybackStatus::Playing => "play",
+                PlaybackStatus::Paused => "pause",
+                PlaybackStatus::Stopped => "stop",
+            },
+        ));
+
+        if let Some(song) = state.current_song()
+            && let Some(entry) = state.current_entry
+        {
+            let elapsed = state.position().as_secs_f64();
+            out.push_str(&format!(
+                "song: 0\nsongid: {entry}\ntime: {}:{}\nelapsed: {elapsed:.3}\nduration: {:.3}\n",
+                elapsed as u64,
+                song.duration.as_secs(),
+                song.duration.as_secs_f64(),
+            ));
+        }
+        if let Some((next, _)) = entries.get(1) {
+            out.push_str(&format!("nextsong: 1\nnextsongid: {next}\n"));
+        }
+    }
+
+    fn play_position(&self, position: usize) -> Result<(), Ack> {
+        let state = self.state();
+        let player = &self.shared.player;
+
+        match position {
+            0 if let Some(current) = state.current => player.send(PlayerCommand::PlaySong(current)),
+            position if position <= state.queue.len() && position > 0 => {
+                player.send(PlayerCommand::SkipToQueued(position - 1))
+            }
+            _ => return Err(Ack::arg("Bad song index")),
+        }
+        Ok(())
+    }
+
+    fn delete(&self, position: usize) -> Result<(), Ack> {
+        let queued = self.state().queue.len();
+
+        match position {
+            // The current track can't leave the queue, so deleting it moves on instead
+            0 => self.shared.player.send(PlayerCommand::Next),
+            position if position <= queued => self
+                .shared
+                .player
+                .send(PlayerCommand::Dequeue(position - 1)),
+            _ => return Err(Ack::arg("Bad song index")),
+        }
+        Ok(())
+    }
+
+    fn position_of(&self, id: EntryId) -> Result<usize, Ack> {
+        play_queue(&self.state())
+            .iter()
+            .position(|&(entry, _)| entry == id)
+            .ok_or_else(|| Ack::new(ACK_NO_EXIST, "No such song"))
+    }
+
+    /// Songs behind a URI, everything for the root.
+    fn resolve(&self, uri: &str) -> Vec<SongId> {
+        let state = self.state();
+        let uri = uri.trim_start_matches("file://");
+
+        state
+            .songs
+            .iter()
+            .filter(|song| {
+                uri.is_empty() || uri == "/" || uri == file_name(song) || uri == song.path
+            })
+            .map(|song| song.id)
+            .collect()
+    }
+}
+
+/// The play queue as MPD sees it: the current track, then ours, each with its entry.
+fn play_queue(state: &PlayerState) -> Vec<(EntryId, SongId)> {
+    let queued = state
+        .queue_entries
+        .iter()
+        .copied()
+        .zip(state.queue.iter().copied());
+    state
+        .current_entry
+        .zip(state.current)
+        .into_iter()
+        .chain(queued)
+        .filter(|&(_, id)| state.songs.contains(id))
+        .collect()
+}
+
+fn subsystems(event: &PlayerEvent) -> Vec<&'static str> {
+    match event {
+        PlayerEvent::StatusChanged(_) | PlayerEvent::Seeked(_) => vec!["player"],
+        PlayerEvent::TrackChanged(_) => vec!["player", "playlist"],
+        PlayerEvent::QueueChanged => vec!["playlist"],
+        PlayerEvent::VolumeChanged(_) => vec!["mixer"],
+        PlayerEvent::ModeChanged(_) => vec!["options"],
+    }
+}
+
+fn read_lines(stream: TcpStream, tx: Sender<Input>) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        loop {
+            // One byte past the limit tells a line that's too long from one that just fits
+            let mut line = Vec::new();
+            match reader
+                .by_ref()
+                .take(MAX_LINE as u64 + 1)
+                .read_until(b'\n', &mut line)
+            {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+            } else if line.len() > MAX_LINE {
+                let _ = tx.send(Input::TooLong);
+                return;
+            }
+
+            let Ok(line) = String::from_utf8(line) else {
+                break;
+            };
+            if tx.send(Input::Line(line)).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(Input::Closed);
+    });
+}
+
+fn forward_events(player: &PlayerHandle, tx: Sender<Input>) {
+    let events = player.subscribe();
+    std::thread::spawn(move || {
+        for event in events {
+            if tx.send(Input::Event(event)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn file_name(song: &Song) -> &str {
+    Path::new(&song.path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&song.path)
+}
+
+/// `queued` is the song's position and entry when it's listed as part of the play queue.
+fn write_song(out: &mut String, song: &Song, queued: Option<(usize, EntryId)>) {
+    let modified: DateTime<Utc> = song.created.into();
+
+    out.push_str(&format!("file: {}\n", file_name(song)));
+    out.push_str(&format!(
+        "Last-Modified: {}\n",
+        modified.format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+    out.push_str(&format!(
+        "Time: {}\nduration: {:.3}\n",
+        song.duration.as_secs(),
+        song.duration.as_secs_f64()
+    ));
+    out.push_str(&format!(
+        "Title: {}\nArtist: {}\nAlbum: {}\n",
+        song.title, song.artist, song.album
+    ));
+
+    if let Some(album_artist) = &song.album_artist {
+        out.push_str(&format!("AlbumArtist: {album_artist}\n"));
+    }
+    for genre in &song.genres {
+        out.push_str(&format!("Genre: {genre}\n"));
+    }
+    for composer in &song.composers {
+        out.push_str(&format!("Composer: {composer}\n"));
+    }
+    if let Some(track) = song.track_number {
+        out.push_str(&format!("Track: {track}\n"));
+    }
+    if let Some(disc) = song.disc_number {
+        out.push_str(&format!("Disc: {disc}\n"));
+    }
+    if let Some(released) = &song.released {
+        out.push_str(&format!("Date: {released}\n"));
+    }
+
+    if let Some((position, entry)) = queued {
+        out.push_str(&format!("Pos: {position}\nId: {entry}\n"));
+    }
+}
+
+fn matches_tag(song: &Song, tag: &str, value: &str, exact: bool) -> bool {
+    let file = file_name(song);
+    let candidates: Vec<&str> = match tag.to_lowercase().as_str() {
+        "any" => vec![&song.title, &song.artist, &song.album, file],
+        "title" => vec![&song.title],
+        "artist" => vec![&song.artist],
+        "album" => vec![&song.album],
+        "albumartist" => song.album_artist.as_deref().into_iter().collect(),
+        "genre" => song.genres.iter().map(String::as_str).collect(),
+        "composer" => song.composers.iter().map(String::as_str).collect(),
+        "file" | "filename" => vec![file],
+        _ => Vec::new(),
+    };
+
+    if exact {
+        candidates.contains(&value)
+    } else {
+        let value = value.to_lowercase();
+        candidates
+            .iter()
+            .any(|candidate| candidate.to_lowercase().contains(&value))
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, Ack> {
+    text.parse()
+        .map_err(|_| Ack::arg(format!("Integer expected: {text}")))
+}
+
+fn flag(args: &[String]) -> Result<bool, Ack> {
+    match args.first().map(String::as_str) {
+        Some("1") => Ok(true),
+        Some("0") => Ok(false),
+        other => Err(Ack::arg(format!(
+            "Boolean (0/1) expected: {}",
+            other.unwrap_or("")
+        ))),
+    }
+}
+
+fn seconds(text: &str) -> Result<Duration, Ack> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| Ack::arg(format!("Number expected: {text}")))
+}
+
+/// `seekcur` takes a plain time, or a signed one to move relative to the position.
+fn seek_command(time: &str) -> Result<PlayerCommand, Ack> {
+    if let Some(offset) = time.strip_prefix('+') {
+        Ok(PlayerCommand::SeekForward(seconds(offset)?))
+    } else if let Some(offset) = time.strip_prefix('-') {
+        Ok(PlayerCommand::SeekBackward(seconds(offset)?))
+    } else {
+        Ok(PlayerCommand::Seek(seconds(time)?))
+    }
+}
+
+/// Splits a command line on spaces, keeping double-quoted arguments together.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => arg.push(escaped),
+                        None => return Err("Unterminated escape".to_string()),
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err("Missing closing '\"'".to_string()),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_split_on_spaces_and_quotes() {
+        assert_eq!(
+            tokenize(r#"search artist "Aurora \"AV\" Vale"  title x"#).unwrap(),
+            vec!["search", "artist", "Aurora \"AV\" Vale", "title", "x"]
+        );
+        assert_eq!(tokenize("  status ").unwrap(), vec!["status"]);
+        assert!(tokenize(r#"add "unterminated"#).is_err());
+    }
+
+    #[test]
+    fn seekcur_understands_relative_times() {
+        assert!(matches!(
+            seek_command("+5"),
+            Ok(PlayerCommand::SeekForward(offset)) if offset == Duration::from_secs(5)
+        ));
+        assert!(matches!(
+            seek_command("-2.5"),
+            Ok(PlayerCommand::SeekBackward(offset)) if offset == Duration::from_millis(2500)
+        ));
+        assert!(matches!(seek_command("30"), Ok(PlayerCommand::Seek(_))));
+        assert!(seek_command("soon").is_err());
+    }
+}
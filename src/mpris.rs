@@ -1,38 +1,319 @@
-use crate::player::PlayerState;
+use crate::app::{AppCommand, AppHandle, PlaylistsSnapshot};
+use sanctum::cache::{cover_path, save_cover};
+use sanctum::utils::{file_uri_to_path, fingerprint, path_to_file_uri};
+use sanctum::{
+    PlayCounts, PlaybackMode, PlayerCommand, PlayerEvent, PlayerHandle, PlayerState, Song,
+};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::{Duration, SystemTime};
 
 use mpris_server::{
-    LoopStatus, Metadata, PlaybackRate, PlaybackStatus, PlayerInterface, RootInterface, Time,
-    TrackId, Volume,
-    zbus::{Result, fdo},
+    LoopStatus, Metadata, PlaybackRate, PlaybackStatus, PlayerInterface, Playlist, PlaylistId,
+    PlaylistOrdering, PlaylistsInterface, PlaylistsProperty, Property, RootInterface, Server,
+    Signal, Time, TrackId, TrackListInterface, TrackListSignal, Uri, Volume,
+    zbus::{Result, fdo, zvariant::ObjectPath},
 };
 
-// #[derive(Debug)]
-// pub enum MprisState {
-//     Play,
-//     Pause,
-//     PlayPause,
-//     Next,
-//     Previous,
-//     Shuffle(bool),
-//     Loop,
-//     Volume(f64),
-//     Seek(i64),
-//     Stop,
-//     Position(i64),
-// }
+// What the decoders built into rodio can play
+const SUPPORTED_MIME_TYPES: &[&str] = &[
+    "audio/mpeg",
+    "audio/flac",
+    "audio/x-flac",
+    "audio/mp4",
+    "audio/aac",
+    "audio/x-m4a",
+    "audio/ogg",
+    "audio/vorbis",
+    "audio/wav",
+    "audio/x-wav",
+];
+
+/// Changes the bridge publishes, the player's own events plus the app's.
+#[derive(Debug)]
+pub enum MprisUpdate {
+    Player(PlayerEvent),
+    PlaylistsChanged,
+}
 
 pub struct MprisHandler {
     pub state: Arc<Mutex<PlayerState>>,
+    pub player: PlayerHandle,
+    pub app: AppHandle,
+    pub playlists: Arc<Mutex<PlaylistsSnapshot>>,
+    pub cache_dir: String,
+    /// Where covers extracted here go, so the UI picks them up too.
+    pub covers: Sender<(String, String, Option<[u8; 3]>)>,
+    pub ctx: egui::Context,
+}
+
+impl MprisHandler {
+    /// The current track followed by the queue, as the `TrackList` interface sees it.
+    fn track_list(&self) -> Vec<(TrackId, Song)> {
+        let state = self.state.lock().unwrap();
+
+        let songs: Vec<&Song> = state
+            .current
+            .into_iter()
+            .chain(state.queue.iter().copied())
+            .filter_map(|id| state.songs.get(id))
+            .collect();
+        let ids = track_ids(songs.iter().map(|song| song.path.as_str()));
+        ids.into_iter().zip(songs.into_iter().cloned()).collect()
+    }
+}
+
+/// Forwards player events to desktop widgets listening on the bus.
+///
+/// Runs on its own thread so MPRIS stays current while the window is hidden. The returned
+/// sender is for changes the player doesn't know about, like switching playlists.
+pub fn spawn_bridge(
+    mpris: Server<MprisHandler>,
+    events: Receiver<PlayerEvent>,
+) -> Sender<MprisUpdate> {
+    let (tx, rx) = channel();
+
+    let forward = tx.clone();
+    std::thread::Builder::new()
+        .name("sanctum-mpris-events".to_string())
+        .spawn(move || {
+            for event in events {
+                if forward.send(MprisUpdate::Player(event)).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("Can't start MPRIS thread!");
+
+    std::thread::Builder::new()
+        .name("sanctum-mpris".to_string())
+        .spawn(move || {
+            // The player already started before we subscribed, catch up on the current track
+            let current = mpris
+                .imp()
+                .state
+                .lock()
+                .ok()
+                .and_then(|state| state.current);
+            publish(&mpris, &PlayerEvent::TrackChanged(current));
+
+            for update in rx {
+                match update {
+                    MprisUpdate::Player(event) => publish(&mpris, &event),
+                    MprisUpdate::PlaylistsChanged => publish_playlists(&mpris),
+                }
+            }
+        })
+        .expect("Can't start MPRIS thread!");
+
+    tx
+}
+
+fn publish_playlists(mpris: &Server<MprisHandler>) {
+    let (count, active) = {
+        let snapshot = mpris.imp().playlists.lock().unwrap();
+        let active = snapshot
+            .playlists
+            .get(snapshot.active)
+            .map(|playlist| mpris_playlist(snapshot.active, playlist));
+        (snapshot.playlists.len() as u32, active)
+    };
+
+    let properties = [
+        PlaylistsProperty::PlaylistCount(count),
+        PlaylistsProperty::ActivePlaylist(active),
+    ];
+
+    if let Err(e) = futures::executor::block_on(mpris.playlists_properties_changed(properties)) {
+        eprintln!("Failed to publish playlists: {e}");
+    }
+}
+
+fn publish(mpris: &Server<MprisHandler>, event: &PlayerEvent) {
+    let result = match event {
+        PlayerEvent::Seeked(position) => {
+            let signal = Signal::Seeked {
+                position: to_time(*position),
+            };
+            futures::executor::block_on(mpris.emit(signal))
+        }
+        PlayerEvent::StatusChanged(status) => futures::executor::block_on(
+            mpris.properties_changed([Property::PlaybackStatus(*status)]),
+        ),
+        PlayerEvent::ModeChanged(mode) => futures::executor::block_on(mpris.properties_changed([
+            Property::Shuffle(*mode == PlaybackMode::Shuffled),
+            Property::LoopStatus(loop_status(*mode)),
+        ])),
+        PlayerEvent::VolumeChanged(volume) => futures::executor::block_on(
+            mpris.properties_changed([Property::Volume(to_volume(*volume))]),
+        ),
+        PlayerEvent::TrackChanged(_) => {
+            let Some(metadata) = update_metadata(mpris.imp()) else {
+                return;
+            };
+
+            futures::executor::block_on(mpris.properties_changed([Property::Metadata(metadata)]))
+                .and_then(|()| replace_track_list(mpris))
+        }
+        PlayerEvent::QueueChanged => replace_track_list(mpris),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Failed to publish {event:?}: {e}");
+    }
+}
+
+// Queue edits are small, resending the whole list keeps clients from drifting
+fn replace_track_list(mpris: &Server<MprisHandler>) -> Result<()> {
+    let tracks: Vec<TrackId> = mpris
+        .imp()
+        .track_list()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    let current_track = tracks.first().cloned().unwrap_or(TrackId::NO_TRACK);
+
+    futures::executor::block_on(mpris.track_list_emit(TrackListSignal::TrackListReplaced {
+        tracks,
+        current_track,
+    }))
+}
+
+/// Rebuilds the current track's metadata and stores it for the `Metadata` property.
+fn update_metadata(handler: &MprisHandler) -> Option<Metadata> {
+    let (song, plays) = {
+        let state = handler.state.lock().ok()?;
+        (state.current_song().cloned()?, state.play_counts.clone())
+    };
+
+    // The UI may not have rendered this cover yet, so extract it here if needed
+    if !std::path::Path::new(&cover_path(&handler.cache_dir, &song.album)).exists()
+        && let Some((image_path, accent)) = save_cover(&song.path, &song.album, &handler.cache_dir)
+    {
+        let _ = handler
+            .covers
+            .send((song.album.clone(), image_path, accent));
+        handler.ctx.request_repaint();
+    }
+
+    // The current track heads the track list, so it's always the song's first entry
+    let metadata = track_metadata(track_id(&song.path, 0), &song, &plays, &handler.cache_dir);
+
+    handler.state.lock().ok()?.metadata = metadata.clone();
+    Some(metadata)
+}
+
+fn loop_status(mode: PlaybackMode) -> LoopStatus {
+    match mode {
+        PlaybackMode::Repeat => LoopStatus::Track,
+        _ => LoopStatus::None,
+    }
+}
+
+// MPRIS volume is 0.0 to 1.0, the player's is a percentage
+fn to_volume(volume: u32) -> Volume {
+    volume as f64 / 100.
+}
+
+fn from_volume(volume: Volume) -> u32 {
+    (volume.clamp(0., 1.) * 100.).round() as u32
+}
+
+fn to_time(duration: Duration) -> Time {
+    Time::from_millis(duration.as_millis() as i64)
+}
+
+fn from_time(time: Time) -> Duration {
+    Duration::from_millis(time.as_millis().max(0) as u64)
+}
+
+/// Object path naming an entry of the track list, derived from its file so it survives
+/// restarts and rescans. `entry` counts the times the same file came before it in the list.
+pub fn track_id(path: &str, entry: usize) -> TrackId {
+    let id = fingerprint(&[path.as_bytes()]);
+    TrackId::try_from(format!("/org/sanctum/Player/Track/{id:016x}_{entry}"))
+        .unwrap_or(TrackId::NO_TRACK)
+}
+
+/// Ids for a track list, telling apart songs that are in it more than once.
+fn track_ids<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<TrackId> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    paths
+        .into_iter()
+        .map(|path| {
+            let entry = seen.entry(path).or_default();
+            *entry += 1;
+            track_id(path, *entry - 1)
+        })
+        .collect()
+}
+
+fn playlist_id(index: usize) -> PlaylistId {
+    ObjectPath::try_from(format!("/org/sanctum/Player/Playlist/{index}"))
+        .expect("Playlist path is always valid")
+        .into()
+}
+
+fn mpris_playlist(index: usize, playlist: &sanctum::Playlist) -> Playlist {
+    Playlist {
+        id: playlist_id(index),
+        name: playlist.name.clone(),
+        icon: String::new(),
+    }
+}
+
+// Playlists are folders, so their dates come from the filesystem
+fn folder_time(playlist: &sanctum::Playlist, ordering: PlaylistOrdering) -> SystemTime {
+    std::fs::metadata(&playlist.path)
+        .and_then(|metadata| match ordering {
+            PlaylistOrdering::CreationDate => metadata.created(),
+            _ => metadata.modified(),
+        })
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Metadata for a song at track list entry `id`, with cover art only if it's already cached.
+pub fn track_metadata(id: TrackId, song: &Song, plays: &PlayCounts, cache_dir: &str) -> Metadata {
+    let mut metadata = Metadata::builder()
+        .title(song.title.clone())
+        .artist(vec![song.artist.clone()])
+        .album(song.album.clone())
+        .length(to_time(song.duration))
+        .trackid(id)
+        .url(path_to_file_uri(&song.path))
+        .use_count(plays.get(&song.path).min(i32::MAX as u32) as i32)
+        .build();
+
+    metadata.set_album_artist(song.album_artist.clone().map(|artist| vec![artist]));
+    metadata.set_track_number(song.track_number.map(|number| number as i32));
+    metadata.set_disc_number(song.disc_number.map(|number| number as i32));
+    metadata.set_content_created(song.released.clone());
+    metadata.set_user_rating(song.rating);
+    if !song.genres.is_empty() {
+        metadata.set_genre(Some(song.genres.clone()));
+    }
+    if !song.composers.is_empty() {
+        metadata.set_composer(Some(song.composers.clone()));
+    }
+
+    let cover_art = cover_path(cache_dir, &song.album);
+    if std::path::Path::new(&cover_art).exists() {
+        metadata.set_art_url(Some(path_to_file_uri(&cover_art)));
+    }
+
+    metadata
 }
 
 impl RootInterface for MprisHandler {
     async fn raise(&self) -> fdo::Result<()> {
+        self.app.send(AppCommand::Raise);
         Ok(())
     }
 
     async fn quit(&self) -> fdo::Result<()> {
+        self.app.send(AppCommand::Quit);
         Ok(())
     }
 
@@ -57,7 +338,7 @@ impl RootInterface for MprisHandler {
     }
 
     async fn has_track_list(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(true)
     }
 
     async fn identity(&self) -> fdo::Result<String> {
@@ -69,89 +350,94 @@ impl RootInterface for MprisHandler {
     }
 
     async fn supported_uri_schemes(&self) -> fdo::Result<Vec<String>> {
-        Ok(vec![])
+        Ok(vec!["file".to_string()])
     }
 
     async fn supported_mime_types(&self) -> fdo::Result<Vec<String>> {
-        Ok(vec![])
+        Ok(SUPPORTED_MIME_TYPES
+            .iter()
+            .map(|mime| mime.to_string())
+            .collect())
     }
 }
 
 impl PlayerInterface for MprisHandler {
     async fn next(&self) -> fdo::Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.skip = true;
-        }
-
+        self.player.send(PlayerCommand::Next);
         Ok(())
     }
 
     async fn previous(&self) -> fdo::Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.previous = true;
-        }
+        self.player.send(PlayerCommand::Previous);
         Ok(())
     }
 
     async fn pause(&self) -> fdo::Result<()> {
-        println!("Triggered: Pause");
-        if let Ok(mut state) = self.state.lock() {
-            state.pause = true;
-        }
-
+        self.player.send(PlayerCommand::Pause);
         Ok(())
     }
 
     async fn play_pause(&self) -> fdo::Result<()> {
-        println!("Triggered: Play Pause");
-        if let Ok(mut state) = self.state.lock() {
-            state.play_pause = true;
-        }
-
+        self.player.send(PlayerCommand::PlayPause);
         Ok(())
     }
 
     async fn stop(&self) -> fdo::Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.stop = true;
-        }
-
+        self.player.send(PlayerCommand::Stop);
         Ok(())
     }
 
     async fn play(&self) -> fdo::Result<()> {
-        println!("Triggered: Play");
-        if let Ok(mut state) = self.state.lock() {
-            state.play = true;
-        }
+        self.player.send(PlayerCommand::Play);
         Ok(())
     }
 
     async fn shuffle(&self) -> fdo::Result<bool> {
-        Ok(false)
+        let state = self.state.lock().unwrap();
+        Ok(state.is_shuffled())
     }
 
     async fn set_shuffle(&self, shuffle: bool) -> Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.shuffle = shuffle;
-        }
-
+        self.player.send(PlayerCommand::SetShuffle(shuffle));
         Ok(())
     }
 
     async fn position(&self) -> fdo::Result<Time> {
         let state = self.state.lock().unwrap();
-        Ok(Time::from_secs(state.player_pos as i64))
+        Ok(to_time(state.position()))
     }
 
-    async fn set_position(&self, _track_id: TrackId, position: Time) -> fdo::Result<()> {
-        let mut state = self.state.lock().unwrap();
-        state.mpris_pos = position.as_secs() as u64;
+    async fn set_position(&self, track_id: TrackId, position: Time) -> fdo::Result<()> {
+        let (current, length) = {
+            let state = self.state.lock().unwrap();
+            let length = state.current_song().map(|song| song.duration);
+            let current = state
+                .current_song()
+                .map(|song| self::track_id(&song.path, 0));
+            (current, length)
+        };
+
+        // The spec asks us to ignore requests for another track or outside this one
+        if Some(track_id) != current || position.is_negative() || Some(from_time(position)) > length
+        {
+            return Ok(());
+        }
+
+        self.player.send(PlayerCommand::Seek(from_time(position)));
         Ok(())
     }
 
     async fn open_uri(&self, uri: String) -> fdo::Result<()> {
-        println!("OpenUri({uri})");
+        let Some(path) = file_uri_to_path(&uri) else {
+            return Err(fdo::Error::NotSupported(format!(
+                "Only local file:// URIs can be opened, not {uri}"
+            )));
+        };
+        if !path.is_file() {
+            return Err(fdo::Error::FileNotFound(path.display().to_string()));
+        }
+
+        self.app.send(AppCommand::OpenFile(path));
         Ok(())
     }
 
@@ -162,25 +448,12 @@ impl PlayerInterface for MprisHandler {
 
     async fn loop_status(&self) -> fdo::Result<LoopStatus> {
         let state = self.state.lock().unwrap();
-
-        let loop_status = state.repeat;
-        let loop_state: LoopStatus = if loop_status {
-            LoopStatus::Track
-        } else {
-            LoopStatus::None
-        };
-
-        Ok(loop_state)
+        Ok(loop_status(state.mode))
     }
 
     async fn set_loop_status(&self, loop_status: LoopStatus) -> Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            match loop_status {
-                LoopStatus::Track => state.repeat = true,
-                LoopStatus::None => state.repeat = false,
-                _ => state.repeat = false,
-            }
-        }
+        let repeat = matches!(loop_status, LoopStatus::Track);
+        self.player.send(PlayerCommand::SetRepeat(repeat));
         Ok(())
     }
 
@@ -201,15 +474,12 @@ impl PlayerInterface for MprisHandler {
 
     async fn volume(&self) -> fdo::Result<Volume> {
         let state = self.state.lock().unwrap();
-        let volume = state.volume as f64;
-        Ok(volume)
+        Ok(to_volume(state.volume))
     }
 
     async fn set_volume(&self, volume: Volume) -> Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.volume = volume as u64;
-        }
-
+        self.player
+            .send(PlayerCommand::SetVolume(from_volume(volume)));
         Ok(())
     }
 
@@ -230,10 +500,13 @@ impl PlayerInterface for MprisHandler {
     }
 
     async fn seek(&self, offset: Time) -> fdo::Result<()> {
-        if let Ok(mut state) = self.state.lock() {
-            state.mpris_pos = offset.as_secs() as u64;
-        }
+        let command = if offset.is_negative() {
+            PlayerCommand::SeekBackward(from_time(offset.abs()))
+        } else {
+            PlayerCommand::SeekForward(from_time(offset))
+        };
 
+        self.player.send(command);
         Ok(())
     }
 
@@ -253,3 +526,197 @@ impl PlayerInterface for MprisHandler {
         Ok(PlaybackRate::default())
     }
 }
+
+impl TrackListInterface for MprisHandler {
+    async fn get_tracks_metadata(&self, track_ids: Vec<TrackId>) -> fdo::Result<Vec<Metadata>> {
+        let tracks = self.track_list();
+        let plays = self.state.lock().unwrap().play_counts.clone();
+
+        Ok(track_ids
+            .iter()
+            .filter_map(|id| tracks.iter().find(|(track, _)| track == id))
+            .map(|(id, song)| track_metadata(id.clone(), song, &plays, &self.cache_dir))
+            .collect())
+    }
+
+    async fn add_track(
+        &self,
+        uri: Uri,
+        after_track: TrackId,
+        set_as_current: bool,
+    ) -> fdo::Result<()> {
+        let path = file_uri_to_path(&uri).map(|path| path.display().to_string());
+        let id = path.and_then(|path| self.state.lock().unwrap().songs.find(&path));
+        let Some(id) = id else {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{uri} is not in the library"
+            )));
+        };
+
+        // Position 0 of the track list is the current track, the queue starts after it
+        let position = if after_track == TrackId::NO_TRACK {
+            0
+        } else {
+            match self
+                .track_list()
+                .iter()
+                .position(|(id, _)| *id == after_track)
+            {
+                Some(position) => position,
+                None => return Ok(()),
+            }
+        };
+
+        self.player.send(PlayerCommand::EnqueueAt(position, id));
+        if set_as_current {
+            self.player.send(PlayerCommand::SkipToQueued(position));
+        }
+        Ok(())
+    }
+
+    async fn remove_track(&self, track_id: TrackId) -> fdo::Result<()> {
+        // The current track isn't part of the queue, so it can't be removed
+        let queued = self
+            .track_list()
+            .iter()
+            .skip(1)
+            .position(|(id, _)| *id == track_id);
+
+        if let Some(position) = queued {
+            self.player.send(PlayerCommand::Dequeue(position));
+        }
+        Ok(())
+    }
+
+    async fn go_to(&self, track_id: TrackId) -> fdo::Result<()> {
+        let position = self.track_list().iter().position(|(id, _)| *id == track_id);
+
+        match position {
+            Some(0) => self.player.send(PlayerCommand::Seek(Duration::ZERO)),
+            Some(position) => self.player.send(PlayerCommand::SkipToQueued(position - 1)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    async fn tracks(&self) -> fdo::Result<Vec<TrackId>> {
+        Ok(self.track_list().into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn can_edit_tracks(&self) -> fdo::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl PlaylistsInterface for MprisHandler {
+    async fn activate_playlist(&self, playlist_id: PlaylistId) -> fdo::Result<()> {
+        let index = {
+            let snapshot = self.playlists.lock().unwrap();
+            (0..snapshot.playlists.len()).find(|index| playlist_id == self::playlist_id(*index))
+        };
+
+        match index {
+            Some(index) => {
+                self.app.send(AppCommand::SwitchPlaylist(index));
+                Ok(())
+            }
+            None => Err(fdo::Error::InvalidArgs(format!(
+                "No playlist {}",
+                playlist_id.as_str()
+            ))),
+        }
+    }
+
+    async fn get_playlists(
+        &self,
+        index: u32,
+        max_count: u32,
+        order: PlaylistOrdering,
+        reverse_order: bool,
+    ) -> fdo::Result<Vec<Playlist>> {
+        let snapshot = self.playlists.lock().unwrap();
+        let mut playlists: Vec<(usize, &sanctum::Playlist)> =
+            snapshot.playlists.iter().enumerate().collect();
+
+        match order {
+            PlaylistOrdering::Alphabetical => {
+                playlists.sort_by_key(|(_, playlist)| playlist.name.to_lowercase())
+            }
+            PlaylistOrdering::CreationDate | PlaylistOrdering::ModifiedDate => {
+                playlists.sort_by_key(|(_, playlist)| folder_time(playlist, order))
+            }
+            // Config order is the user's order, and nothing records when a playlist was played
+            PlaylistOrdering::UserDefined | PlaylistOrdering::LastPlayDate => {}
+        }
+
+        if reverse_order {
+            playlists.reverse();
+        }
+
+        Ok(playlists
+            .into_iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .map(|(index, playlist)| mpris_playlist(index, playlist))
+            .collect())
+    }
+
+    async fn playlist_count(&self) -> fdo::Result<u32> {
+        Ok(self.playlists.lock().unwrap().playlists.len() as u32)
+    }
+
+    async fn orderings(&self) -> fdo::Result<Vec<PlaylistOrdering>> {
+        Ok(vec![
+            PlaylistOrdering::Alphabetical,
+            PlaylistOrdering::CreationDate,
+            PlaylistOrdering::ModifiedDate,
+            PlaylistOrdering::UserDefined,
+        ])
+    }
+
+    async fn active_playlist(&self) -> fdo::Result<Option<Playlist>> {
+        let snapshot = self.playlists.lock().unwrap();
+
+        Ok(snapshot
+            .playlists
+            .get(snapshot.active)
+            .map(|playlist| mpris_playlist(snapshot.active, playlist)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_songs_get_their_own_track_ids() {
+        let ids = track_ids([
+            "/music/a.flac",
+            "/music/b.flac",
+            "/music/a.flac",
+            "/music/a.flac",
+        ]);
+
+        assert_eq!(ids[0], track_id("/music/a.flac", 0));
+        assert_eq!(ids[2], track_id("/music/a.flac", 1));
+        // So going to or removing the second copy finds that one, not the first
+        let second = ids
+            .iter()
+            .position(|id| *id == track_id("/music/a.flac", 1));
+        assert_eq!(second, Some(2));
+        for (i, id) in ids.iter().enumerate() {
+            assert!(!ids[i + 1..].contains(id), "{id:?} is in the list twice");
+        }
+
+        // Entries keep their ids when the list is rebuilt the same way
+        assert_eq!(
+            ids,
+            track_ids([
+                "/music/a.flac",
+                "/music/b.flac",
+                "/music/a.flac",
+                "/music/a.flac"
+            ])
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::decoder::DecoderError;
+use rodio::source::{SeekError, UniformSourceIterator};
+
+use crate::songs::Song;
+use crate::visualizer::{AudioTap, Tapped};
+
+#[derive(Debug)]
+pub enum OutputError {
+    Stream(rodio::StreamError),
+    Open(std::io::Error),
+    Decode(DecoderError),
+    Seek(SeekError),
+    Empty,
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Stream(e) => write!(f, "Can't open audio device: {e}"),
+            OutputError::Open(e) => write!(f, "Can't open file: {e}"),
+            OutputError::Decode(e) => write!(f, "Can't decode file: {e}"),
+            OutputError::Seek(e) => write!(f, "Can't seek: {e}"),
+            OutputError::Empty => write!(f, "Nothing is loaded"),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+/// Where the player sends audio. Tracks are queued back to back, so appending the
+/// next one before the current one ends gives gapless playback.
+pub trait AudioOutput {
+    fn append(&mut self, song: &Song) -> Result<(), OutputError>;
+    fn play(&mut self);
+    fn pause(&mut self);
+    fn is_paused(&self) -> bool;
+    /// Drops every queued track and pauses.
+    fn clear(&mut self);
+    /// Number of tracks still queued, including the one playing.
+    fn len(&self) -> usize;
+    /// Position inside the track that is currently playing.
+    fn position(&self) -> Duration;
+    fn seek(&mut self, position: Duration) -> Result<(), OutputError>;
+    fn set_volume(&mut self, volume: f32);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub(crate) fn decode(path: &str) -> Result<rodio::Decoder<std::io::BufReader<File>>, OutputError> {
+    let file = File::open(path).map_err(OutputError::Open)?;
+    rodio::Decoder::try_from(std::io::BufReader::new(file)).map_err(OutputError::Decode)
+}
+
+/// Speakers, through the default rodio device.
+pub struct RodioOutput {
+    _stream_handle: rodio::OutputStream,
+    sink: rodio::Sink,
+    tap: Option<AudioTap>,
+}
+
+impl RodioOutput {
+    pub fn open_default() -> Result<Self, OutputError> {
+        let stream_handle =
+            rodio::OutputStreamBuilder::open_default_stream().map_err(OutputError::Stream)?;
+        let sink = rodio::Sink::connect_new(stream_handle.mixer());
+
+        Ok(Self {
+            _stream_handle: stream_handle,
+            sink,
+            tap: None,
+        })
+    }
+
+    /// Copies everything played from now on into `tap`, for the visualizer.
+    pub fn with_tap(mut self, tap: AudioTap) -> Self {
+        self.tap = Some(tap);
+        self
+    }
+}
+
+impl AudioOutput for RodioOutput {
+    fn append(&mut self, song: &Song) -> Result<(), OutputError> {
+        let decoder = decode(&song.path)?;
+
+        match &self.tap {
+            Some(tap) => self.sink.append(Tapped::new(decoder, tap.clone())),
+            None => self.sink.append(decoder),
+        }
+
+        Ok(())
+    }
+
+    fn play(&mut self) {
+        self.sink.play();
+    }
+
+    fn pause(&mut self) {
+        self.sink.pause();
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn clear(&mut self) {
+        self.sink.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.sink.len()
+    }
+
+    fn position(&self) -> Duration {
+        self.sink.get_pos()
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), OutputError> {
+        if self.sink.empty() {
+            return Err(OutputError::Empty);
+        }
+
+        self.sink.try_seek(position).map_err(OutputError::Seek)
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+}
+
+struct NullState {
+    tracks: VecDeque<Duration>,
+    position: Duration,
+    paused: bool,
+    volume: f32,
+    appended: Vec<String>,
+}
+
+/// Plays nothing and keeps a virtual clock that only moves when `advance` is called.
+///
+/// Clones share the same clock, so a test can keep one while the player owns another.
+#[derive(Clone)]
+pub struct NullOutput {
+    inner: Arc<Mutex<NullState>>,
+}
+
+impl Default for NullOutput {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(NullState {
+                tracks: VecDeque::new(),
+                position: Duration::ZERO,
+                paused: true,
+                volume: 1.,
+                appended: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl NullOutput {
+    fn state(&self) -> std::sync::MutexGuard<'_, NullState> {
+        self.inner.lock().expect("Null output poisoned!")
+    }
+
+    /// Moves the clock forward, rolling over into queued tracks like a real sink would.
+    pub fn advance(&self, elapsed: Duration) {
+        let mut state = self.state();
+
+        if state.paused {
+            return;
+        }
+
+        state.position += elapsed;
+
+        while let Some(&length) = state.tracks.front() {
+            if state.position < length {
+                break;
+            }
+
+            state.position -= length;
+            state.tracks.pop_front();
+        }
+
+        if state.tracks.is_empty() {
+            state.position = Duration::ZERO;
+        }
+    }
+
+    /// Paths of every track handed to the output, in order.
+    pub fn appended(&self) -> Vec<String> {
+        self.state().appended.clone()
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.state().volume
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn append(&mut self, song: &Song) -> Result<(), OutputError> {
+        let mut state = self.state();
+        state.tracks.push_back(song.duration);
+        state.appended.push(song.path.clone());
+        Ok(())
+    }
+
+    fn play(&mut self) {
+        self.state().paused = false;
+    }
+
+    fn pause(&mut self) {
+        self.state().paused = true;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    fn clear(&mut self) {
+        let mut state = self.state();
+        state.tracks.clear();
+        state.position = Duration::ZERO;
+        state.paused = true;
+    }
+
+    fn len(&self) -> usize {
+        self.state().tracks.len()
+    }
+
+    fn position(&self) -> Duration {
+        self.state().position
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), OutputError> {
+        let mut state = self.state();
+        let length = *state.tracks.front().ok_or(OutputError::Empty)?;
+        state.position = position.min(length);
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.state().volume = volume;
+    }
+}
+
+/// Minimal 16-bit PCM WAV writer, the header is kept valid after every write.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    channels: u16,
+    sample_rate: u32,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create(
+        path: impl AsRef<Path>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> std::io::Result<Self> {
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            channels,
+            sample_rate,
+            samples: 0,
+        };
+
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_len = self.samples * 2;
+        let block_align = self.channels * 2;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(b"RIFF")?;
+        self.file.write_all(&(36 + data_len).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        self.file.write_all(&16u32.to_le_bytes())?;
+        self.file.write_all(&1u16.to_le_bytes())?;
+        self.file.write_all(&self.channels.to_le_bytes())?;
+        self.file.write_all(&self.sample_rate.to_le_bytes())?;
+        self.file.write_all(&byte_rate.to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&16u16.to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&data_len.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+
+        Ok(())
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.samples += samples.len() as u32;
+        self.write_header()?;
+        self.file.flush()
+    }
+
+    /// Interleaved samples written so far.
+    pub fn len(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+}
+
+struct CaptureState {
+    writer: WavWriter,
+    tracks: VecDeque<Box<dyn Source + Send>>,
+    // Interleaved samples consumed from the front track
+    consumed: u64,
+    paused: bool,
+    volume: f32,
+}
+
+/// Renders into a WAV file instead of speakers, on the same manual clock as [`NullOutput`].
+///
+/// Silence is written whenever the queue runs dry while unpaused, so gaps between
+/// tracks show up in the capture.
+#[derive(Clone)]
+pub struct CaptureOutput {
+    inner: Arc<Mutex<CaptureState>>,
+}
+
+impl CaptureOutput {
+    pub fn create(
+        path: impl AsRef<Path>,
+        channels: u16,
+        sample_rate: u32,
+    ) -> std::io::Result<Self> {
+        let writer = WavWriter::create(path, channels, sample_rate)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(CaptureState {
+                writer,
+                tracks: VecDeque::new(),
+                consumed: 0,
+                paused: true,
+                volume: 1.,
+            })),
+        })
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, CaptureState> {
+        self.inner.lock().expect("Capture output poisoned!")
+    }
+
+    pub fn advance(&self, elapsed: Duration) -> std::io::Result<()> {
+        let mut state = self.state();
+
+        if state.paused {
+            return Ok(());
+        }
+
+        let channels = state.writer.channels as u64;
+        let frames = (elapsed.as_secs_f64() * state.writer.sample_rate as f64).round() as u64;
+        let mut needed = frames * channels;
+        let mut samples = Vec::with_capacity(needed as usize);
+
+        while needed > 0 {
+            let volume = state.volume;
+            let Some(track) = state.tracks.front_mut() else {
+                samples.resize(samples.len() + needed as usize, 0.);
+                break;
+            };
+
+            match track.next() {
+                Some(sample) => {
+                    samples.push(sample * volume);
+                    state.consumed += 1;
+                    needed -= 1;
+                }
+                None => {
+                    state.tracks.pop_front();
+                    state.consumed = 0;
+                }
+            }
+        }
+
+        state.writer.write(&samples)
+    }
+
+    /// Length of the capture so far.
+    pub fn captured(&self) -> Duration {
+        let state = self.state();
+        let frames = state.writer.len() / state.writer.channels as u32;
+        Duration::from_secs_f64(frames as f64 / state.writer.sample_rate as f64)
+    }
+}
+
+impl AudioOutput for CaptureOutput {
+    fn append(&mut self, song: &Song) -> Result<(), OutputError> {
+        let decoder = decode(&song.path)?;
+        let mut state = self.state();
+        let (channels, sample_rate) = (state.writer.channels, state.writer.sample_rate);
+
+        state.tracks.push_back(Box::new(UniformSourceIterator::new(
+            decoder,
+            channels,
+            sample_rate,
+        )));
+        Ok(())
+    }
+
+    fn play(&mut self) {
+        self.state().paused = false;
+    }
+
+    fn pause(&mut self) {
+        self.state().paused = true;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    fn clear(&mut self) {
+        let mut state = self.state();
+        state.tracks.clear();
+        state.consumed = 0;
+        state.paused = true;
+    }
+
+    fn len(&self) -> usize {
+        self.state().tracks.len()
+    }
+
+    fn position(&self) -> Duration {
+        let state = self.state();
+        let frames = state.consumed / state.writer.channels as u64;
+        Duration::from_secs_f64(frames as f64 / state.writer.sample_rate as f64)
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<(), OutputError> {
+        let mut state = self.state();
+        let frames = (position.as_secs_f64() * state.writer.sample_rate as f64) as u64;
+        let channels = state.writer.channels as u64;
+
+        let track = state.tracks.front_mut().ok_or(OutputError::Empty)?;
+        track.try_seek(position).map_err(OutputError::Seek)?;
+        state.consumed = frames * channels;
+
+        Ok(())
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.state().volume = volume;
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use rodio::Source;
+
+use crate::output::{OutputError, decode};
+use crate::songs::Song;
+use crate::utils::{fingerprint, write_atomic};
+
+/// Columns of peak data kept per track, whatever its length.
+pub const PEAK_BUCKETS: usize = 1024;
+
+/// Where a song's peaks live in the cache, keyed on its path, size and modification time
+/// so an edited file gets recomputed.
+pub fn peaks_path(cache_dir: &str, song_path: &str) -> String {
+    let (size, modified) = std::fs::metadata(song_path)
+        .map(|metadata| {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            (metadata.len(), modified.as_secs())
+        })
+        .unwrap_or_default();
+
+    let key = fingerprint(&[
+        song_path.as_bytes(),
+        &size.to_le_bytes(),
+        &modified.to_le_bytes(),
+    ]);
+
+    format!("{}/peaks/{:016x}.peaks", cache_dir, key)
+}
+
+/// Loudest absolute sample in each of `buckets` equal slices of the track, in 0..=1.
+///
+/// Decodes the whole file, so call it from a worker thread.
+pub fn compute_peaks(song: &Song, buckets: usize) -> Result<Vec<f32>, OutputError> {
+    let decoder = decode(&song.path)?;
+
+    // Reported lengths can be off, so gather 10ms slices first and spread them out after
+    let slice = (decoder.sample_rate() as usize * decoder.channels().max(1) as usize / 100).max(1);
+    let mut slices = Vec::new();
+
+    for (i, sample) in decoder.enumerate() {
+        if i % slice == 0 {
+            slices.push(0_f32);
+        }
+
+        if let Some(peak) = slices.last_mut() {
+            *peak = peak.max(sample.abs().min(1.));
+        }
+    }
+
+    if slices.is_empty() {
+        return Ok(vec![0.; buckets]);
+    }
+
+    Ok((0..buckets)
+        .map(|bucket| {
+            let from = bucket * slices.len() / buckets;
+            let to = ((bucket + 1) * slices.len() / buckets).max(from + 1);
+            slices[from..to.min(slices.len())]
+                .iter()
+                .copied()
+                .fold(0., f32::max)
+        })
+        .collect())
+}
+
+pub fn load_peaks(path: impl AsRef<Path>) -> Option<Vec<f32>> {
+    let bytes = std::fs::read(path).ok()?;
+
+    if bytes.is_empty() {
+        return None;
+    }
+
+    Some(bytes.iter().map(|byte| *byte as f32 / 255.).collect())
+}
+
+/// Stores peaks one byte per column, plenty for drawing.
+pub fn save_peaks(path: impl AsRef<Path>, peaks: &[f32]) -> std::io::Result<()> {
+    let path = path.as_ref();
+
+    let bytes: Vec<u8> = peaks
+        .iter()
+        .map(|peak| (peak.clamp(0., 1.) * 255.).round() as u8)
+        .collect();
+    write_atomic(path, &bytes)
+}
+
+/// Cached peaks for a song, computing and storing them on a miss.
+///
+/// Blocking, so call it from a worker thread.
+pub fn cached_peaks(song: &Song, cache_dir: &str) -> Option<Vec<f32>> {
+    let path = peaks_path(cache_dir, &song.path);
+
+    if let Some(peaks) = load_peaks(&path) {
+        return Some(peaks);
+    }
+
+    let peaks = match compute_peaks(song, PEAK_BUCKETS) {
+        Ok(peaks) => peaks,
+        Err(e) => {
+            eprintln!("Can't compute waveform for {:?}: {e}", song.path);
+            return None;
+        }
+    };
+
+    if let Err(e) = save_peaks(&path, &peaks) {
+        eprintln!("Can't cache waveform for {:?}: {e}", song.path);
+    }
+
+    Some(peaks)
+}
@@ -1,502 +1,1229 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
 
-use crate::Config;
-use crate::MprisHandler;
-use crate::cache::SancCache;
-use mpris_server::{Metadata, PlaybackStatus, Property, Server, Time, TrackId};
-use rand::Rng;
+use crate::events::{EventBus, PlayerCommand, PlayerEvent, PlayerHandle};
+use crate::library::{EntryId, Library, SongId};
+use crate::output::AudioOutput;
+use mpris_server::{Metadata, PlaybackStatus};
+use rand::seq::{IndexedRandom, SliceRandom};
 
-use crate::songs::Song;
+use crate::songs::{PlayCounts, Song};
 
 use std::time::{Duration, Instant};
 
+// How often the engine wakes up while playing to refresh the position snapshot
+const TICK: Duration = Duration::from_millis(250);
+
+// How close to the end of a track the next one gets queued up behind it
+const PRELOAD: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Deserialize, serde::Serialize)]
 pub enum PlaybackMode {
+    #[default]
     Normal,
     Shuffled,
     Repeat,
 }
 
+/// Snapshot of the engine that the UI and remotes read from.
+#[derive(Clone)]
 pub struct PlayerState {
     pub status: PlaybackStatus,
-    pub player_pos: u64,
-    pub mpris_pos: u64,
+    pub player_pos: Duration,
+    /// When `player_pos` was last written by the engine.
+    pub pos_updated: Instant,
     pub mode: PlaybackMode,
     pub metadata: Metadata,
-    pub skip: bool,
-    pub repeat: bool,
-    pub shuffle: bool,
-    pub previous: bool,
-    pub play: bool,
-    pub pause: bool,
-    pub play_pause: bool,
-    pub stop: bool,
-    pub volume: u64,
-    pub current_index: usize,
+    pub volume: u32,
+    /// Only empty along with the library.
+    pub current: Option<SongId>,
+    pub current_entry: Option<EntryId>,
+    pub queue: Vec<SongId>,
+    /// The entry of each queued song, in the same order.
+    pub queue_entries: Vec<EntryId>,
+    pub songs: Arc<Library>,
+    /// Shared rather than copied, the engine records into it as tracks start.
+    pub play_counts: PlayCounts,
+    /// Songs in the order shuffle walks through them, empty unless shuffled.
+    pub shuffle_order: Arc<Vec<SongId>>,
 }
 
 impl Default for PlayerState {
     fn default() -> Self {
         Self {
-            status: PlaybackStatus::Playing,
-            player_pos: 0,
-            mpris_pos: 0,
+            status: PlaybackStatus::Stopped,
+            player_pos: Duration::ZERO,
+            pos_updated: Instant::now(),
             mode: PlaybackMode::Normal,
             metadata: Metadata::new(),
-            skip: false,
-            shuffle: false,
-            repeat: false,
-            previous: false,
             volume: 100,
-            play: false,
-            pause: false,
-            play_pause: false,
-            stop: false,
-            current_index: 0,
+            current: None,
+            current_entry: None,
+            queue: Vec::new(),
+            queue_entries: Vec::new(),
+            songs: Arc::new(Library::default()),
+            play_counts: PlayCounts::default(),
+            shuffle_order: Arc::new(Vec::new()),
         }
     }
 }
+
+impl PlayerState {
+    pub fn current_song(&self) -> Option<&Song> {
+        self.songs.get(self.current?)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.status == PlaybackStatus::Playing
+    }
+
+    /// Position in the current track, extrapolated from the engine's last update so
+    /// it stays accurate between ticks.
+    pub fn position(&self) -> Duration {
+        if !self.is_playing() {
+            return self.player_pos;
+        }
+
+        let position = self.player_pos + self.pos_updated.elapsed();
+        match self.current_song() {
+            Some(song) => position.min(song.duration),
+            None => position,
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.status == PlaybackStatus::Stopped
+    }
+
+    pub fn is_repeat(&self) -> bool {
+        matches!(self.mode, PlaybackMode::Repeat)
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        matches!(self.mode, PlaybackMode::Shuffled)
+    }
+}
+
+/// Track appended behind the current one so the switch happens without a gap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Preload {
+    Loaded(SongId),
+    // Couldn't be decoded, it gets skipped once the current track ends
+    Failed(SongId),
+}
+
 pub struct Player {
-    pub _stream_handle: rodio::OutputStream,
-    pub sink: rodio::Sink,
-    pub track_pos: u64,
-    pub current_index: usize,
-    pub prev_index: usize,
-    pub queue: Vec<usize>,
-    pub skip: bool,
-    pub last_skip: Instant,
+    pub output: Box<dyn AudioOutput>,
+    pub songs: Arc<Library>,
+    pub track_pos: Duration,
+    /// Only empty along with the library.
+    pub current: Option<SongId>,
+    pub current_entry: Option<EntryId>,
+    pub queue: Vec<SongId>,
+    /// The entry of each queued song, in the same order.
+    pub queue_entries: Vec<EntryId>,
     pub mode: PlaybackMode,
+    pub status: PlaybackStatus,
+    pub volume: u32,
     pub state: Arc<Mutex<PlayerState>>,
+    /// Shuffle plays the whole list in this order before coming round again.
+    pub shuffle_order: Arc<Vec<SongId>>,
+    preload: Option<Preload>,
+    // The current track started over without changing, remotes see that as a seek to 0
+    restarted: bool,
+    // Whether the loaded track has counted as a play yet, that waits until it actually plays
+    counted: bool,
+    commands: Receiver<PlayerCommand>,
+    events: EventBus,
 }
 
 impl Player {
-    pub fn new(current_index: usize, state: Arc<Mutex<PlayerState>>) -> Self {
-        let stream_handle =
-            rodio::OutputStreamBuilder::open_default_stream().expect("Can't find speaker!");
-
-        let sink = rodio::Sink::connect_new(stream_handle.mixer());
-
-        Self {
-            _stream_handle: stream_handle,
-            sink,
-            current_index,
-            prev_index: 0,
-            track_pos: 0,
-            skip: false,
-            last_skip: Instant::now(),
+    /// Starts out on `current`, or the first song when that's not in the library.
+    pub fn new(
+        output: Box<dyn AudioOutput>,
+        current: Option<SongId>,
+        songs: Arc<Library>,
+        state: Arc<Mutex<PlayerState>>,
+    ) -> (Self, PlayerHandle) {
+        let (tx, rx) = channel();
+        let events = EventBus::default();
+        let handle = PlayerHandle::new(tx, events.clone());
+
+        let current = current
+            .filter(|&id| songs.contains(id))
+            .or_else(|| songs.first());
+
+        let player = Self {
+            output,
+            songs,
+            current,
+            current_entry: current.map(|_| EntryId::next()),
+            track_pos: Duration::ZERO,
             queue: Vec::new(),
+            queue_entries: Vec::new(),
             mode: PlaybackMode::Normal,
+            status: PlaybackStatus::Stopped,
+            volume: 100,
             state,
-        }
+            shuffle_order: Arc::new(Vec::new()),
+            preload: None,
+            restarted: false,
+            counted: false,
+            commands: rx,
+            events,
+        };
+
+        (player, handle)
     }
 
-    pub fn handle_keybinds(
-        &mut self,
-        i: &eframe::egui::InputState,
-        event: &egui::Event,
-        volume: &mut u32,
-        config: &mut Config,
-        songs: &[Song],
-    ) {
-        if let egui::Event::Key {
-            key: egui::Key::Space,
-            pressed: true,
-            repeat: false,
-            ..
-        } = event
-        {
-            self.playback();
-        }
-
-        if i.modifiers.ctrl {
-            if let egui::Event::Key {
-                key: egui::Key::ArrowLeft,
-                pressed: true,
-                repeat: false,
-                ..
-            } = event
-            {
-                self.previous(songs);
-            }
-
-            if let egui::Event::Key {
-                key: egui::Key::ArrowRight,
-                pressed: true,
-                repeat: false,
-                ..
-            } = event
-            {
-                self.skip(songs);
-            }
+    /// Starts the audio engine on its own thread, independent of the UI frame loop.
+    ///
+    /// The output is opened on that thread, so it lives and dies with the engine.
+    pub fn spawn(
+        open_output: impl FnOnce() -> Box<dyn AudioOutput> + Send + 'static,
+        current: Option<SongId>,
+        volume: u32,
+        songs: Arc<Library>,
+        state: Arc<Mutex<PlayerState>>,
+    ) -> PlayerHandle {
+        let (tx, rx) = channel();
+
+        std::thread::Builder::new()
+            .name("sanctum-player".to_string())
+            .spawn(move || {
+                let (mut player, handle) = Player::new(open_output(), current, songs, state);
+                player.volume(volume);
+                player.cue();
+                player.update_state();
+
+                if tx.send(handle).is_ok() {
+                    player.run();
+                }
+            })
+            .expect("Can't start player thread!");
 
-            if i.key_pressed(egui::Key::ArrowUp) {
-                *volume += 1;
-                self.volume(*volume);
-                config.set_volume(*volume);
-            }
+        rx.recv().expect("Player thread died during startup!")
+    }
 
-            if i.key_pressed(egui::Key::ArrowDown) {
-                *volume -= 1;
-                self.volume(*volume);
-                config.set_volume(*volume);
-            }
+    /// Engine loop: sleeps until a command arrives or the clock says something is due.
+    pub fn run(&mut self) {
+        loop {
+            let received = match self.next_wakeup() {
+                Some(timeout) => self.commands.recv_timeout(timeout),
+                None => self
+                    .commands
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
 
-            if let egui::Event::Key {
-                key: egui::Key::S,
-                pressed: true,
-                repeat: false,
-                ..
-            } = event
-            {
-                self.shuffle();
+            match received {
+                Ok(command) => self.apply(command),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
             }
 
-            if let egui::Event::Key {
-                key: egui::Key::R,
-                pressed: true,
-                repeat: false,
-                ..
-            } = event
-            {
-                self.repeat();
-            }
+            self.handle_commands();
+            self.process();
+            self.update_state();
         }
     }
-    pub fn set_index(&mut self, index: usize) {
-        self.current_index = index;
-        self.prev_index = index;
 
-        if self.sink.is_paused() {
-            self.resume();
+    /// How long the engine may sleep before the current track could end.
+    ///
+    /// Returns None when nothing is playing, the engine then only wakes up for commands.
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        if !self.is_playing() {
+            return None;
         }
 
-        if !self.idle() {
-            self.sink.skip_one();
-        }
+        let until_preload = self.remaining().saturating_sub(PRELOAD);
+        let until_end = self.remaining();
+        let due = if self.preload.is_none() && !until_preload.is_zero() {
+            until_preload
+        } else {
+            until_end
+        };
+
+        Some(due.clamp(Duration::from_millis(10), TICK))
     }
 
-    pub fn idle(&self) -> bool {
-        self.sink.empty() || self.sink.is_paused()
+    fn current_song(&self) -> Option<&Song> {
+        self.songs.get(self.current?)
     }
 
-    pub fn is_playing(&self) -> bool {
-        !self.sink.is_paused() && !self.sink.empty()
+    fn remaining(&self) -> Duration {
+        self.current_song()
+            .map(|song| song.duration)
+            .unwrap_or_default()
+            .saturating_sub(self.output.position())
     }
 
-    pub fn process(&mut self, songs: &[Song]) {
-        if songs.is_empty() {
-            return;
+    pub fn handle_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            self.apply(command);
         }
+    }
 
-        // Safety guard against out-of-bounds indices
-        if self.current_index >= songs.len() {
-            self.current_index = 0;
+    pub fn apply(&mut self, command: PlayerCommand) {
+        match command {
+            PlayerCommand::Play => self.resume(),
+            PlayerCommand::Pause => self.pause(),
+            PlayerCommand::PlayPause => self.playback(),
+            PlayerCommand::Stop => self.stop(),
+            PlayerCommand::Next => self.skip(),
+            PlayerCommand::Previous => self.previous(),
+            PlayerCommand::PlaySong(id) => self.play_song(id),
+            PlayerCommand::SetSongs(songs) => self.set_songs(songs),
+            PlayerCommand::AppendSongs(songs) => self.append_songs(songs),
+            PlayerCommand::Enqueue(id) => self.add_queue(id),
+            PlayerCommand::EnqueueAt(position, id) => {
+                self.insert_queue(position, id, EntryId::next())
+            }
+            PlayerCommand::EnqueueEntry(position, id, entry) => {
+                self.insert_queue(position, id, entry)
+            }
+            PlayerCommand::Dequeue(position) => self.remove_queue(position),
+            PlayerCommand::SkipToQueued(position) => self.skip_to_queued(position),
+            PlayerCommand::Seek(position) => self.seek_to(position),
+            PlayerCommand::SeekForward(offset) => self.seek_forward(offset),
+            PlayerCommand::SeekBackward(offset) => {
+                self.seek_to(self.output.position().saturating_sub(offset))
+            }
+            PlayerCommand::SetVolume(volume) => self.volume(volume),
+            PlayerCommand::AdjustVolume(step) => {
+                self.volume(self.volume.saturating_add_signed(step.into()))
+            }
+            PlayerCommand::SetShuffle(toggle) => self.set_shuffle(toggle),
+            PlayerCommand::RestoreShuffle(order) => self.restore_shuffle(order),
+            PlayerCommand::ToggleShuffle => self.shuffle(),
+            PlayerCommand::SetRepeat(toggle) => self.set_repeat(toggle),
+            PlayerCommand::ToggleRepeat => self.repeat(),
         }
+    }
 
-        self.track_pos = self.sink.get_pos().as_secs();
-
-        let manual_skip = self.skip;
-        let cooldown_done = self.last_skip.elapsed() > Duration::from_millis(300);
+    pub fn play_song(&mut self, id: SongId) {
+        if self.songs.contains(id) {
+            self.set_current(Some(id));
+            self.play();
+        }
+    }
 
-        // Audio sink is empty and cooldown has passed = track naturally finished
-        let track_finished = !manual_skip && self.sink.empty() && cooldown_done;
+    // A different song is a new entry, the same one going round again isn't
+    fn set_current(&mut self, id: Option<SongId>) {
+        if id != self.current {
+            self.current = id;
+            self.current_entry = id.map(|_| EntryId::next());
+        }
+    }
 
-        if track_finished || manual_skip {
-            self.skip = false;
-            self.last_skip = Instant::now();
+    // Queued songs the library no longer has go, along with their entries
+    fn retain_queue(&mut self) {
+        let songs = &self.songs;
+        (self.queue, self.queue_entries) = self
+            .queue
+            .iter()
+            .zip(&self.queue_entries)
+            .filter(|(id, _)| songs.contains(**id))
+            .map(|(&id, &entry)| (id, entry))
+            .unzip();
+    }
 
-            // 1. Advance the index based on queue or playback mode
-            if !self.queue.is_empty() {
-                let queued_idx = self.queue.remove(0);
-                self.current_index = if queued_idx < songs.len() {
-                    queued_idx
-                } else {
-                    0
-                };
-            } else {
-                match self.mode {
-                    PlaybackMode::Normal => {
-                        self.current_index = (self.current_index + 1) % songs.len();
-                    }
-                    PlaybackMode::Shuffled => {
-                        let mut rng = rand::rng();
-                        self.current_index = rng.random_range(0..songs.len());
-                    }
-                    PlaybackMode::Repeat => {
-                        // If manually skipped in repeat mode, move to next song;
-                        // if track finished naturally, current_index stays the same.
-                        if manual_skip {
-                            self.current_index = (self.current_index + 1) % songs.len();
-                        }
-                    }
-                }
-            }
+    /// Swaps in another library, e.g. after switching playlists. Whatever is current or
+    /// queued stays if the new library has it too.
+    pub fn set_songs(&mut self, songs: Arc<Library>) {
+        self.stop();
+        self.songs = songs;
+        self.retain_queue();
+        let current = self
+            .current
+            .filter(|&id| self.songs.contains(id))
+            .or_else(|| self.songs.first());
+        self.set_current(current);
+        if self.is_shuffled() {
+            self.reshuffle();
+        }
+    }
 
-            // 2. Always start playing the newly selected track!
-            self.play(songs);
-            self.prev_index = self.current_index;
+    /// Takes a library with more songs in it, without interrupting playback.
+    pub fn append_songs(&mut self, songs: Arc<Library>) {
+        // New songs join the end of the shuffle, the order so far stays
+        if self.is_shuffled() {
+            let mut order = self.shuffle_order.as_ref().clone();
+            order.extend(songs.ids().filter(|&id| !self.songs.contains(id)));
+            self.shuffle_order = Arc::new(order);
+        }
+        self.songs = songs;
+        self.retain_queue();
+        if self.current.is_none() {
+            self.set_current(self.songs.first());
         }
     }
 
-    pub fn update_state(
-        &mut self,
-        mpris: &Server<MprisHandler>,
-        cache: &SancCache,
-        songs: &[Song],
-    ) {
-        let song = &songs[self.current_index];
+    pub fn idle(&self) -> bool {
+        self.status != PlaybackStatus::Playing
+    }
 
-        let mut trigger_skip = false;
-        let mut trigger_previous = false;
-        let mut trigger_stop = false;
-        let mut trigger_play = false;
-        let mut trigger_pause = false;
-        let mut trigger_play_pause = false;
+    pub fn is_playing(&self) -> bool {
+        self.status == PlaybackStatus::Playing
+    }
 
-        let mut status_changed = false;
-        let mut metadata_changed = false;
-        let mut pos_changed = false;
-        let mut mpris_pos = 0;
+    pub fn process(&mut self) {
+        if self.songs.is_empty() {
+            return;
+        }
 
-        let mut new_status: PlaybackStatus = PlaybackStatus::Stopped;
-        let mut new_metadata: Metadata = Metadata::new();
+        if self.is_playing() {
+            if let Some(Preload::Loaded(next)) = self.preload
+                && self.output.len() == 1
+            {
+                // The preloaded track took over, the previous one ended without a gap
+                self.preload = None;
+                self.advance_to(next);
+            }
 
-        if let Ok(mut state) = self.state.lock() {
-            new_status = if self.is_playing() {
-                PlaybackStatus::Playing
-            } else if self.sink.empty() {
-                PlaybackStatus::Stopped
-            } else {
-                PlaybackStatus::Paused
-            };
+            if self.output.is_empty() {
+                // Output ran dry while we were meant to be playing = track naturally finished
+                if let Some(Preload::Loaded(skipped) | Preload::Failed(skipped)) =
+                    self.preload.take()
+                {
+                    self.advance_to(skipped);
+                }
 
-            if state.status != new_status {
-                state.status = new_status;
-                status_changed = true;
+                if let Some(next) = self.upcoming() {
+                    self.advance_to(next);
+                }
+                self.play();
+            } else if self.preload.is_none()
+                && self.remaining() <= PRELOAD
+                && let Some(next) = self.upcoming()
+                && let Some(song) = self.songs.get(next)
+            {
+                self.preload = Some(match self.output.append(song) {
+                    Ok(()) => Preload::Loaded(next),
+                    Err(e) => {
+                        eprintln!("Unable to preload {}: {e}", song.path);
+                        Preload::Failed(next)
+                    }
+                });
             }
+        }
 
-            if state.player_pos != self.track_pos {
-                state.player_pos = self.track_pos;
-                state.mpris_pos = self.track_pos;
-            }
+        self.track_pos = self.output.position();
+    }
+
+    /// Picks what plays after the current track, without consuming the queue yet.
+    fn upcoming(&self) -> Option<SongId> {
+        if let Some(&queued) = self.queue.first() {
+            return Some(queued);
+        }
 
-            if state.mpris_pos != state.player_pos {
-                mpris_pos = state.mpris_pos;
-                state.player_pos = state.mpris_pos;
-                pos_changed = true;
+        match self.mode {
+            PlaybackMode::Normal => self.songs.step(self.current?, 1),
+            PlaybackMode::Shuffled => self.shuffled(1),
+            PlaybackMode::Repeat => self.current,
+        }
+    }
+
+    /// The song `step` places away from the current one in the shuffle order.
+    fn shuffled(&self, step: isize) -> Option<SongId> {
+        let order = &self.shuffle_order;
+        match order.iter().position(|&id| Some(id) == self.current) {
+            Some(position) => {
+                let len = order.len() as isize;
+                Some(order[(position as isize + step).rem_euclid(len) as usize])
             }
+            // Only while the order is being rebuilt, never expected in practice
+            None => self.songs.choose(&mut rand::rng()).map(|song| song.id),
+        }
+    }
 
-            state.mode = if matches!(self.mode, PlaybackMode::Normal) {
-                PlaybackMode::Normal
-            } else if matches!(self.mode, PlaybackMode::Repeat) {
-                PlaybackMode::Repeat
-            } else {
-                PlaybackMode::Shuffled
-            };
+    /// A new random order, starting from the current track so everything else plays first.
+    fn reshuffle(&mut self) {
+        let mut order: Vec<SongId> = self
+            .songs
+            .ids()
+            .filter(|&id| Some(id) != self.current)
+            .collect();
+        order.shuffle(&mut rand::rng());
+        if let Some(current) = self.current {
+            order.insert(0, current);
+        }
+        self.shuffle_order = Arc::new(order);
+    }
+
+    /// Makes `id` current, popping it off the queue if that's where it came from.
+    fn advance_to(&mut self, id: SongId) {
+        if self.queue.first() == Some(&id) {
+            self.queue.remove(0);
+            self.current = Some(id);
+            self.current_entry = Some(self.queue_entries.remove(0));
+        } else {
+            self.set_current(Some(id));
+        }
 
-            new_metadata = Metadata::builder()
-                .title(song.title.clone())
-                .artist(vec![song.artist.clone()])
-                .album(song.album.clone())
-                .length(Time::from_secs(song.duration as i64))
-                .trackid(TrackId::NO_TRACK)
-                .build();
+        self.restarted = true;
+        self.counted = false;
+    }
 
-            if let Some(cover_art) = cache.covers.get(&song.album) {
-                new_metadata.set_art_url(Some(format!("file://{}", cover_art)));
-            }
+    /// Copies the player into the shared snapshot and broadcasts whatever changed.
+    pub fn update_state(&mut self) {
+        let mut events = Vec::new();
 
-            if state.metadata != new_metadata {
-                state.metadata = new_metadata.clone();
-                metadata_changed = true;
+        if let Ok(mut state) = self.state.lock() {
+            if !self.counted
+                && self.is_playing()
+                && let Some(song) = self.current_song()
+            {
+                state.play_counts.record(&song.path);
+                self.counted = true;
             }
 
-            if state.skip {
-                state.skip = false;
-                self.skip = true;
-                trigger_skip = true;
+            if state.status != self.status {
+                state.status = self.status;
+                events.push(PlayerEvent::StatusChanged(self.status));
             }
 
-            if state.previous {
-                state.previous = false;
-                self.skip = true;
-                trigger_previous = true;
+            state.current_entry = self.current_entry;
+            if state.current != self.current || !Arc::ptr_eq(&state.songs, &self.songs) {
+                state.current = self.current;
+                state.songs = Arc::clone(&self.songs);
+                events.push(PlayerEvent::TrackChanged(self.current));
+            } else if self.restarted {
+                events.push(PlayerEvent::Seeked(Duration::ZERO));
             }
 
-            if state.play && !self.is_playing() {
-                state.play = false;
-                trigger_play = true;
+            if state.mode != self.mode {
+                state.mode = self.mode;
+                events.push(PlayerEvent::ModeChanged(self.mode));
             }
-            if state.stop && !self.idle() {
-                state.stop = false;
-                trigger_stop = true;
+
+            if !Arc::ptr_eq(&state.shuffle_order, &self.shuffle_order) {
+                state.shuffle_order = Arc::clone(&self.shuffle_order);
             }
 
-            if state.pause && self.is_playing() {
-                state.pause = false;
-                trigger_pause = true;
+            if state.volume != self.volume {
+                state.volume = self.volume;
+                events.push(PlayerEvent::VolumeChanged(self.volume));
             }
 
-            if state.play_pause {
-                state.play_pause = false;
-                trigger_play_pause = true;
+            if state.queue_entries != self.queue_entries {
+                state.queue = self.queue.clone();
+                state.queue_entries = self.queue_entries.clone();
+                events.push(PlayerEvent::QueueChanged);
             }
-        }
 
-        if trigger_skip {
-            self.skip(songs);
+            state.player_pos = self.track_pos;
+            state.pos_updated = Instant::now();
         }
 
-        if trigger_previous {
-            self.previous(songs);
-        }
+        self.restarted = false;
 
-        if trigger_play {
-            self.resume();
+        for event in events {
+            self.events.emit(event);
         }
+    }
 
-        if trigger_pause {
+    pub fn playback(&mut self) {
+        if self.is_playing() {
             self.pause();
+        } else {
+            self.resume();
         }
+    }
 
-        if trigger_stop {
-            self.stop();
+    fn resume(&mut self) {
+        if self.output.is_empty() {
+            self.play();
+        } else {
+            self.output.play();
+            self.status = PlaybackStatus::Playing;
         }
+    }
 
-        if trigger_play_pause {
-            self.playback();
+    fn pause(&mut self) {
+        if !self.output.is_empty() {
+            self.output.pause();
+            self.status = PlaybackStatus::Paused;
         }
+    }
 
-        if status_changed {
-            futures::executor::block_on(
-                mpris.properties_changed([Property::PlaybackStatus(new_status)]),
-            )
-            .expect("Failed to update PlaybackStatus to Playing!");
-        }
+    fn stop(&mut self) {
+        self.track_pos = Duration::ZERO;
+        self.preload = None;
+        self.output.clear();
+        self.status = PlaybackStatus::Stopped;
+    }
 
-        if metadata_changed {
-            futures::executor::block_on(
-                mpris.properties_changed([Property::Metadata(new_metadata)]),
-            )
-            .expect("Failed to update PlaybackStatus to Playing!");
-        }
+    pub fn add_queue(&mut self, id: SongId) {
+        self.insert_queue(usize::MAX, id, EntryId::next());
+    }
 
-        if pos_changed {
-            self.seek_to(mpris_pos as i64);
+    pub fn insert_queue(&mut self, position: usize, id: SongId, entry: EntryId) {
+        if self.songs.contains(id) {
+            let position = position.min(self.queue.len());
+            self.queue.insert(position, id);
+            self.queue_entries.insert(position, entry);
         }
     }
 
-    pub fn playback(&mut self) {
-        if self.sink.is_paused() {
-            self.resume();
-        } else {
-            self.pause();
+    pub fn remove_queue(&mut self, position: usize) {
+        if position < self.queue.len() {
+            self.queue.remove(position);
+            self.queue_entries.remove(position);
         }
     }
 
-    fn resume(&mut self) {
-        self.sink.play();
+    pub fn skip_to_queued(&mut self, position: usize) {
+        if position < self.queue.len() {
+            self.queue.drain(..position);
+            self.queue_entries.drain(..position);
+            self.skip();
+        }
     }
 
-    fn pause(&mut self) {
-        self.sink.pause();
+    /// Loads the current track into the output but leaves it paused.
+    pub fn cue(&mut self) {
+        self.play();
+        self.pause();
     }
 
-    fn stop(&mut self) {
-        self.track_pos = 0;
-        self.sink.stop();
-    }
+    fn play(&mut self) {
+        self.preload = None;
 
-    pub fn add_queue(&mut self, index: usize) {
-        self.queue.push(index);
-    }
+        let Some(song) = self.current.and_then(|id| self.songs.get(id)) else {
+            return;
+        };
 
-    fn play(&mut self, songs: &[Song]) {
-        let song = &songs[self.current_index];
-        let song_path = &song.path;
+        // Drop whatever is still playing so the new track starts right away
+        self.output.clear();
 
-        let song_file = std::io::BufReader::new(
-            std::fs::File::open(song_path)
-                .unwrap_or_else(|_| panic!("Unable to open file: {song_path}")),
-        );
-        let decoder = rodio::Decoder::try_from(song_file).expect("Unable to make decoder!");
+        if let Err(e) = self.output.append(song) {
+            eprintln!("Unable to play {}: {e}", song.path);
+            self.stop();
+            return;
+        }
+
+        self.output.play();
 
-        self.sink.append(decoder);
+        self.track_pos = Duration::ZERO;
+        self.restarted = true;
+        self.counted = false;
+        self.status = PlaybackStatus::Playing;
     }
 
-    pub fn skip(&mut self, songs: &[Song]) {
-        if !self.queue.is_empty() {
-            self.current_index = self.queue.remove(0);
-        } else if (self.current_index + 1) >= songs.len() {
-            self.current_index = 0;
-        } else {
-            self.current_index += 1;
+    pub fn skip(&mut self) {
+        if self.songs.is_empty() {
+            return;
         }
 
-        self.skip = true;
+        // Repeat only holds on to a track that ends by itself
+        let next = if self.is_repeat() && self.queue.is_empty() {
+            self.current.and_then(|current| self.songs.step(current, 1))
+        } else {
+            self.upcoming()
+        };
+
+        if let Some(next) = next {
+            self.advance_to(next);
+        }
+        self.play();
     }
 
-    pub fn previous(&mut self, songs: &[Song]) {
-        if self.current_index == 0 {
-            self.current_index = songs.len() - 1;
-        } else {
-            self.current_index -= 1;
+    pub fn previous(&mut self) {
+        if self.songs.is_empty() {
+            return;
         }
 
-        self.skip = true;
+        let previous = if self.is_shuffled() {
+            self.shuffled(-1)
+        } else {
+            self.current
+                .and_then(|current| self.songs.step(current, -1))
+        };
+
+        if previous.is_some() {
+            self.set_current(previous);
+        }
+        self.play();
     }
 
     pub fn volume(&mut self, new_volume: u32) {
-        if new_volume as f32 != self.sink.volume() {
-            self.sink.set_volume(new_volume as f32 / 100.);
-        }
+        self.volume = new_volume.min(100);
+        self.output.set_volume(self.volume as f32 / 100.);
     }
 
     pub fn done(&self) -> bool {
-        self.sink.empty()
+        self.output.is_empty()
     }
 
     pub fn shuffle(&mut self) {
-        self.mode = if matches!(self.mode, PlaybackMode::Shuffled) {
-            PlaybackMode::Normal
-        } else {
-            PlaybackMode::Shuffled
-        }
+        self.set_shuffle(!self.is_shuffled());
     }
 
     pub fn set_shuffle(&mut self, toggle: bool) {
         if toggle {
+            if !self.is_shuffled() {
+                self.reshuffle();
+            }
             self.mode = PlaybackMode::Shuffled;
-        } else {
+        } else if self.is_shuffled() {
             self.mode = PlaybackMode::Normal;
+            self.shuffle_order = Arc::new(Vec::new());
         }
     }
 
-    pub fn repeat(&mut self) {
-        self.mode = if matches!(self.mode, PlaybackMode::Repeat) {
-            PlaybackMode::Normal
+    /// Shuffles in a saved order, or a new one if it doesn't fit the song list anymore.
+    pub fn restore_shuffle(&mut self, order: Vec<SongId>) {
+        let mut seen = HashSet::new();
+        let fits = order.len() == self.songs.len()
+            && order
+                .iter()
+                .all(|&id| self.songs.contains(id) && seen.insert(id));
+
+        if fits {
+            self.shuffle_order = Arc::new(order);
+            self.mode = PlaybackMode::Shuffled;
         } else {
-            PlaybackMode::Repeat
+            self.set_shuffle(true);
+        }
+    }
+
+    pub fn repeat(&mut self) {
+        self.set_repeat(!self.is_repeat());
+    }
+
+    pub fn set_repeat(&mut self, toggle: bool) {
+        if toggle {
+            self.mode = PlaybackMode::Repeat;
+            self.shuffle_order = Arc::new(Vec::new());
+        } else if self.is_repeat() {
+            self.mode = PlaybackMode::Normal;
         }
     }
 
     pub fn is_repeat(&self) -> bool {
-        let is_repeat = matches!(self.mode, PlaybackMode::Repeat);
-        is_repeat
+        matches!(self.mode, PlaybackMode::Repeat)
     }
 
     pub fn is_shuffled(&self) -> bool {
-        let is_shuffled = matches!(self.mode, PlaybackMode::Shuffled);
-        is_shuffled
+        matches!(self.mode, PlaybackMode::Shuffled)
+    }
+
+    pub fn seek_to(&mut self, position: Duration) {
+        match self.output.seek(position) {
+            Ok(()) => {
+                self.track_pos = position;
+                self.events.emit(PlayerEvent::Seeked(position));
+            }
+            Err(e) => eprintln!("Can't seek: {e}"),
+        }
+    }
+
+    pub fn seek_forward(&mut self, offset: Duration) {
+        let Some(song) = self.current_song() else {
+            return;
+        };
+
+        // `track_pos` is only as fresh as the last tick
+        let position = self.output.position() + offset;
+        if position >= song.duration {
+            self.skip();
+        } else {
+            self.seek_to(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{CaptureOutput, NullOutput, WavWriter};
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    fn song(path: &str, duration: u64) -> Song {
+        Song {
+            id: SongId::of(path),
+            title: path.to_string(),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            search_key: path.to_string(),
+            path: path.to_string(),
+            created_date: "just now".to_string(),
+            duration: Duration::from_secs(duration),
+            created: SystemTime::now(),
+            album_artist: None,
+            track_number: None,
+            disc_number: None,
+            genres: Vec::new(),
+            composers: Vec::new(),
+            released: None,
+            rating: None,
+        }
+    }
+
+    fn null_player(durations: &[u64]) -> (Player, NullOutput) {
+        let songs = durations
+            .iter()
+            .enumerate()
+            .map(|(index, duration)| song(&format!("song-{index}"), *duration))
+            .collect();
+        player_with(songs)
+    }
+
+    fn player_with(songs: Vec<Song>) -> (Player, NullOutput) {
+        let output = NullOutput::default();
+        let state = Arc::new(Mutex::new(PlayerState::default()));
+        let (player, _) = Player::new(
+            Box::new(output.clone()),
+            None,
+            Arc::new(Library::new(songs)),
+            state,
+        );
+
+        (player, output)
+    }
+
+    fn id(player: &Player, index: usize) -> SongId {
+        player.songs[index].id
+    }
+
+    /// Where the current song sits in the library.
+    fn playing(player: &Player) -> usize {
+        player.songs.position(player.current.unwrap()).unwrap()
     }
 
-    pub fn seek(&mut self) {
-        let new_pos = Duration::from_secs(self.track_pos);
-        self.sink.try_seek(new_pos).expect("Can't seek!");
+    fn positions(player: &Player, ids: &[SongId]) -> Vec<usize> {
+        ids.iter()
+            .map(|&id| player.songs.position(id).unwrap())
+            .collect()
     }
 
-    pub fn seek_to(&mut self, seconds: i64) {
-        let new_pos = Duration::from_secs(seconds as u64);
-        self.sink.try_seek(new_pos).expect("Can't seek!");
+    fn play_for(player: &mut Player, output: &NullOutput, elapsed: Duration) {
+        let mut left = elapsed;
+
+        while !left.is_zero() {
+            let step = left.min(TICK);
+            output.advance(step);
+            player.process();
+            left -= step;
+        }
+    }
+
+    #[test]
+    fn advances_in_order_when_tracks_end() {
+        let (mut player, output) = null_player(&[10, 10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+
+        play_for(&mut player, &output, Duration::from_secs(11));
+        assert_eq!(playing(&player), 1);
+
+        play_for(&mut player, &output, Duration::from_secs(20));
+        assert_eq!(playing(&player), 0);
+        assert!(player.is_playing());
+    }
+
+    #[test]
+    fn next_track_is_appended_before_current_ends() {
+        let (mut player, output) = null_player(&[10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+
+        play_for(&mut player, &output, Duration::from_secs(9));
+        assert_eq!(output.appended(), vec!["song-0", "song-1"]);
+        assert_eq!(playing(&player), 0);
+
+        // Rolled over inside the output, so no time was lost at the boundary
+        play_for(&mut player, &output, Duration::from_millis(1500));
+        assert_eq!(playing(&player), 1);
+        assert_eq!(output.position(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn queue_plays_before_the_next_track() {
+        let (mut player, output) = null_player(&[10, 10, 10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 3)));
+
+        play_for(&mut player, &output, Duration::from_secs(11));
+        assert_eq!(playing(&player), 3);
+        assert!(player.queue.is_empty());
+
+        player.apply(PlayerCommand::Enqueue(id(&player, 1)));
+        player.apply(PlayerCommand::Next);
+        assert_eq!(playing(&player), 1);
+    }
+
+    #[test]
+    fn queue_entries_stay_apart_and_follow_their_song() {
+        let (mut player, _) = null_player(&[10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 1)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 1)));
+
+        let entries = player.queue_entries.clone();
+        assert_eq!(entries.len(), 2);
+        assert_ne!(entries[0], entries[1]);
+        assert!(!entries.contains(&player.current_entry.unwrap()));
+
+        player.apply(PlayerCommand::Dequeue(0));
+        player.apply(PlayerCommand::Next);
+        assert_eq!(player.current_entry, Some(entries[1]));
+        assert!(player.queue_entries.is_empty());
+    }
+
+    #[test]
+    fn queue_can_be_edited_and_jumped_into() {
+        let (mut player, _) = null_player(&[10, 10, 10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 1)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 2)));
+        player.apply(PlayerCommand::EnqueueAt(0, id(&player, 3)));
+        assert_eq!(positions(&player, &player.queue), vec![3, 1, 2]);
+
+        player.apply(PlayerCommand::Dequeue(1));
+        assert_eq!(positions(&player, &player.queue), vec![3, 2]);
+
+        player.apply(PlayerCommand::SkipToQueued(1));
+        assert_eq!(playing(&player), 2);
+        assert!(player.queue.is_empty());
+    }
+
+    #[test]
+    fn repeat_replays_until_skipped() {
+        let (mut player, output) = null_player(&[10, 10]);
+        player.apply(PlayerCommand::SetRepeat(true));
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+
+        play_for(&mut player, &output, Duration::from_secs(25));
+        assert_eq!(playing(&player), 0);
+
+        player.apply(PlayerCommand::Next);
+        assert_eq!(playing(&player), 1);
+        assert!(player.is_repeat());
+    }
+
+    #[test]
+    fn shuffle_stays_in_bounds_and_toggles_off() {
+        let (mut player, output) = null_player(&[5, 5, 5]);
+        player.apply(PlayerCommand::ToggleShuffle);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+
+        for _ in 0..20 {
+            player.apply(PlayerCommand::Next);
+            assert!(playing(&player) < 3);
+        }
+
+        play_for(&mut player, &output, Duration::from_secs(30));
+        assert!(playing(&player) < 3);
+        assert!(player.is_playing());
+
+        player.apply(PlayerCommand::ToggleShuffle);
+        assert_eq!(player.mode, PlaybackMode::Normal);
+    }
+
+    #[test]
+    fn shuffle_plays_everything_once_per_round() {
+        let (mut player, _) = null_player(&[5, 5, 5, 5, 5]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 2)));
+        player.apply(PlayerCommand::SetShuffle(true));
+        assert_eq!(player.shuffle_order[0], id(&player, 2));
+
+        let mut played = vec![playing(&player)];
+        for _ in 0..4 {
+            player.apply(PlayerCommand::Next);
+            played.push(playing(&player));
+        }
+        assert_eq!(played, positions(&player, &player.shuffle_order));
+
+        // Round again, then back along the same order
+        player.apply(PlayerCommand::Next);
+        assert_eq!(playing(&player), 2);
+        player.apply(PlayerCommand::Previous);
+        assert_eq!(playing(&player), played[4]);
+    }
+
+    #[test]
+    fn saved_shuffle_orders_come_back_if_they_still_fit() {
+        let (mut player, _) = null_player(&[5, 5, 5]);
+        let saved = vec![id(&player, 2), id(&player, 0), id(&player, 1)];
+        player.apply(PlayerCommand::RestoreShuffle(saved.clone()));
+        assert!(player.is_shuffled());
+        assert_eq!(*player.shuffle_order, saved);
+
+        player.apply(PlayerCommand::SetShuffle(false));
+        assert!(player.shuffle_order.is_empty());
+
+        // Out of range or repeated entries mean the list changed, so it shuffles afresh
+        let elsewhere = SongId::of("elsewhere");
+        player.apply(PlayerCommand::RestoreShuffle(vec![
+            id(&player, 0),
+            id(&player, 0),
+            elsewhere,
+        ]));
+        assert!(player.is_shuffled());
+        let mut order = positions(&player, &player.shuffle_order);
+        order.sort();
+        assert_eq!(order, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn songs_are_followed_into_a_new_library() {
+        let (mut player, _) = null_player(&[10, 10, 10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 1)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 3)));
+        player.apply(PlayerCommand::Enqueue(id(&player, 0)));
+        let (current, queued) = (player.current, player.queue[0]);
+
+        // Rescanned in another order with one of the queued songs gone
+        let mut songs: Vec<Song> = player.songs.to_vec();
+        songs.reverse();
+        songs.retain(|song| song.path != "song-0");
+        player.apply(PlayerCommand::SetSongs(Arc::new(Library::new(songs))));
+
+        assert_eq!(player.current, current);
+        assert_eq!(player.queue, vec![queued]);
+        assert_eq!(playing(&player), 2);
+    }
+
+    #[test]
+    fn previous_wraps_to_the_last_track() {
+        let (mut player, _) = null_player(&[10, 10, 10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.apply(PlayerCommand::Previous);
+
+        assert_eq!(playing(&player), 2);
+    }
+
+    #[test]
+    fn seeking_moves_the_output_and_broadcasts() {
+        let (mut player, output) = null_player(&[60]);
+        let events = player.events.subscribe();
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.apply(PlayerCommand::Seek(Duration::from_secs(42)));
+
+        assert_eq!(output.position(), Duration::from_secs(42));
+        assert!(
+            events
+                .try_iter()
+                .any(|event| event == PlayerEvent::Seeked(Duration::from_secs(42)))
+        );
+
+        play_for(&mut player, &output, Duration::from_secs(1));
+        assert_eq!(player.track_pos, Duration::from_secs(43));
+    }
+
+    #[test]
+    fn relative_seeks_clamp_and_run_into_the_next_track() {
+        let (mut player, output) = null_player(&[60, 60]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        play_for(&mut player, &output, Duration::from_secs(20));
+
+        player.apply(PlayerCommand::SeekForward(Duration::from_secs(10)));
+        assert_eq!(output.position(), Duration::from_secs(30));
+
+        player.apply(PlayerCommand::SeekBackward(Duration::from_secs(45)));
+        assert_eq!(output.position(), Duration::ZERO);
+
+        player.apply(PlayerCommand::SeekForward(Duration::from_secs(90)));
+        assert_eq!(playing(&player), 1);
+        assert_eq!(output.position(), Duration::ZERO);
+    }
+
+    #[test]
+    fn relative_seeks_start_from_where_playback_is_now() {
+        let (mut player, output) = null_player(&[60]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        play_for(&mut player, &output, Duration::from_secs(20));
+
+        // Played on since the last tick, which the player hasn't seen yet
+        output.advance(Duration::from_millis(200));
+        player.apply(PlayerCommand::SeekForward(Duration::from_secs(5)));
+        assert_eq!(output.position(), Duration::from_millis(25_200));
+
+        output.advance(Duration::from_millis(200));
+        player.apply(PlayerCommand::SeekBackward(Duration::from_secs(5)));
+        assert_eq!(output.position(), Duration::from_millis(20_400));
+    }
+
+    #[test]
+    fn the_last_fraction_of_a_second_still_plays() {
+        // Whole seconds would make these 10 and 5
+        let mut first = song("long-0", 0);
+        first.duration = Duration::from_millis(10_600);
+        let mut second = song("long-1", 0);
+        second.duration = Duration::from_millis(5_900);
+        let (mut player, output) = player_with(vec![first, second]);
+
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        play_for(&mut player, &output, Duration::from_millis(10_200));
+        assert_eq!(playing(&player), 0);
+        assert!(player.remaining() > Duration::from_millis(300));
+
+        player.apply(PlayerCommand::SeekForward(Duration::from_millis(300)));
+        assert_eq!(playing(&player), 0);
+        assert_eq!(output.position(), Duration::from_millis(10_500));
+
+        player.update_state();
+        let state = player.state.lock().unwrap().clone();
+        let position = state.position();
+        assert!(position >= Duration::from_millis(10_500));
+        assert!(position < Duration::from_millis(10_600));
+    }
+
+    #[test]
+    fn position_keeps_sub_second_precision() {
+        let (mut player, output) = null_player(&[10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        play_for(&mut player, &output, Duration::from_millis(1750));
+        player.update_state();
+
+        assert_eq!(player.track_pos, Duration::from_millis(1750));
+        assert_eq!(
+            player.state.lock().unwrap().player_pos,
+            Duration::from_millis(1750)
+        );
+    }
+
+    #[test]
+    fn repeating_a_track_counts_as_a_seek() {
+        let (mut player, output) = null_player(&[10]);
+        player.apply(PlayerCommand::SetRepeat(true));
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        player.update_state();
+
+        let events = player.events.subscribe();
+        play_for(&mut player, &output, Duration::from_secs(11));
+        player.update_state();
+
+        let events: Vec<PlayerEvent> = events.try_iter().collect();
+        assert!(events.contains(&PlayerEvent::Seeked(Duration::ZERO)));
+        assert!(!events.contains(&PlayerEvent::TrackChanged(Some(id(&player, 0)))));
+    }
+
+    #[test]
+    fn plays_count_once_the_track_actually_plays() {
+        let (mut player, output) = null_player(&[10, 10]);
+        let plays = player.state.lock().unwrap().play_counts.clone();
+
+        player.cue();
+        player.update_state();
+        assert_eq!(plays.get("song-0"), 0);
+
+        player.apply(PlayerCommand::Play);
+        player.update_state();
+        player.apply(PlayerCommand::Pause);
+        player.apply(PlayerCommand::Play);
+        player.update_state();
+        assert_eq!(plays.get("song-0"), 1);
+
+        play_for(&mut player, &output, Duration::from_secs(11));
+        player.update_state();
+        assert_eq!(plays.get("song-1"), 1);
+    }
+
+    #[test]
+    fn pause_holds_the_clock() {
+        let (mut player, output) = null_player(&[10]);
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+        play_for(&mut player, &output, Duration::from_secs(3));
+        player.apply(PlayerCommand::Pause);
+        play_for(&mut player, &output, Duration::from_secs(30));
+
+        assert_eq!(player.status, PlaybackStatus::Paused);
+        assert_eq!(output.position(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn state_snapshot_reports_changes() {
+        let (mut player, _) = null_player(&[10, 10]);
+        let events = player.events.subscribe();
+        player.apply(PlayerCommand::PlaySong(id(&player, 1)));
+        player.apply(PlayerCommand::SetVolume(40));
+        player.apply(PlayerCommand::Enqueue(id(&player, 0)));
+        player.update_state();
+
+        let state = player.state.lock().unwrap();
+        assert_eq!(state.current, Some(id(&player, 1)));
+        assert_eq!(state.volume, 40);
+        assert_eq!(state.queue, vec![id(&player, 0)]);
+        assert!(state.is_playing());
+
+        let events: Vec<PlayerEvent> = events.try_iter().collect();
+        assert!(events.contains(&PlayerEvent::TrackChanged(Some(id(&player, 1)))));
+        assert!(events.contains(&PlayerEvent::VolumeChanged(40)));
+        assert!(events.contains(&PlayerEvent::QueueChanged));
+    }
+
+    #[test]
+    fn volume_steps_build_on_each_other() {
+        let (mut player, output) = null_player(&[10]);
+        player.apply(PlayerCommand::SetVolume(50));
+
+        // Sent faster than state updates go out, each one still counts
+        for _ in 0..3 {
+            player.apply(PlayerCommand::AdjustVolume(1));
+        }
+        assert_eq!(player.volume, 53);
+        assert_eq!(output.volume(), 0.53);
+
+        player.apply(PlayerCommand::AdjustVolume(-60));
+        assert_eq!(player.volume, 0);
+        player.apply(PlayerCommand::SetVolume(99));
+        player.apply(PlayerCommand::AdjustVolume(5));
+        assert_eq!(player.volume, 100);
+    }
+
+    fn fixture(name: &str, seconds: u32) -> PathBuf {
+        let dir = std::env::temp_dir().join("sanctum-player-tests");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        let mut writer = WavWriter::create(&path, 1, 8000).unwrap();
+        writer.write(&vec![0.5; 8000 * seconds as usize]).unwrap();
+
+        path
+    }
+
+    fn captured_samples(path: &PathBuf) -> Vec<i16> {
+        let bytes = std::fs::read(path).unwrap();
+        bytes[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn capture_has_no_gap_between_tracks() {
+        let first = fixture("gapless-a.wav", 1);
+        let second = fixture("gapless-b.wav", 1);
+        let capture_path = std::env::temp_dir().join("sanctum-player-tests/gapless-out.wav");
+
+        let output = CaptureOutput::create(&capture_path, 1, 8000).unwrap();
+        let songs = vec![
+            song(&first.display().to_string(), 1),
+            song(&second.display().to_string(), 1),
+        ];
+        let state = Arc::new(Mutex::new(PlayerState::default()));
+        let (mut player, _) = Player::new(
+            Box::new(output.clone()),
+            None,
+            Arc::new(Library::new(songs)),
+            state,
+        );
+        player.apply(PlayerCommand::PlaySong(id(&player, 0)));
+
+        for _ in 0..15 {
+            player.process();
+            output.advance(Duration::from_millis(100)).unwrap();
+        }
+
+        assert_eq!(output.captured(), Duration::from_millis(1500));
+        assert_eq!(playing(&player), 1);
+
+        let samples = captured_samples(&capture_path);
+        assert_eq!(samples.len(), 12000);
+        assert!(samples.iter().all(|sample| *sample > 16000));
     }
 }
@@ -1,4 +1,6 @@
-use crate::songs::Song;
+use std::path::Path;
+
+use crate::library::{Library, SongId};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub enum Sort {
@@ -10,41 +12,43 @@ pub enum Sort {
     Time { reverse: bool },
 }
 
+impl Default for Sort {
+    fn default() -> Self {
+        Self::Track { reverse: false }
+    }
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Playlist {
     pub name: String,
     pub path: String,
+    #[serde(default)]
     pub sort_order: Sort,
 }
 
-pub fn sort_songs(playlist: Playlist, song_view: &mut [usize], songs: &[Song]) {
+pub fn sort_songs(playlist: Playlist, song_view: &mut [SongId], library: &Library) {
     let sort = playlist.sort_order();
     song_view.sort_by(|&view, &song| {
+        // Folder order is what track order means here
+        let position = |id| library.position(id).unwrap_or(usize::MAX);
+        let (Some(a), Some(b)) = (library.get(view), library.get(song)) else {
+            return position(view).cmp(&position(song));
+        };
+
         let (order, reverse) = match sort {
-            Sort::Track { reverse } => (view.cmp(&song), reverse),
-            Sort::Title { reverse } => (
-                songs[view]
-                    .title
-                    .to_lowercase()
-                    .cmp(&songs[song].title.to_lowercase()),
-                reverse,
-            ),
+            Sort::Track { reverse } => (position(view).cmp(&position(song)), reverse),
+            Sort::Title { reverse } => {
+                (a.title.to_lowercase().cmp(&b.title.to_lowercase()), reverse)
+            }
             Sort::Artist { reverse } => (
-                songs[view]
-                    .artist
-                    .to_lowercase()
-                    .cmp(&songs[song].artist.to_lowercase()),
-                reverse,
-            ),
-            Sort::Album { reverse } => (
-                songs[view]
-                    .album
-                    .to_lowercase()
-                    .cmp(&songs[song].album.to_lowercase()),
+                a.artist.to_lowercase().cmp(&b.artist.to_lowercase()),
                 reverse,
             ),
-            Sort::Date { reverse } => (songs[view].created.cmp(&songs[song].created), reverse),
-            Sort::Time { reverse } => (songs[view].duration.cmp(&songs[song].duration), reverse),
+            Sort::Album { reverse } => {
+                (a.album.to_lowercase().cmp(&b.album.to_lowercase()), reverse)
+            }
+            Sort::Date { reverse } => (a.created.cmp(&b.created), reverse),
+            Sort::Time { reverse } => (a.duration.cmp(&b.duration), reverse),
         };
 
         if reverse { order.reverse() } else { order }
@@ -52,6 +56,20 @@ pub fn sort_songs(playlist: Playlist, song_view: &mut [usize], songs: &[Song]) {
 }
 
 impl Playlist {
+    /// A playlist for a music folder, named after it and in track order.
+    pub fn from_folder(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        Self {
+            name,
+            path: path.display().to_string(),
+            sort_order: Sort::default(),
+        }
+    }
+
     pub fn set_sort(&mut self, sort_order: Sort) {
         self.sort_order = sort_order
     }
@@ -1,3 +1,4 @@
+use crate::library::SongId;
 use crate::songs::Song;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
@@ -5,12 +6,12 @@ use fuzzy_matcher::skim::SkimMatcherV2;
 pub struct Search {
     pub modal: bool,
     pub matcher: SkimMatcherV2,
-    pub results: Vec<(usize, i64)>,
+    pub results: Vec<(SongId, i64)>,
     pub query: String,
 }
 
 impl Search {
-    pub fn search_query(&mut self, songs: &Vec<Song>) -> Vec<(usize, i64)> {
+    pub fn search_query(&mut self, songs: &[Song]) -> Vec<(SongId, i64)> {
         let query = self.query.trim();
 
         if query.is_empty() || query.len() < 2 {
@@ -19,13 +20,13 @@ impl Search {
 
         let mut results = Vec::new();
 
-        for (index, song) in songs.iter().enumerate() {
+        for song in songs {
             if let Some(score) = self.matcher.fuzzy_match(&song.search_key, query) {
-                results.push((index, score));
+                results.push((song.id, score));
             }
         }
 
-        results.sort_by(|a, b| b.1.cmp(&a.1));
+        results.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
 
         if let Some((_, best)) = results.first() {
             let cutoff = best / 2;
@@ -35,7 +36,7 @@ impl Search {
         results
     }
 
-    pub fn handle_query(&mut self, songs: &Vec<Song>) {
+    pub fn handle_query(&mut self, songs: &[Song]) {
         self.results = self.search_query(songs);
     }
 
@@ -0,0 +1,368 @@
+//! What was playing when Sanctum last saved, kept apart from the config since it changes
+//! all the time and losing it is harmless.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, state_dir};
+use crate::events::{PlayerCommand, PlayerEvent};
+use crate::library::{Library, SongId};
+use crate::player::{PlaybackMode, PlayerState};
+use crate::utils::write_atomic;
+
+/// How long changes have to settle before they're written.
+pub const SETTLE: Duration = Duration::from_secs(2);
+
+/// How often the session is written while playing, for the position.
+pub const POSITION_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Session {
+    /// Index into the config's playlists.
+    pub playlist: usize,
+    /// Path of the current track, which survives the folder changing unlike an index.
+    pub track: Option<String>,
+    /// The index configs before version 3 kept instead, used when there's no path.
+    #[serde(skip_serializing)]
+    pub track_index: Option<usize>,
+    pub position_ms: u64,
+    /// Paths of the queued tracks, in order.
+    pub queue: Vec<String>,
+    pub mode: PlaybackMode,
+    /// Paths in shuffle order, empty unless shuffled.
+    pub shuffle_order: Vec<String>,
+}
+
+impl Session {
+    /// What the player is doing right now, to save.
+    pub fn capture(state: &PlayerState, playlist: usize) -> Self {
+        let paths = |ids: &[SongId]| {
+            ids.iter()
+                .filter_map(|&id| state.songs.get(id))
+                .map(|song| song.path.clone())
+                .collect()
+        };
+
+        Self {
+            playlist,
+            track: state.current_song().map(|song| song.path.clone()),
+            track_index: None,
+            position_ms: state.position().as_millis() as u64,
+            queue: paths(&state.queue),
+            mode: state.mode,
+            shuffle_order: paths(&state.shuffle_order),
+        }
+    }
+
+    /// The session saved at `path`, or a fresh one when it's missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                eprintln!("Can't read {}: {e}", path.display());
+                return Self::default();
+            }
+        };
+
+        serde_json::from_str(&text).unwrap_or_else(|e| {
+            eprintln!(
+                "Can't parse {}, starting a new session: {e}",
+                path.display()
+            );
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self).expect("Can't export session!");
+        write_atomic(path, text.as_bytes())
+    }
+
+    /// Starts over when the playlist it points at is gone.
+    pub fn repair(&mut self, playlists: usize) {
+        if self.playlist >= playlists {
+            *self = Self {
+                mode: self.mode,
+                ..Self::default()
+            };
+        }
+    }
+
+    /// The saved track if `songs` still has it, falling back to the first one.
+    pub fn track_in(&self, songs: &Library) -> Option<SongId> {
+        let by_path = self.track.as_deref().and_then(|track| songs.find(track));
+        let by_index = self.track_index.and_then(|index| songs.ids().nth(index));
+
+        by_path.or(by_index).or_else(|| songs.first())
+    }
+
+    /// Commands that bring a player started on [`Session::track_in`] back to where it was.
+    /// Tracks that have disappeared since are left out.
+    pub fn commands(&self, songs: &Library) -> Vec<PlayerCommand> {
+        let mut commands = Vec::new();
+
+        match self.mode {
+            PlaybackMode::Shuffled => commands.push(PlayerCommand::RestoreShuffle(
+                self.shuffle_order
+                    .iter()
+                    .filter_map(|path| songs.find(path))
+                    .collect(),
+            )),
+            PlaybackMode::Repeat => commands.push(PlayerCommand::SetRepeat(true)),
+            PlaybackMode::Normal => {}
+        }
+
+        for queued in &self.queue {
+            if let Some(id) = songs.find(queued) {
+                commands.push(PlayerCommand::Enqueue(id));
+            }
+        }
+
+        // Only the exact track picks up where it left off
+        let found = self
+            .track
+            .as_deref()
+            .is_some_and(|track| songs.find(track).is_some());
+        if found && self.position_ms > 0 {
+            commands.push(PlayerCommand::Seek(Duration::from_millis(self.position_ms)));
+        }
+
+        commands
+    }
+}
+
+pub fn session_path() -> PathBuf {
+    state_dir().join("session.json")
+}
+
+/// Tracks unsaved changes to the config and session, so bursts of them (dragging the
+/// volume, skipping through tracks) turn into one write once they settle.
+#[derive(Debug)]
+pub struct Autosave {
+    config: Option<Instant>,
+    session: Option<Instant>,
+    session_saved: Instant,
+}
+
+impl Autosave {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            config: None,
+            session: None,
+            session_saved: now,
+        }
+    }
+
+    pub fn config_changed(&mut self, now: Instant) {
+        self.config = Some(now);
+    }
+
+    pub fn session_changed(&mut self, now: Instant) {
+        self.session = Some(now);
+    }
+
+    pub fn config_due(&self, now: Instant) -> bool {
+        self.config
+            .is_some_and(|changed| now.duration_since(changed) >= SETTLE)
+    }
+
+    /// While playing the position keeps moving, so that's saved every so often too.
+    pub fn session_due(&self, now: Instant, playing: bool) -> bool {
+        let settled = self
+            .session
+            .is_some_and(|changed| now.duration_since(changed) >= SETTLE);
+        settled || (playing && now.duration_since(self.session_saved) >= POSITION_INTERVAL)
+    }
+
+    pub fn config_saved(&mut self) {
+        self.config = None;
+    }
+
+    pub fn session_saved(&mut self, now: Instant) {
+        self.session = None;
+        self.session_saved = now;
+    }
+
+    /// How long until something is due, to wake up for it.
+    pub fn next_due(&self, now: Instant, playing: bool) -> Option<Duration> {
+        let settle = |changed: Instant| SETTLE.saturating_sub(now.duration_since(changed));
+        let position = playing
+            .then(|| POSITION_INTERVAL.saturating_sub(now.duration_since(self.session_saved)));
+
+        [self.config.map(settle), self.session.map(settle), position]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+pub enum AutosaveUpdate {
+    Player(PlayerEvent),
+    /// The config as the UI has it now.
+    Config(Box<Config>),
+    /// Another playlist is playing.
+    Playlist(usize),
+    /// Write everything now, the answer comes once it's on disk.
+    Flush(Sender<()>),
+}
+
+/// Writes the config and session as they settle. It runs on its own thread, so saving
+/// carries on while the window is minimized and nothing gets drawn.
+pub struct Autosaver {
+    pub config: Config,
+    pub config_path: PathBuf,
+    pub session_path: PathBuf,
+    /// Index of the playing playlist, for the session.
+    pub playlist: usize,
+    pub state: Arc<Mutex<PlayerState>>,
+    pub autosave: Autosave,
+}
+
+impl Autosaver {
+    pub fn spawn(self, events: Receiver<PlayerEvent>) -> Sender<AutosaveUpdate> {
+        let (tx, rx) = channel();
+
+        let forward = tx.clone();
+        std::thread::Builder::new()
+            .name("sanctum-autosave-events".to_string())
+            .spawn(move || {
+                for event in events {
+                    if forward.send(AutosaveUpdate::Player(event)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Can't start autosave thread!");
+
+        std::thread::Builder::new()
+            .name("sanctum-autosave".to_string())
+            .spawn(move || self.run(rx))
+            .expect("Can't start autosave thread!");
+
+        tx
+    }
+
+    fn run(mut self, updates: Receiver<AutosaveUpdate>) {
+        loop {
+            let update = match self.autosave.next_due(Instant::now(), self.playing()) {
+                Some(wait) => updates.recv_timeout(wait),
+                None => updates.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            let now = Instant::now();
+            match update {
+                Ok(AutosaveUpdate::Player(event)) => self.player_event(event, now),
+                Ok(AutosaveUpdate::Config(config)) => {
+                    self.config = *config;
+                    self.autosave.config_changed(now);
+                }
+                Ok(AutosaveUpdate::Playlist(index)) => {
+                    self.playlist = index;
+                    self.autosave.session_changed(now);
+                }
+                Ok(AutosaveUpdate::Flush(done)) => {
+                    self.save_config();
+                    self.save_session();
+                    let _ = done.send(());
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            let now = Instant::now();
+            if self.autosave.config_due(now) {
+                self.save_config();
+            }
+            if self.autosave.session_due(now, self.playing()) {
+                self.save_session();
+            }
+        }
+    }
+
+    fn player_event(&mut self, event: PlayerEvent, now: Instant) {
+        match event {
+            PlayerEvent::VolumeChanged(volume) => {
+                self.config.set_volume(volume);
+                self.autosave.config_changed(now);
+            }
+            // Play counts go up as tracks start
+            PlayerEvent::TrackChanged(_) | PlayerEvent::StatusChanged(_) => {
+                self.autosave.config_changed(now);
+                self.autosave.session_changed(now);
+            }
+            PlayerEvent::ModeChanged(_) | PlayerEvent::QueueChanged | PlayerEvent::Seeked(_) => {
+                self.autosave.session_changed(now)
+            }
+        }
+    }
+
+    fn playing(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.is_playing())
+    }
+
+    fn save_config(&mut self) {
+        if let Ok(state) = self.state.lock() {
+            self.config.set_play_counts(state.play_counts.to_map());
+        }
+        if let Err(e) = self.config.save(&self.config_path) {
+            eprintln!("Can't save config: {e}");
+        }
+        self.autosave.config_saved();
+    }
+
+    fn save_session(&mut self) {
+        let session = match self.state.lock() {
+            Ok(state) => Session::capture(&state, self.playlist),
+            Err(_) => return,
+        };
+        if let Err(e) = session.save(&self.session_path) {
+            eprintln!("Can't save session: {e}");
+        }
+        self.autosave.session_saved(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_wait_to_settle() {
+        let start = Instant::now();
+        let mut autosave = Autosave::new(start);
+        assert!(!autosave.config_due(start));
+        assert_eq!(autosave.next_due(start, false), None);
+
+        autosave.config_changed(start);
+        autosave.config_changed(start + Duration::from_secs(1));
+        assert!(!autosave.config_due(start + Duration::from_secs(2)));
+        assert_eq!(
+            autosave.next_due(start + Duration::from_secs(2), false),
+            Some(Duration::from_secs(1))
+        );
+        assert!(autosave.config_due(start + Duration::from_secs(3)));
+
+        autosave.config_saved();
+        assert!(!autosave.config_due(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn playing_saves_the_session_now_and_then() {
+        let start = Instant::now();
+        let mut autosave = Autosave::new(start);
+        let later = start + POSITION_INTERVAL;
+
+        assert!(!autosave.session_due(later, false));
+        assert!(autosave.session_due(later, true));
+
+        autosave.session_saved(later);
+        assert!(!autosave.session_due(later + SETTLE, true));
+        assert_eq!(autosave.next_due(later, true), Some(POSITION_INTERVAL));
+    }
+}
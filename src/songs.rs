@@ -1,12 +1,20 @@
+use crate::library::SongId;
 use crate::utils::format_date;
 use lofty::config::{ParseOptions, ParsingMode};
 use lofty::error::LoftyError;
 use lofty::file::TaggedFile;
 use lofty::prelude::*;
 use lofty::probe::Probe;
+use lofty::tag::{ItemKey, ItemValue, Tag};
+use std::collections::HashMap;
+use std::io;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone)]
 pub struct Song {
+    pub id: SongId,
     pub title: String,
     pub artist: String,
     pub album: String,
@@ -14,8 +22,53 @@ pub struct Song {
     pub path: String,
     pub created_date: String,
 
-    pub duration: u64,
+    /// Down to the millisecond, so nothing rounds off the end of the track.
+    pub duration: Duration,
     pub created: SystemTime,
+
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub genres: Vec<String>,
+    pub composers: Vec<String>,
+    /// Recording date as tagged, usually a year or an ISO 8601 date.
+    pub released: Option<String>,
+    /// Rating from the tags, scaled to 0..=1.
+    pub rating: Option<f64>,
+}
+
+/// How many times each song has started playing, keyed by path and shared between threads.
+#[derive(Debug, Clone, Default)]
+pub struct PlayCounts {
+    counts: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl PlayCounts {
+    pub fn new(counts: HashMap<String, u32>) -> Self {
+        Self {
+            counts: Arc::new(Mutex::new(counts)),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> u32 {
+        self.counts
+            .lock()
+            .map(|counts| counts.get(path).copied().unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    pub fn record(&self, path: &str) {
+        if let Ok(mut counts) = self.counts.lock() {
+            *counts.entry(path.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn to_map(&self) -> HashMap<String, u32> {
+        self.counts
+            .lock()
+            .map(|counts| counts.clone())
+            .unwrap_or_default()
+    }
 }
 
 pub fn get_tags(path: PathBuf, options: ParseOptions) -> Result<TaggedFile, LoftyError> {
@@ -23,53 +76,118 @@ pub fn get_tags(path: PathBuf, options: ParseOptions) -> Result<TaggedFile, Loft
     Ok(tag_file)
 }
 
-pub fn load_songs(main_dir: String) -> Vec<Song> {
-    let mut songs: Vec<Song> = Vec::new();
+fn strings(tag: Option<&Tag>, key: &ItemKey) -> Vec<String> {
+    tag.map(|tag| tag.get_strings(key).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Ratings come as an ID3 popularimeter (0-255 after the email) or as text, where
+/// taggers variously use 0-5 stars, 0-100 or 0-255.
+fn rating(tag: &Tag) -> Option<f64> {
+    let value = match tag.get(&ItemKey::Popularimeter)?.value() {
+        ItemValue::Binary(frame) => {
+            let email_end = frame.iter().position(|byte| *byte == 0)?;
+            *frame.get(email_end + 1)? as f64
+        }
+        ItemValue::Text(text) => text.trim().parse::<f64>().ok()?,
+        ItemValue::Locator(_) => return None,
+    };
+
+    let scale = match value {
+        value if value < 0. => return None,
+        value if value <= 5. => 5.,
+        value if value <= 100. => 100.,
+        _ => 255.,
+    };
+
+    Some((value / scale).min(1.))
+}
+
+/// Reads one file's tags, for the library scan or a file opened from outside it.
+pub fn load_song(path: PathBuf) -> Result<Song, LoftyError> {
     let parsing_options = ParseOptions::new().parsing_mode(ParsingMode::Relaxed);
+    let song_path = path.display().to_string();
 
-    for entry in std::fs::read_dir(main_dir).expect("Music folder not found!") {
-        let entry = entry.expect("Entries found!");
-        let path = entry.path();
+    let file_stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Unknown".to_string());
 
-        let song_path = path.display().to_string();
+    let tag_file = get_tags(path.clone(), parsing_options)?;
+
+    // Untagged files still play, they just fall back to their file name
+    let tag = tag_file.primary_tag().or_else(|| tag_file.first_tag());
+    let title = tag
+        .and_then(|tag| tag.title().map(|title| title.to_string()))
+        .unwrap_or(file_stem);
+    let artist = tag
+        .and_then(|tag| tag.artist().map(|artist| artist.to_string()))
+        .unwrap_or_else(|| "Unknown".to_string());
+    let album = tag
+        .and_then(|tag| tag.album().map(|album| album.to_string()))
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let album_artist =
+        tag.and_then(|tag| tag.get_string(&ItemKey::AlbumArtist).map(str::to_string));
+    let released = tag
+        .and_then(|tag| tag.get_string(&ItemKey::RecordingDate).map(str::to_string))
+        .or_else(|| tag.and_then(|tag| tag.year()).map(|year| year.to_string()));
+
+    let properties = tag_file.properties();
 
-        match get_tags(path, parsing_options) {
-            Ok(tag_file) => {
-                let tag = tag_file
-                    .primary_tag()
-                    .or_else(|| tag_file.first_tag())
-                    .expect(&format!("No tags found!: {}", song_path));
-
-                let properties = tag_file.properties();
-
-                let duration = properties.duration();
-                let seconds = duration.as_secs();
-
-                let metadata = entry.metadata().expect("No metadata found!");
-                let created_time = metadata.created().ok().unwrap();
-                let created_date = format_date(created_time);
-
-                let song = Song {
-                    title: tag.title().as_deref().unwrap_or("Unknown").to_string(),
-                    artist: tag.artist().as_deref().unwrap_or("Unknown").to_string(),
-                    album: tag.album().as_deref().unwrap_or("Unknown").to_string(),
-                    path: song_path,
-                    duration: seconds,
-                    search_key: format!(
-                        "{} {} {}",
-                        tag.title().as_deref().unwrap_or("Unknown").to_lowercase(),
-                        tag.artist().as_deref().unwrap_or("Unknown").to_lowercase(),
-                        tag.album().as_deref().unwrap_or("Unknown").to_lowercase(),
-                    ),
-                    created: created_time,
-                    created_date,
-                };
-
-                songs.push(song);
+    let duration = properties.duration();
+
+    // Not every filesystem records a creation time
+    let created_time = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let created_date = format_date(created_time);
+
+    Ok(Song {
+        id: SongId::of(&song_path),
+        search_key: format!(
+            "{} {} {}",
+            title.to_lowercase(),
+            artist.to_lowercase(),
+            album.to_lowercase(),
+        ),
+        title,
+        artist,
+        album,
+        path: song_path,
+        duration,
+        created: created_time,
+        created_date,
+        album_artist,
+        track_number: tag.and_then(|tag| tag.track()),
+        disc_number: tag.and_then(|tag| tag.disk()),
+        genres: strings(tag, &ItemKey::Genre),
+        composers: strings(tag, &ItemKey::Composer),
+        released,
+        rating: tag.and_then(rating),
+    })
+}
+
+/// Every track in a playlist's folder. Files that can't be read are skipped, only a
+/// folder that can't be listed at all is an error.
+pub fn load_songs(main_dir: String) -> io::Result<Vec<Song>> {
+    let mut songs: Vec<Song> = Vec::new();
+
+    for entry in std::fs::read_dir(main_dir)? {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                eprintln!("Can't read a folder entry: {e}");
+                continue;
             }
+        };
+        let song_path = path.display().to_string();
+
+        match load_song(path) {
+            Ok(song) => songs.push(song),
             Err(e) => eprintln!("Tag parse error for {:?}: {e}", song_path),
         }
     }
 
-    songs
+    Ok(songs)
 }
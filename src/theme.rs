@@ -0,0 +1,321 @@
+//! Colors and text sizes for the UI: the built-in dark and light themes, plus any the
+//! user drops into the themes folder.
+//!
+//! A theme file only has to list what it changes, everything else comes from its base.
+
+use std::path::{Path, PathBuf};
+
+use egui::{Color32, FontId};
+
+/// How far an accent taken from album art has to stand out from the background, WCAG's
+/// minimum for large text and controls.
+pub const MIN_ACCENT_CONTRAST: f32 = 3.;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Whether it builds on egui's dark look rather than its light one.
+    pub dark: bool,
+    /// Slider fills, the playing position and toggles that are on.
+    pub accent: Color32,
+    pub background: Color32,
+    /// Labels and track rows.
+    pub text: Color32,
+    /// Toggles that are off.
+    pub muted: Color32,
+    /// A TrueType or OpenType file to use instead of egui's own font.
+    pub font: Option<PathBuf>,
+    /// Track rows, playlists and the playbar buttons.
+    pub text_size: f32,
+    pub heading_size: f32,
+    pub large_size: f32,
+    /// The search field.
+    pub title_size: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "Dark".to_string(),
+            dark: true,
+            accent: Color32::from_rgb(1, 92, 128),
+            background: Color32::from_gray(27),
+            text: Color32::from_gray(140),
+            muted: Color32::from_gray(180),
+            font: None,
+            text_size: 18.,
+            heading_size: 24.,
+            large_size: 36.,
+            title_size: 48.,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "Light".to_string(),
+            dark: false,
+            accent: Color32::from_rgb(0, 109, 153),
+            background: Color32::from_gray(248),
+            text: Color32::from_gray(80),
+            muted: Color32::from_gray(150),
+            ..Self::dark()
+        }
+    }
+
+    /// Reads a theme file, naming it after the file unless it says otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Can't read it: {e}"))?;
+        let file: ThemeFile = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+
+        let mut theme = match file.base.as_deref() {
+            None | Some("dark") => Self::dark(),
+            Some("light") => Self::light(),
+            Some(other) => return Err(format!("unknown base \"{other}\", use dark or light")),
+        };
+        theme.name = match file.name {
+            Some(name) => name,
+            None => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+
+        let colors = [
+            (&mut theme.accent, file.accent),
+            (&mut theme.background, file.background),
+            (&mut theme.text, file.text),
+            (&mut theme.muted, file.muted),
+        ];
+        for (color, value) in colors {
+            if let Some(HexColor(value)) = value {
+                *color = value;
+            }
+        }
+
+        let sizes = [
+            (&mut theme.text_size, file.text_size),
+            (&mut theme.heading_size, file.heading_size),
+            (&mut theme.large_size, file.large_size),
+            (&mut theme.title_size, file.title_size),
+        ];
+        for (size, value) in sizes {
+            if let Some(value) = value {
+                if !(4.0..=200.0).contains(&value) {
+                    return Err(format!("text size {value} should be between 4 and 200"));
+                }
+                *size = value;
+            }
+        }
+
+        // Fonts next to the theme can be named without their folder
+        theme.font = file.font.map(|font| match path.parent() {
+            Some(dir) => dir.join(font),
+            None => font,
+        });
+
+        Ok(theme)
+    }
+
+    /// This theme with `accent` in place of its own, lightened or darkened until it
+    /// stands out from the background.
+    pub fn with_accent(&self, accent: Color32) -> Self {
+        let away = readable_on(self.background);
+        let mut readable = accent;
+        for step in 1..=10 {
+            if contrast(readable, self.background) >= MIN_ACCENT_CONTRAST {
+                break;
+            }
+            readable = accent.lerp_to_gamma(away, step as f32 / 10.);
+        }
+
+        Self {
+            accent: readable,
+            ..self.clone()
+        }
+    }
+
+    pub fn text_font(&self) -> FontId {
+        FontId::proportional(self.text_size)
+    }
+
+    pub fn heading_font(&self) -> FontId {
+        FontId::proportional(self.heading_size)
+    }
+
+    pub fn large_font(&self) -> FontId {
+        FontId::proportional(self.large_size)
+    }
+
+    pub fn title_font(&self) -> FontId {
+        FontId::proportional(self.title_size)
+    }
+
+    /// egui's own look for the base, recolored with this theme.
+    pub fn visuals(&self) -> egui::Visuals {
+        let mut visuals = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+
+        // Only plain text, so headings and buttons still stand out from it
+        visuals.widgets.noninteractive.fg_stroke.color = self.text;
+        visuals.panel_fill = self.background;
+        visuals.window_fill = self.background;
+        visuals.selection.bg_fill = self.accent;
+        // Selected text sits on the accent
+        visuals.selection.stroke.color = readable_on(self.accent);
+        visuals.hyperlink_color = self.accent;
+        visuals.text_cursor.stroke.color = self.accent;
+        visuals
+    }
+}
+
+/// WCAG contrast ratio, from 1 for the same color to 21 for black on white.
+pub fn contrast(a: Color32, b: Color32) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn luminance(color: Color32) -> f32 {
+    let linear = egui::Rgba::from(color);
+    0.2126 * linear.r() + 0.7152 * linear.g() + 0.0722 * linear.b()
+}
+
+/// Black or white, whichever reads better on `background`.
+fn readable_on(background: Color32) -> Color32 {
+    if contrast(Color32::WHITE, background) >= contrast(Color32::BLACK, background) {
+        Color32::WHITE
+    } else {
+        Color32::BLACK
+    }
+}
+
+/// The built-in themes followed by every `*.json` theme in `dir`, by file name. A file
+/// using a built-in name replaces it, files that can't be read are reported and skipped.
+pub fn load_themes(dir: &Path) -> Vec<Theme> {
+    let mut themes = vec![Theme::dark(), Theme::light()];
+
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(_) => return themes,
+    };
+    paths.sort();
+
+    for path in paths {
+        match Theme::load(&path) {
+            Ok(theme) => match themes.iter_mut().find(|known| known.name == theme.name) {
+                Some(known) => *known = theme,
+                None => themes.push(theme),
+            },
+            Err(e) => eprintln!("{}: {e}", path.display()),
+        }
+    }
+
+    themes
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    name: Option<String>,
+    base: Option<String>,
+    accent: Option<HexColor>,
+    background: Option<HexColor>,
+    text: Option<HexColor>,
+    muted: Option<HexColor>,
+    font: Option<PathBuf>,
+    text_size: Option<f32>,
+    heading_size: Option<f32>,
+    large_size: Option<f32>,
+    title_size: Option<f32>,
+}
+
+/// A color written like `#015c80` in theme files.
+#[derive(serde::Deserialize)]
+#[serde(try_from = "String")]
+struct HexColor(Color32);
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        Color32::from_hex(text.trim())
+            .map(HexColor)
+            .map_err(|_| format!("\"{text}\" isn't a color like #015c80"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme_file(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sanctum-theme-{name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.json"));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn theme_files_change_only_what_they_list() {
+        let path = theme_file(
+            "paper",
+            r##"{ "base": "light", "accent": "#c0392b", "text_size": 16, "font": "Inter.ttf" }"##,
+        );
+        let theme = Theme::load(&path).unwrap();
+
+        assert_eq!(theme.name, "paper");
+        assert!(!theme.dark);
+        assert_eq!(theme.accent, Color32::from_rgb(0xc0, 0x39, 0x2b));
+        assert_eq!(theme.background, Theme::light().background);
+        assert_eq!(theme.text_size, 16.);
+        assert_eq!(theme.title_size, 48.);
+        assert_eq!(theme.font, Some(path.with_file_name("Inter.ttf")));
+    }
+
+    #[test]
+    fn cover_accents_are_kept_readable() {
+        let dark = Theme::dark();
+        let navy = Color32::from_rgb(20, 30, 90);
+        assert!(contrast(navy, dark.background) < MIN_ACCENT_CONTRAST);
+        let lifted = dark.with_accent(navy).accent;
+        assert!(contrast(lifted, dark.background) >= MIN_ACCENT_CONTRAST);
+
+        let light = Theme::light();
+        let yellow = Color32::from_rgb(250, 230, 60);
+        let darkened = light.with_accent(yellow).accent;
+        assert!(contrast(darkened, light.background) >= MIN_ACCENT_CONTRAST);
+
+        // Colors that already stand out are left alone
+        let orange = Color32::from_rgb(240, 130, 30);
+        assert_eq!(dark.with_accent(orange).accent, orange);
+        assert_eq!(dark.with_accent(orange).background, dark.background);
+    }
+
+    #[test]
+    fn broken_theme_files_say_why() {
+        let color = theme_file("color", r#"{ "accent": "blue" }"#);
+        assert!(Theme::load(&color).unwrap_err().contains("\"blue\""));
+
+        let base = theme_file("base", r#"{ "base": "sepia" }"#);
+        assert!(Theme::load(&base).unwrap_err().contains("sepia"));
+
+        let size = theme_file("size", r#"{ "text_size": 0 }"#);
+        assert!(Theme::load(&size).is_err());
+
+        let typo = theme_file("typo", r##"{ "acent": "#ffffff" }"##);
+        assert!(Theme::load(&typo).unwrap_err().contains("acent"));
+    }
+}
@@ -0,0 +1,33 @@
+use sanctum::Song;
+use sanctum::cache::{SancCache, save_cover};
+
+pub fn load_cover_art(ui: &mut egui::Ui, cache: &mut SancCache, song: &Song) {
+    let album = &song.album;
+
+    let response = if let Some(cover_art) = cache.covers.get(album) {
+        ui.add_sized([48., 48.], egui::Image::new(format!("file://{cover_art}")))
+    } else {
+        ui.allocate_response(egui::vec2(48., 48.), egui::Sense::hover())
+    };
+
+    let is_visible = response.rect.intersects(ui.clip_rect());
+
+    if is_visible && !cache.covers.contains_key(album) && !cache.loading_covers.contains(album) {
+        cache.loading_covers.insert(album.clone());
+
+        let album_key = album.clone();
+        let song_path = song.path.clone();
+        let cache_dir = cache.path.clone();
+        let tx = cache.tx.clone();
+        let ctx = ui.ctx().clone();
+
+        // Offload to our bounded 2-thread Rayon pool
+        cache.pool.spawn(move || {
+            if let Some((image_path, accent)) = save_cover(&song_path, &album_key, &cache_dir) {
+                // Notify main egui thread, which may be idle until the next repaint
+                let _ = tx.send((album_key, image_path, accent));
+                ctx.request_repaint();
+            }
+        });
+    }
+}
@@ -1,4 +1,10 @@
+pub mod cover;
+pub mod onboarding;
 pub mod playbar;
 pub mod searchbar;
+pub mod seekbar;
+pub mod shortcuts;
 pub mod sidebar;
+pub mod theme;
 pub mod tracklist;
+pub mod visualizer;
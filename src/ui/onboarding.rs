@@ -0,0 +1,175 @@
+use std::path::PathBuf;
+
+use sanctum::config::music_dir;
+use sanctum::{Config, Playlist, Theme};
+
+/// First run: picks the music folders that become playlists.
+pub struct Onboarding {
+    /// Settings the playlists get added to, defaults unless a config without playlists exists.
+    base: Config,
+    /// Shown above everything, for why onboarding came up again.
+    notice: Option<String>,
+    playlists: Vec<Playlist>,
+    folder: String,
+    error: Option<String>,
+    theme: Theme,
+}
+
+impl Onboarding {
+    pub fn new(ctx: &egui::Context, base: Config, notice: Option<String>) -> Self {
+        let (_, theme) = crate::ui::theme::load(ctx, &base);
+        let mut onboarding = Self {
+            base,
+            notice,
+            playlists: Vec::new(),
+            folder: String::new(),
+            error: None,
+            theme,
+        };
+
+        // Most people keep everything in ~/Music, so start with that if it's there
+        let music = music_dir();
+        if music.is_dir() {
+            onboarding.playlists.push(Playlist::from_folder(&music));
+        }
+        onboarding
+    }
+
+    fn add_folder(&mut self) {
+        let folder = self.folder.trim();
+        let path = match folder.strip_prefix("~/") {
+            Some(rest) => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default()
+                .join(rest),
+            None => PathBuf::from(folder),
+        };
+
+        let path = match std::fs::canonicalize(&path) {
+            Ok(path) if path.is_dir() => path,
+            Ok(_) => {
+                self.error = Some(format!("{folder} isn't a folder"));
+                return;
+            }
+            Err(e) => {
+                self.error = Some(format!("Can't open {folder}: {e}"));
+                return;
+            }
+        };
+
+        let path_text = path.display().to_string();
+        if self
+            .playlists
+            .iter()
+            .any(|playlist| playlist.path == path_text)
+        {
+            self.error = Some(format!("{path_text} is already a playlist"));
+            return;
+        }
+
+        self.playlists.push(Playlist::from_folder(&path));
+        self.folder.clear();
+        self.error = None;
+    }
+
+    fn problem(&self) -> Option<String> {
+        if self.playlists.is_empty() {
+            return Some("Add at least one folder".to_string());
+        }
+
+        for (index, playlist) in self.playlists.iter().enumerate() {
+            let name = playlist.name.trim();
+            if name.is_empty() {
+                return Some(format!("{} needs a name", playlist.path));
+            }
+            let taken = self.playlists[..index]
+                .iter()
+                .any(|other| other.name.trim().eq_ignore_ascii_case(name));
+            if taken {
+                return Some(format!("There are two playlists called {name}"));
+            }
+        }
+        None
+    }
+
+    /// Draws the page, returning the new config once the user is done.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Config> {
+        let mut finished = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(48.);
+                ui.heading(egui::RichText::new("Welcome to Sanctum").font(self.theme.large_font()));
+                ui.label(
+                    egui::RichText::new(
+                        "Pick the folders your music lives in. Each one becomes a playlist.",
+                    )
+                    .font(self.theme.text_font()),
+                );
+                ui.add_space(24.);
+            });
+
+            if let Some(notice) = &self.notice {
+                ui.colored_label(ui.visuals().warn_fg_color, notice);
+                ui.add_space(12.);
+            }
+
+            let mut removed = None;
+            egui::Grid::new("onboarding_playlists")
+                .num_columns(3)
+                .spacing([12., 8.])
+                .show(ui, |ui| {
+                    for (index, playlist) in self.playlists.iter_mut().enumerate() {
+                        ui.add(egui::TextEdit::singleline(&mut playlist.name).desired_width(200.));
+                        ui.label(&playlist.path);
+                        if ui.button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            if let Some(index) = removed {
+                self.playlists.remove(index);
+            }
+
+            ui.add_space(12.);
+            ui.horizontal(|ui| {
+                let field = ui.add(
+                    egui::TextEdit::singleline(&mut self.folder)
+                        .hint_text("/path/to/music")
+                        .desired_width(400.),
+                );
+                let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                if (ui.button("Add folder").clicked() || entered) && !self.folder.trim().is_empty()
+                {
+                    self.add_folder();
+                }
+            });
+
+            let problem = self.error.clone().or_else(|| self.problem());
+            if let Some(problem) = &problem {
+                ui.colored_label(ui.visuals().warn_fg_color, problem);
+            }
+
+            ui.add_space(24.);
+            let start = ui.add_enabled(
+                self.problem().is_none(),
+                egui::Button::new(
+                    egui::RichText::new("Start listening").font(self.theme.heading_font()),
+                ),
+            );
+            if start.clicked() {
+                let mut config = std::mem::take(&mut self.base);
+                for playlist in &self.playlists {
+                    let mut playlist = playlist.clone();
+                    playlist.name = playlist.name.trim().to_string();
+                    config.add_playlist(playlist);
+                }
+                finished = Some(config);
+            }
+        });
+
+        finished
+    }
+}
@@ -1,6 +1,8 @@
 use crate::Sanctum;
-use crate::cache::load_cover_art;
-use crate::utils::format_timestamp;
+use crate::ui;
+use crate::ui::cover::load_cover_art;
+use sanctum::PlayerCommand;
+use sanctum::utils::format_timestamp;
 
 pub fn playbar(ui: &mut egui::Ui, idle: bool, sanc: &mut Sanctum) {
     let play_symbols = ["▶", "⏸"];
@@ -11,44 +13,42 @@ pub fn playbar(ui: &mut egui::Ui, idle: bool, sanc: &mut Sanctum) {
     };
 
     let play_button =
-        egui::Button::new(egui::RichText::new(play_state).font(egui::FontId::proportional(18.0)))
+        egui::Button::new(egui::RichText::new(play_state).font(sanc.theme.text_font()))
             .min_size(egui::Vec2::new(40.0, 40.0))
             .corner_radius(100)
             .frame(true);
 
-    let prev_button =
-        egui::Button::new(egui::RichText::new("⏪").font(egui::FontId::proportional(18.0)))
-            .min_size(egui::Vec2::new(40.0, 40.0))
-            .frame(false);
+    let prev_button = egui::Button::new(egui::RichText::new("⏪").font(sanc.theme.text_font()))
+        .min_size(egui::Vec2::new(40.0, 40.0))
+        .frame(false);
 
-    let skip_button =
-        egui::Button::new(egui::RichText::new("⏩").font(egui::FontId::proportional(18.0)))
-            .min_size(egui::Vec2::new(40.0, 40.0))
-            .frame(false);
+    let skip_button = egui::Button::new(egui::RichText::new("⏩").font(sanc.theme.text_font()))
+        .min_size(egui::Vec2::new(40.0, 40.0))
+        .frame(false);
 
     let shufl_color = if sanc.player.is_shuffled() {
-        egui::Color32::from_rgb(1, 92, 128)
+        sanc.theme.accent
     } else {
-        egui::Color32::from_rgb(180, 180, 180)
+        sanc.theme.muted
     };
 
     let shufl_button = egui::Button::new(
         egui::RichText::new("🔀")
-            .font(egui::FontId::proportional(18.0))
+            .font(sanc.theme.text_font())
             .color(shufl_color),
     )
     .min_size(egui::Vec2::new(40.0, 40.0))
     .frame(false);
 
     let loop_color = if sanc.player.is_repeat() {
-        egui::Color32::from_rgb(1, 92, 128)
+        sanc.theme.accent
     } else {
-        egui::Color32::from_rgb(180, 180, 180)
+        sanc.theme.muted
     };
 
     let repeat_button = egui::Button::new(
         egui::RichText::new("🔁")
-            .font(egui::FontId::proportional(18.0))
+            .font(sanc.theme.text_font())
             .color(loop_color),
     )
     .min_size(egui::Vec2::new(40.0, 40.0))
@@ -56,10 +56,10 @@ pub fn playbar(ui: &mut egui::Ui, idle: bool, sanc: &mut Sanctum) {
 
     ui.columns(3, |columns| {
         columns[0].horizontal_centered(|ui| {
-            if !sanc.player.done() {
-                let current_track = &sanc.songs[sanc.player.current_index];
-
-                load_cover_art(ui, &mut sanc.cache, current_track);
+            if let Some(current_track) = sanc.player.current_song().cloned()
+                && !sanc.player.done()
+            {
+                load_cover_art(ui, &mut sanc.cache, &current_track);
 
                 ui.heading(format!("{}\n{}", current_track.title, current_track.artist));
             } else {
@@ -71,61 +71,62 @@ pub fn playbar(ui: &mut egui::Ui, idle: bool, sanc: &mut Sanctum) {
             ui.horizontal(|ui| {
                 ui.add_space(ui.max_rect().width() / 3.);
                 if ui.add(repeat_button).clicked() {
-                    sanc.player.repeat();
+                    sanc.handle.send(PlayerCommand::ToggleRepeat);
                 }
 
                 if ui.add(prev_button).clicked() {
-                    sanc.player.previous(&sanc.songs);
+                    sanc.handle.send(PlayerCommand::Previous);
                 }
 
                 if ui.add(play_button).clicked() {
-                    sanc.player.playback();
+                    sanc.handle.send(PlayerCommand::PlayPause);
                 }
 
                 if ui.add(skip_button).clicked() {
-                    sanc.player.skip(&sanc.songs);
+                    sanc.handle.send(PlayerCommand::Next);
                 }
 
                 if ui.add(shufl_button).clicked() {
-                    sanc.player.shuffle();
+                    sanc.handle.send(PlayerCommand::ToggleShuffle);
                 }
             });
 
             ui.horizontal(|ui| {
-                ui.spacing_mut().slider_width = ui.max_rect().width() - 50.;
-                ui.style_mut().visuals.slider_trailing_fill = true;
+                let total_duration = sanc
+                    .player
+                    .current_song()
+                    .map(|song| song.duration)
+                    .unwrap_or_default();
 
-                let total_duration = sanc.songs[sanc.player.current_index].duration;
-                let time_slider = egui::Slider::new(&mut sanc.player.track_pos, 0..=total_duration)
-                    .logarithmic(false)
-                    .show_value(false)
-                    .clamping(egui::SliderClamping::Always)
-                    .trailing_fill(true);
-
-                let seek_bar = ui.add(time_slider);
-
-                if seek_bar.drag_stopped() {
-                    sanc.player.seek();
-                }
+                ui::seekbar::seek_bar(ui, sanc, ui.max_rect().width() - 110.);
 
                 ui.label(format!(
                     "{} / {}",
-                    format_timestamp(sanc.player.track_pos),
-                    format_timestamp(total_duration)
+                    format_timestamp(sanc.player.position().as_secs()),
+                    format_timestamp(total_duration.as_secs())
                 ));
             });
         });
 
         columns[2].horizontal_centered(|ui| {
-            ui.add_space(ui.max_rect().width() - 200.);
+            let free = ui.max_rect().width() - 220.;
+
+            if !sanc.visualizer.fullscreen && free > 40. {
+                ui::visualizer::visualizer(ui, sanc, egui::vec2(free, 36.));
+                ui.add_space(20.);
+            } else {
+                ui.add_space(free + 20.);
+            }
 
             ui.label("🔈");
             ui.style_mut().visuals.slider_trailing_fill = true;
 
-            ui.add(egui::Slider::new(&mut sanc.volume, 0..=100));
-
-            sanc.player.volume(sanc.volume);
-            sanc.config.set_volume(sanc.volume);
+            if ui
+                .add(egui::Slider::new(&mut sanc.volume, 0..=100))
+                .changed()
+            {
+                sanc.handle.send(PlayerCommand::SetVolume(sanc.volume));
+            }
         });
     });
 }
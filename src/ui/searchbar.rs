@@ -1,10 +1,10 @@
 use crate::Sanctum;
-use crate::cache::load_cover_art;
+use crate::ui::cover::load_cover_art;
+use sanctum::PlayerCommand;
 
 pub fn search_bar(ui: &mut egui::Ui, sanc: &mut Sanctum) {
     let search_button =
-        egui::Button::new(egui::RichText::new("🔎").font(egui::FontId::proportional(18.)))
-            .frame(false);
+        egui::Button::new(egui::RichText::new("🔎").font(sanc.theme.text_font())).frame(false);
 
     if ui.add(search_button).clicked() {
         sanc.search.open_modal();
@@ -13,9 +13,8 @@ pub fn search_bar(ui: &mut egui::Ui, sanc: &mut Sanctum) {
     if sanc.search.modal {
         let modal = egui::Modal::new(egui::Id::new("Search Bar")).show(ui.ctx(), |ui| {
             let search = ui.add_sized(
-                [600., 48.],
-                egui::TextEdit::singleline(&mut sanc.search.query)
-                    .font(egui::FontId::proportional(48.)),
+                [600., sanc.theme.title_size],
+                egui::TextEdit::singleline(&mut sanc.search.query).font(sanc.theme.title_font()),
             );
 
             search.request_focus();
@@ -24,8 +23,10 @@ pub fn search_bar(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                 sanc.search.handle_query(&sanc.songs);
             }
 
-            if search.ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-                sanc.player.set_index(sanc.search.results[0].0);
+            if search.ctx.input(|i| i.key_pressed(egui::Key::Enter))
+                && let Some(&(id, _)) = sanc.search.results.first()
+            {
+                sanc.handle.send(PlayerCommand::PlaySong(id));
                 sanc.search.close_modal();
             }
 
@@ -33,37 +34,41 @@ pub fn search_bar(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                 .min_scrolled_height(365.)
                 .show(ui, |ui| {
                     if !sanc.search.results.is_empty() {
-                        for (index, _) in sanc.search.results.iter().take(50) {
-                            let song = &sanc.songs[*index];
+                        for &(id, _) in sanc.search.results.iter().take(50) {
+                            let Some(song) = sanc.songs.get(id) else {
+                                continue;
+                            };
                             ui.horizontal_wrapped(|ui| {
-                                load_cover_art(ui, &mut sanc.cache, &song);
+                                load_cover_art(ui, &mut sanc.cache, song);
                                 let song_title = ui.add(
                                     egui::Button::new(
                                         egui::RichText::new(format!(
                                             "{}\n{}\n{}",
                                             song.title, song.artist, song.album
                                         ))
-                                        .font(egui::FontId::proportional(18.)),
+                                        .font(sanc.theme.text_font()),
                                     )
                                     .frame(false),
                                 );
 
                                 if song_title.clicked() {
-                                    sanc.player.set_index(*index);
+                                    sanc.handle.send(PlayerCommand::PlaySong(id));
                                     sanc.search.modal = false;
                                 }
 
                                 let queue_button = egui::Button::new(
-                                    egui::RichText::new("+").font(egui::FontId::proportional(36.)),
+                                    egui::RichText::new("+").font(sanc.theme.large_font()),
                                 )
                                 .frame(false);
 
-                                ui.add_space(ui.available_size_before_wrap().x - 36.);
+                                ui.add_space(
+                                    ui.available_size_before_wrap().x - sanc.theme.large_size,
+                                );
 
                                 let queue_song = ui.add(queue_button);
 
                                 if queue_song.clicked() {
-                                    sanc.player.add_queue(*index);
+                                    sanc.handle.send(PlayerCommand::Enqueue(id));
                                 }
                             });
                             ui.separator();
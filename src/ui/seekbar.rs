@@ -0,0 +1,140 @@
+use crate::Sanctum;
+use sanctum::cache::SancCache;
+use sanctum::peaks::cached_peaks;
+use sanctum::utils::format_precise_timestamp;
+use sanctum::{PlayerCommand, Song};
+use std::sync::Arc;
+
+// Width of one drawn column, including the gap after it
+const COLUMN: f32 = 3.;
+
+fn load_peaks(ctx: &egui::Context, cache: &mut SancCache, song: &Song) -> Option<Arc<Vec<f32>>> {
+    if let Some(peaks) = cache.peaks.get(&song.path) {
+        return Some(Arc::clone(peaks));
+    }
+
+    let mut wanted = cache.wanted_peaks.lock().expect("Peaks request poisoned!");
+    if wanted.as_deref() != Some(song.path.as_str()) {
+        *wanted = Some(song.path.clone());
+
+        let song = song.clone();
+        let cache_dir = cache.path.clone();
+        let tx = cache.peaks_tx.clone();
+        let wanted = Arc::clone(&cache.wanted_peaks);
+        let ctx = ctx.clone();
+
+        cache.peaks_pool.spawn(move || {
+            // Skipping through tracks leaves requests behind that nobody looks at anymore
+            let current = wanted
+                .lock()
+                .is_ok_and(|wanted| wanted.as_deref() == Some(song.path.as_str()));
+            if !current {
+                return;
+            }
+            if let Some(peaks) = cached_peaks(&song, &cache_dir) {
+                let _ = tx.send((song.path, peaks));
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    None
+}
+
+/// Loudest peak under each drawn column, scaled so the loudest column fills the bar.
+fn columns(peaks: &[f32], count: usize) -> Vec<f32> {
+    let mut columns: Vec<f32> = (0..count)
+        .map(|column| {
+            let from = column * peaks.len() / count;
+            let to = ((column + 1) * peaks.len() / count).max(from + 1);
+            peaks[from..to.min(peaks.len())]
+                .iter()
+                .copied()
+                .fold(0., f32::max)
+        })
+        .collect();
+
+    let loudest = columns.iter().copied().fold(0., f32::max);
+    if loudest > 0. {
+        columns.iter_mut().for_each(|column| *column /= loudest);
+    }
+
+    columns
+}
+
+/// Waveform of the current track that doubles as the seek control.
+///
+/// Clicking or letting go of a drag seeks, hovering shows the time under the pointer.
+pub fn seek_bar(ui: &mut egui::Ui, sanc: &mut Sanctum, width: f32) {
+    let (rect, response) =
+        ui.allocate_exact_size(egui::vec2(width, 28.), egui::Sense::click_and_drag());
+
+    let Some(song) = sanc.player.current_song().cloned() else {
+        return;
+    };
+
+    let length = song.duration;
+    let fraction_at = |x: f32| ((x - rect.left()) / rect.width()).clamp(0., 1.);
+
+    // While dragging, show where the track would jump to rather than where it is
+    let played = match response.interact_pointer_pos() {
+        Some(pointer) if response.dragged() => fraction_at(pointer.x),
+        _ if !length.is_zero() => {
+            (sanc.player.position().as_secs_f32() / length.as_secs_f32()).min(1.)
+        }
+        _ => 0.,
+    };
+
+    let accent = sanc.theme.accent;
+    let idle = ui.visuals().widgets.inactive.bg_fill;
+    let painter = ui.painter_at(rect);
+
+    match load_peaks(ui.ctx(), &mut sanc.cache, &song) {
+        Some(peaks) if !peaks.is_empty() => {
+            let count = (rect.width() / COLUMN).max(1.) as usize;
+
+            for (i, peak) in columns(&peaks, count).iter().enumerate() {
+                let left = rect.left() + i as f32 * COLUMN;
+                let height = (peak * rect.height()).max(2.);
+                let column = egui::Rect::from_center_size(
+                    egui::pos2(left + COLUMN / 2., rect.center().y),
+                    egui::vec2(COLUMN - 1., height),
+                );
+                let color = if (i as f32 + 0.5) / count as f32 <= played {
+                    accent
+                } else {
+                    idle
+                };
+                painter.rect_filled(column, 0., color);
+            }
+        }
+        // Plain track until the waveform is ready
+        _ => {
+            let track = egui::Rect::from_center_size(rect.center(), egui::vec2(rect.width(), 4.));
+            painter.rect_filled(track, 2., idle);
+
+            let mut done = track;
+            done.set_right(track.left() + track.width() * played);
+            painter.rect_filled(done, 2., accent);
+        }
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        painter.vline(pointer.x, rect.y_range(), egui::Stroke::new(1., accent));
+    }
+
+    let response = match response.hover_pos() {
+        Some(pointer) => {
+            let position = length.mul_f32(fraction_at(pointer.x));
+            response.on_hover_text_at_pointer(format_precise_timestamp(position))
+        }
+        None => response,
+    };
+
+    if (response.clicked() || response.drag_stopped())
+        && let Some(pointer) = response.interact_pointer_pos()
+    {
+        let position = length.mul_f32(fraction_at(pointer.x));
+        sanc.handle.send(PlayerCommand::Seek(position));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::Sanctum;
+use sanctum::keymap::{Action, KeyBinding, Keymap};
+
+/// The keyboard shortcuts window, where actions get rebound.
+#[derive(Default)]
+pub struct ShortcutsView {
+    pub open: bool,
+    /// The action waiting for its new key.
+    capturing: Option<Action>,
+    /// What the last change did besides the obvious, like taking a key from another action.
+    notice: Option<String>,
+}
+
+impl ShortcutsView {
+    /// While a key is being captured it shouldn't trigger anything else.
+    pub fn capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+}
+
+/// The key pressed this frame, if it's a complete binding. Escape cancels instead.
+fn captured(ctx: &egui::Context) -> Option<Option<KeyBinding>> {
+    ctx.input(|i| {
+        i.events.iter().find_map(|event| match event {
+            egui::Event::Key {
+                key: egui::Key::Escape,
+                pressed: true,
+                ..
+            } => Some(None),
+            egui::Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                ..
+            } => Some(Some(KeyBinding::pressed(*key, *modifiers))),
+            _ => None,
+        })
+    })
+}
+
+pub fn shortcuts(ctx: &egui::Context, sanc: &mut Sanctum) {
+    let view = &mut sanc.shortcuts;
+    if !view.open {
+        view.capturing = None;
+        return;
+    }
+
+    let mut keys = sanc.config.get_keys().clone();
+
+    if let Some(action) = view.capturing
+        && let Some(binding) = captured(ctx)
+    {
+        view.capturing = None;
+        // Otherwise Space or Enter would also press the focused button and start over
+        if let Some(focused) = ctx.memory(|memory| memory.focused()) {
+            ctx.memory_mut(|memory| memory.surrender_focus(focused));
+        }
+        view.notice = binding.and_then(|binding| {
+            keys.bind(action, binding)
+                .map(|other| format!("{binding} no longer does \"{}\"", other.label()))
+        });
+    }
+
+    let mut open = view.open;
+    egui::Window::new("Keyboard shortcuts")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("shortcuts")
+                .num_columns(2)
+                .spacing([24., 8.])
+                .show(ui, |ui| {
+                    for action in Action::ALL {
+                        ui.label(action.label());
+                        ui.horizontal(|ui| {
+                            for binding in keys.bindings(action).to_vec() {
+                                if ui
+                                    .button(binding.to_string())
+                                    .on_hover_text("Click to remove")
+                                    .clicked()
+                                {
+                                    keys.unbind(action, &binding);
+                                }
+                            }
+
+                            if view.capturing == Some(action) {
+                                ui.label("Press a key, Escape to cancel");
+                            } else if ui.small_button("+").clicked() {
+                                view.capturing = Some(action);
+                                view.notice = None;
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+
+            if let Some(notice) = &view.notice {
+                ui.colored_label(ui.visuals().warn_fg_color, notice);
+            }
+
+            ui.separator();
+            if ui.button("Reset to defaults").clicked() {
+                keys = Keymap::default();
+                view.notice = None;
+            }
+        });
+    view.open = open;
+
+    if keys != *sanc.config.get_keys() {
+        sanc.config.set_keys(keys);
+        sanc.config_changed();
+    }
+}
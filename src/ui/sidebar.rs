@@ -1,22 +1,21 @@
 use crate::Sanctum;
-use crate::songs::load_songs;
 
 pub fn sidebar(ui: &mut egui::Ui, sanc: &mut Sanctum) {
-    ui.heading(egui::RichText::new("Playlists").font(egui::FontId::proportional(24.0)));
+    ui.heading(egui::RichText::new("Playlists").font(sanc.theme.heading_font()));
 
     for index in 0..sanc.playlists.len() {
         let playlist_name = sanc.playlists[index].name.clone();
         if ui
-            .label(egui::RichText::new(playlist_name).font(egui::FontId::proportional(18.0)))
+            .label(egui::RichText::new(playlist_name).font(sanc.theme.text_font()))
             .clicked()
         {
-            sanc.config.set_playlist(index);
-            sanc.config.set_track(0);
-            sanc.songs = load_songs(sanc.playlists[index].path.clone());
-            sanc.songs
-                .sort_unstable_by_key(|item| std::cmp::Reverse(item.created));
-
-            sanc.player.set_index(0);
+            sanc.switch_playlist(index);
         }
     }
+
+    ui.separator();
+    if ui.button("Keyboard shortcuts").clicked() {
+        sanc.shortcuts.open = true;
+    }
+    crate::ui::theme::theme_picker(ui, sanc);
 }
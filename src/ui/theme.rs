@@ -0,0 +1,157 @@
+use crate::Sanctum;
+use egui::Color32;
+use sanctum::Config;
+use sanctum::cache::{SancCache, save_accent};
+use sanctum::config::themes_dir;
+use sanctum::theme::{Theme, load_themes};
+
+/// Every theme there is and the one the config asks for, already applied to `ctx`.
+/// A theme that's gone missing falls back to the default.
+pub fn load(ctx: &egui::Context, config: &Config) -> (Vec<Theme>, Theme) {
+    let themes = load_themes(&themes_dir());
+    let theme = match themes.iter().find(|theme| theme.name == config.get_theme()) {
+        Some(theme) => theme.clone(),
+        None => {
+            eprintln!("No theme called {}, using the default", config.get_theme());
+            Theme::default()
+        }
+    };
+
+    apply(ctx, &theme);
+    (themes, theme)
+}
+
+/// Makes `theme` the look of everything drawn from the next frame on.
+pub fn apply(ctx: &egui::Context, theme: &Theme) {
+    // Otherwise following the system theme would swap the visuals back out
+    ctx.set_theme(if theme.dark {
+        egui::Theme::Dark
+    } else {
+        egui::Theme::Light
+    });
+    ctx.set_visuals(theme.visuals());
+    ctx.style_mut(|style| {
+        style
+            .text_styles
+            .insert(egui::TextStyle::Heading, theme.heading_font());
+    });
+
+    let mut fonts = egui::FontDefinitions::default();
+    if let Some(path) = &theme.font {
+        match std::fs::read(path) {
+            Ok(data) => {
+                fonts
+                    .font_data
+                    .insert(theme.name.clone(), egui::FontData::from_owned(data).into());
+                // egui's fonts stay behind it for the symbols it lacks
+                fonts
+                    .families
+                    .entry(egui::FontFamily::Proportional)
+                    .or_default()
+                    .insert(0, theme.name.clone());
+            }
+            Err(e) => eprintln!("Can't load font {}: {e}", path.display()),
+        }
+    }
+    ctx.set_fonts(fonts);
+}
+
+/// Picks the theme, switching to it right away. Reloading picks up edits to theme files.
+pub fn theme_picker(ui: &mut egui::Ui, sanc: &mut Sanctum) {
+    let mut chosen = None;
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_label("Theme")
+            .selected_text(&sanc.theme.name)
+            .show_ui(ui, |ui| {
+                for theme in &sanc.themes {
+                    if ui
+                        .selectable_label(theme.name == sanc.theme.name, &theme.name)
+                        .clicked()
+                    {
+                        chosen = Some(theme.clone());
+                    }
+                }
+            });
+
+        if ui
+            .small_button("⟳")
+            .on_hover_text("Reload themes")
+            .clicked()
+        {
+            let (themes, theme) = load(ui.ctx(), &sanc.config);
+            sanc.themes = themes;
+            sanc.theme = theme;
+            sanc.cover_accent = None;
+        }
+    });
+
+    if let Some(theme) = chosen
+        && theme.name != sanc.theme.name
+    {
+        apply(ui.ctx(), &theme);
+        sanc.config.set_theme(theme.name.clone());
+        sanc.theme = theme;
+        sanc.cover_accent = None;
+        sanc.config_changed();
+    }
+
+    let mut cover_accent = sanc.config.get_cover_accent();
+    if ui
+        .checkbox(&mut cover_accent, "Accent from album art")
+        .changed()
+    {
+        sanc.config.set_cover_accent(cover_accent);
+        sanc.config_changed();
+    }
+}
+
+/// Gives the theme the playing track's cover accent while that's turned on, and its own
+/// back otherwise.
+pub fn follow_cover(ctx: &egui::Context, sanc: &mut Sanctum) {
+    let album = match sanc.player.current_song() {
+        Some(song) if sanc.config.get_cover_accent() => Some(song.album.clone()),
+        _ => None,
+    };
+    let accent = album.and_then(|album| cover_accent(ctx, &mut sanc.cache, &album));
+    if accent == sanc.cover_accent {
+        return;
+    }
+
+    let base = sanc
+        .themes
+        .iter()
+        .find(|theme| theme.name == sanc.theme.name)
+        .cloned()
+        .unwrap_or_default();
+    sanc.theme = match accent {
+        Some(accent) => base.with_accent(accent),
+        None => base,
+    };
+    sanc.cover_accent = accent;
+    ctx.set_visuals(sanc.theme.visuals());
+}
+
+/// The accent of an album's cover, once it's known.
+fn cover_accent(ctx: &egui::Context, cache: &mut SancCache, album: &str) -> Option<Color32> {
+    if let Some(accent) = cache.accents.get(album) {
+        return accent.map(|[r, g, b]| Color32::from_rgb(r, g, b));
+    }
+
+    // Covers cached before accents were get theirs worked out once
+    if let Some(image_path) = cache.covers.get(album).cloned()
+        && cache.loading_accents.insert(album.to_string())
+    {
+        let album = album.to_string();
+        let cache_dir = cache.path.clone();
+        let tx = cache.tx.clone();
+        let ctx = ctx.clone();
+
+        cache.pool.spawn(move || {
+            let accent = save_accent(&cache_dir, &album);
+            let _ = tx.send((album, image_path, accent));
+            ctx.request_repaint();
+        });
+    }
+    None
+}
@@ -1,19 +1,32 @@
 use crate::Sanctum;
-use crate::cache::load_cover_art;
-use crate::playlist::{Sort, sort_songs};
-use crate::utils::format_timestamp;
+use crate::ui::cover::load_cover_art;
 use egui_extras::{Column, TableBuilder};
+use sanctum::utils::format_timestamp;
+use sanctum::{PlayerCommand, Sort};
 
 pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
     let col_width = ui.max_rect().width() / 3.;
-    TableBuilder::new(ui)
+    let playing_row = std::mem::take(&mut sanc.scroll_to_playing)
+        .then(|| {
+            sanc.song_view
+                .iter()
+                .position(|&id| Some(id) == sanc.player.current)
+        })
+        .flatten();
+
+    let mut table = TableBuilder::new(ui)
         .striped(true)
         .column(Column::auto())
         .column(Column::exact(col_width))
         .column(Column::remainder())
         .column(Column::remainder())
         .column(Column::auto())
-        .column(Column::auto())
+        .column(Column::auto());
+    if let Some(row) = playing_row {
+        table = table.scroll_to_row(row, Some(egui::Align::Center));
+    }
+
+    table
         .header(24., |mut header| {
             header.col(|ui| {
                 ui.horizontal(|ui| {
@@ -32,12 +45,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
@@ -59,12 +67,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
@@ -86,12 +89,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
@@ -113,12 +111,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
@@ -140,12 +133,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
@@ -167,27 +155,25 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                     };
 
                     if ui.button(label).clicked() {
-                        sanc.current_playlist.set_sort(track_sort);
-                        sort_songs(
-                            sanc.current_playlist.clone(),
-                            &mut sanc.song_view,
-                            &sanc.songs,
-                        );
+                        sanc.set_sort(track_sort);
                     }
                 });
             });
         })
         .body(|mut body| {
             for list_index in 0..sanc.song_view.len() {
-                let view_index = &sanc.song_view[list_index];
-                let song = &sanc.songs[*view_index];
+                let id = sanc.song_view[list_index];
+                let (Some(song), Some(position)) = (sanc.songs.get(id), sanc.songs.position(id))
+                else {
+                    continue;
+                };
 
                 body.row(48., |mut row| {
                     row.col(|ui| {
                         ui.horizontal_centered(|ui| {
                             ui.label(
-                                egui::RichText::new(format!("{:02}", *view_index + 1))
-                                    .font(egui::FontId::proportional(18.0)),
+                                egui::RichText::new(format!("{:02}", position + 1))
+                                    .font(sanc.theme.text_font()),
                             );
                         });
                     });
@@ -197,19 +183,19 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                             load_cover_art(ui, &mut sanc.cache, song);
                             let song_title = egui::Button::new(
                                 egui::RichText::new(song.title.to_string())
-                                    .font(egui::FontId::proportional(18.0)),
+                                    .font(sanc.theme.text_font()),
                             )
                             .frame(false);
 
                             let song_title = ui.add(song_title);
 
                             if song_title.clicked() {
-                                sanc.player.set_index(*view_index);
+                                sanc.handle.send(PlayerCommand::PlaySong(id));
                             }
 
                             song_title.context_menu(|ui| {
                                 if ui.button("Add to queue").clicked() {
-                                    sanc.player.add_queue(list_index);
+                                    sanc.handle.send(PlayerCommand::Enqueue(id));
                                 }
                             });
                         });
@@ -219,7 +205,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                         ui.horizontal_centered(|ui| {
                             ui.label(
                                 egui::RichText::new(song.artist.to_string())
-                                    .font(egui::FontId::proportional(18.0)),
+                                    .font(sanc.theme.text_font()),
                             );
                         });
                     });
@@ -228,7 +214,7 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                         ui.horizontal_centered(|ui| {
                             ui.label(
                                 egui::RichText::new(song.album.to_string())
-                                    .font(egui::FontId::proportional(18.0)),
+                                    .font(sanc.theme.text_font()),
                             );
                         });
                     });
@@ -237,19 +223,16 @@ pub fn playlist(ui: &mut egui::Ui, sanc: &mut Sanctum) {
                         ui.horizontal_centered(|ui| {
                             ui.label(
                                 egui::RichText::new(song.created_date.to_string())
-                                    .font(egui::FontId::proportional(18.0)),
+                                    .font(sanc.theme.text_font()),
                             );
                         });
                     });
 
                     row.col(|ui| {
                         ui.horizontal_centered(|ui| {
-                            let timestamp = format_timestamp(song.duration);
+                            let timestamp = format_timestamp(song.duration.as_secs());
 
-                            ui.label(
-                                egui::RichText::new(timestamp)
-                                    .font(egui::FontId::proportional(18.0)),
-                            );
+                            ui.label(egui::RichText::new(timestamp).font(sanc.theme.text_font()));
                         });
                     });
                 });
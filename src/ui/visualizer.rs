@@ -0,0 +1,142 @@
+use crate::Sanctum;
+use sanctum::visualizer::{FFT_SIZE, spectrum};
+use sanctum::{AudioTap, VisualizerStyle};
+use std::time::Duration;
+
+// How much of the previous frame a falling bar keeps, rising bars jump straight up
+const DECAY: f32 = 0.8;
+
+pub struct VisualizerView {
+    pub tap: AudioTap,
+    pub fullscreen: bool,
+    levels: Vec<f32>,
+}
+
+impl VisualizerView {
+    pub fn new(tap: AudioTap) -> Self {
+        Self {
+            tap,
+            fullscreen: false,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Turns the tap off when nothing would be drawn, so the audio thread stops copying.
+    pub fn update_visibility(&mut self, ctx: &egui::Context, style: VisualizerStyle) {
+        let minimized = ctx.input(|i| i.viewport().minimized.unwrap_or(false));
+        self.tap
+            .set_active(style != VisualizerStyle::Off && !minimized);
+
+        if !self.tap.is_active() {
+            self.fullscreen = false;
+            self.levels.clear();
+        }
+    }
+
+    fn settle(&mut self, target: &[f32]) {
+        self.levels.resize(target.len(), 0.);
+
+        for (level, target) in self.levels.iter_mut().zip(target) {
+            *level = target.max(*level * DECAY);
+        }
+    }
+}
+
+fn settings_menu(ui: &mut egui::Ui, sanc: &mut Sanctum) {
+    let mut settings = sanc.config.get_visualizer().clone();
+
+    ui.label("Style");
+    ui.radio_value(&mut settings.style, VisualizerStyle::Bars, "Bars");
+    ui.radio_value(&mut settings.style, VisualizerStyle::Waveform, "Waveform");
+    ui.radio_value(&mut settings.style, VisualizerStyle::Off, "Off");
+
+    ui.separator();
+    ui.label("Frame rate");
+    for fps in [15, 30, 60] {
+        ui.radio_value(&mut settings.fps, fps, format!("{fps} fps"));
+    }
+
+    ui.separator();
+    if ui
+        .checkbox(&mut sanc.visualizer.fullscreen, "Fullscreen")
+        .changed()
+    {
+        ui.close();
+    }
+
+    if settings != *sanc.config.get_visualizer() {
+        sanc.config.set_visualizer(settings);
+        sanc.config_changed();
+    }
+}
+
+/// Draws the visualizer into a `size` box, right clicking it opens its settings.
+pub fn visualizer(ui: &mut egui::Ui, sanc: &mut Sanctum, size: egui::Vec2) {
+    let settings = sanc.config.get_visualizer().clone();
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+
+    response.context_menu(|ui| settings_menu(ui, sanc));
+
+    if response.double_clicked() {
+        sanc.visualizer.fullscreen = !sanc.visualizer.fullscreen;
+    }
+
+    let view = &mut sanc.visualizer;
+    let playing = sanc.player.is_playing();
+
+    let target = match (playing, settings.style) {
+        (true, VisualizerStyle::Bars) => {
+            let (samples, sample_rate) = view.tap.latest(FFT_SIZE);
+            spectrum(&samples, sample_rate, settings.bands.max(1))
+        }
+        (true, VisualizerStyle::Waveform) => {
+            let points = (rect.width() as usize / 2).max(2);
+            let (samples, _) = view.tap.latest(points * 4);
+            samples.chunks(4).map(|chunk| chunk[0]).collect()
+        }
+        _ => vec![0.; view.levels.len()],
+    };
+
+    let painter = ui.painter_at(rect);
+    let color = sanc.theme.accent;
+
+    match settings.style {
+        VisualizerStyle::Bars => {
+            view.settle(&target);
+
+            let slot = rect.width() / view.levels.len().max(1) as f32;
+            for (i, level) in view.levels.iter().enumerate() {
+                let height = (level * rect.height()).max(1.);
+                let left = rect.left() + i as f32 * slot;
+                let bar = egui::Rect::from_min_max(
+                    egui::pos2(left + slot * 0.1, rect.bottom() - height),
+                    egui::pos2(left + slot * 0.9, rect.bottom()),
+                );
+                painter.rect_filled(bar, 1., color);
+            }
+        }
+        VisualizerStyle::Waveform => {
+            view.levels = target;
+
+            let step = rect.width() / (view.levels.len().max(2) - 1) as f32;
+            let points = view
+                .levels
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| {
+                    let y = rect.center().y - sample.clamp(-1., 1.) * rect.height() / 2.;
+                    egui::pos2(rect.left() + i as f32 * step, y)
+                })
+                .collect::<Vec<_>>();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.5, color)));
+        }
+        VisualizerStyle::Off => {}
+    }
+
+    // Keep animating while playing, or until the bars have fallen back down
+    let falling = view.levels.iter().any(|level| level.abs() > 0.01);
+    if view.tap.is_active() && (playing || falling) {
+        let frame = Duration::from_secs_f32(1. / settings.fps.clamp(1, 120) as f32);
+        ui.ctx().request_repaint_after(frame);
+    }
+}
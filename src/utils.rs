@@ -1,5 +1,112 @@
 use chrono::{DateTime, Local};
-use std::time::SystemTime;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// FNV-1a, stable across builds unlike std's hasher, so it can name things on disk or the bus.
+pub fn fingerprint(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+/// Compares secrets without stopping at the first mismatch, so response times don't leak
+/// how much of a guess was right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Replaces `path` with `contents` all at once: they go to a temporary file next to it,
+/// which is synced and renamed over it. A crash leaves the old file or the new one, never half.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+
+    // Unique per call, so threads saving the same file don't share a temporary one
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = dir.join(temp_name);
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result?;
+
+    // Makes the rename itself durable, not every filesystem allows syncing a folder
+    if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// `file://` URI for a local path, percent-encoding anything outside the unreserved set.
+pub fn path_to_file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    uri
+}
+
+/// Local path behind a `file://` URI, with percent escapes decoded. Other schemes give `None`.
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    // Only the local host is ours to open, `file://localhost/...` included
+    let encoded = encoded.strip_prefix("localhost").unwrap_or(encoded);
+    if !encoded.starts_with('/') {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
 
 pub fn format_timestamp(timestamp: u64) -> String {
     let minutes = timestamp / 60;
@@ -8,6 +115,17 @@ pub fn format_timestamp(timestamp: u64) -> String {
     format!("{:02}:{:02}", minutes, seconds)
 }
 
+/// Like `format_timestamp`, with tenths of a second for scrubbing.
+pub fn format_precise_timestamp(position: Duration) -> String {
+    let tenths = position.as_millis() / 100;
+    format!(
+        "{:02}:{:02}.{}",
+        tenths / 600,
+        tenths / 10 % 60,
+        tenths % 10
+    )
+}
+
 pub fn format_date(created: SystemTime) -> String {
     let age = match SystemTime::now().duration_since(created) {
         Ok(d) => d,
@@ -0,0 +1,318 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+
+/// Samples per spectrum frame, ~46ms at 44.1kHz.
+pub const FFT_SIZE: usize = 2048;
+
+// Room for one FFT window plus whatever arrives between two UI frames
+const TAP_CAPACITY: usize = FFT_SIZE * 2;
+
+// Frames gathered on the audio thread before taking the lock
+const TAP_CHUNK: usize = 256;
+
+// Quietest level that still shows up as a bar
+const FLOOR_DB: f32 = -60.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+pub enum VisualizerStyle {
+    Off,
+    #[default]
+    Bars,
+    Waveform,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct VisualizerSettings {
+    pub style: VisualizerStyle,
+    /// Upper bound on repaints per second while the visualizer is on screen.
+    pub fps: u32,
+    pub bands: usize,
+}
+
+impl Default for VisualizerSettings {
+    fn default() -> Self {
+        Self {
+            style: VisualizerStyle::Bars,
+            fps: 30,
+            bands: 48,
+        }
+    }
+}
+
+struct TapBuffer {
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+}
+
+/// The most recent mono samples sent to the speakers, shared with the UI.
+///
+/// Nothing is copied while the tap is inactive, so a hidden visualizer costs nothing.
+#[derive(Clone)]
+pub struct AudioTap {
+    buffer: Arc<Mutex<TapBuffer>>,
+    active: Arc<AtomicBool>,
+}
+
+impl Default for AudioTap {
+    fn default() -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(TapBuffer {
+                samples: VecDeque::with_capacity(TAP_CAPACITY),
+                sample_rate: 44100,
+            })),
+            active: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl AudioTap {
+    pub fn set_active(&self, active: bool) {
+        if !active && self.is_active() {
+            self.clear();
+        }
+
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.samples.clear();
+        }
+    }
+
+    /// Last `count` samples, padded with silence at the front, and their sample rate.
+    pub fn latest(&self, count: usize) -> (Vec<f32>, u32) {
+        let Ok(buffer) = self.buffer.lock() else {
+            return (vec![0.; count], 44100);
+        };
+
+        let available = buffer.samples.len().min(count);
+        let mut samples = vec![0.; count - available];
+        samples.extend(buffer.samples.range(buffer.samples.len() - available..));
+
+        (samples, buffer.sample_rate)
+    }
+
+    fn push(&self, frames: &[f32], sample_rate: u32) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.sample_rate = sample_rate;
+            buffer.samples.extend(frames);
+
+            let overflow = buffer.samples.len().saturating_sub(TAP_CAPACITY);
+            buffer.samples.drain(..overflow);
+        }
+    }
+}
+
+/// Source wrapper that mixes everything it plays down to mono and copies it into an [`AudioTap`].
+pub struct Tapped<S> {
+    inner: S,
+    tap: AudioTap,
+    pending: Vec<f32>,
+    frame: f32,
+    channel: u16,
+}
+
+impl<S: Source> Tapped<S> {
+    pub fn new(inner: S, tap: AudioTap) -> Self {
+        Self {
+            inner,
+            tap,
+            pending: Vec::with_capacity(TAP_CHUNK),
+            frame: 0.,
+            channel: 0,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Tapped<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next()?;
+
+        if self.tap.is_active() {
+            let channels = self.inner.channels().max(1);
+            self.frame += sample;
+            self.channel += 1;
+
+            if self.channel >= channels {
+                self.pending.push(self.frame / channels as f32);
+                self.frame = 0.;
+                self.channel = 0;
+            }
+
+            if self.pending.len() >= TAP_CHUNK {
+                self.tap.push(&self.pending, self.inner.sample_rate());
+                self.pending.clear();
+            }
+        }
+
+        Some(sample)
+    }
+}
+
+impl<S: Source> Source for Tapped<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+/// In-place radix-2 FFT, `re` and `im` must have the same power of two length.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2. * PI / len as f32;
+        let (wr, wi) = (angle.cos(), angle.sin());
+
+        for start in (0..n).step_by(len) {
+            let (mut cr, mut ci) = (1., 0.);
+
+            for k in 0..len / 2 {
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cr - im[b] * ci;
+                let ti = re[b] * ci + im[b] * cr;
+
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+
+                let next_cr = cr * wr - ci * wi;
+                ci = cr * wi + ci * wr;
+                cr = next_cr;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+/// Levels of `samples` in `bands` log-spaced frequency bands, each scaled to 0..=1.
+pub fn spectrum(samples: &[f32], sample_rate: u32, bands: usize) -> Vec<f32> {
+    let n = samples.len().next_power_of_two();
+    let mut re = vec![0.; n];
+    let mut im = vec![0.; n];
+
+    // Hann window keeps leakage from smearing neighbouring bars together
+    for (i, sample) in samples.iter().enumerate() {
+        re[i] = sample * 0.5 * (1. - (2. * PI * i as f32 / (n - 1) as f32).cos());
+    }
+
+    fft(&mut re, &mut im);
+
+    let bin_hz = sample_rate as f32 / n as f32;
+    let (low_hz, high_hz) = (30_f32, (sample_rate as f32 / 2.).min(16000.));
+
+    (0..bands)
+        .map(|band| {
+            let from = low_hz * (high_hz / low_hz).powf(band as f32 / bands as f32);
+            let to = low_hz * (high_hz / low_hz).powf((band + 1) as f32 / bands as f32);
+
+            let first = ((from / bin_hz) as usize).clamp(1, n / 2 - 1);
+            let last = ((to / bin_hz) as usize).clamp(first + 1, n / 2);
+
+            let peak = (first..last)
+                .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * 4. / n as f32)
+                .fold(0., f32::max);
+
+            let db = 20. * peak.max(1e-9).log10();
+            ((db - FLOOR_DB) / -FLOOR_DB).clamp(0., 1.)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, sample_rate: u32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| (2. * PI * hz * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn sine_peaks_in_its_band() {
+        let levels = spectrum(&sine(1000., 44100), 44100, 32);
+        let loudest = levels
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+
+        // 1kHz sits a little over halfway up a 30Hz..16kHz log scale
+        let expected = ((1000_f32 / 30.).ln() / (16000_f32 / 30.).ln() * 32.) as usize;
+        assert!(loudest.abs_diff(expected) <= 1, "peak in band {loudest}");
+        assert!(levels[loudest] > 0.9);
+        assert!(levels[0] < 0.3);
+    }
+
+    #[test]
+    fn silence_is_flat() {
+        let levels = spectrum(&[0.; FFT_SIZE], 44100, 16);
+        assert!(levels.iter().all(|level| *level == 0.));
+    }
+
+    #[test]
+    fn tap_mixes_down_and_keeps_the_latest_samples() {
+        let tap = AudioTap::default();
+        tap.set_active(true);
+
+        let frames = [1., 0.].repeat(TAP_CAPACITY * 2);
+        let stereo = rodio::buffer::SamplesBuffer::new(2, 8000, frames);
+        let played: Vec<f32> = Tapped::new(stereo, tap.clone()).collect();
+        assert_eq!(played.len(), TAP_CAPACITY * 4);
+
+        let (samples, sample_rate) = tap.latest(16);
+        assert_eq!(sample_rate, 8000);
+        assert!(samples.iter().all(|sample| *sample == 0.5));
+    }
+}
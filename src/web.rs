@@ -0,0 +1,326 @@
+use crate::app::Remote;
+use sanctum::http::{self, HttpReply, HttpRequest, HttpResponse, HttpSettings};
+use sanctum::ipc::{EventReport, Reply, Request, Response, TrackReport};
+use sanctum::{PlayerCommand, Search, SongId};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+// Page size for library listings when the client doesn't ask for one
+const DEFAULT_LIMIT: usize = 100;
+
+// How often event streams get the playback position
+const POSITION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A song with the id the player knows it by, which the other endpoints take.
+#[derive(serde::Serialize)]
+struct LibraryTrack {
+    id: SongId,
+    #[serde(flatten)]
+    track: TrackReport,
+}
+
+#[derive(serde::Serialize)]
+struct QueuedTrack {
+    position: usize,
+    id: SongId,
+    #[serde(flatten)]
+    track: TrackReport,
+}
+
+#[derive(serde::Deserialize)]
+struct SeekBody {
+    position_ms: Option<u64>,
+    offset_ms: Option<i64>,
+}
+
+#[derive(serde::Deserialize)]
+struct VolumeBody {
+    volume: u32,
+}
+
+#[derive(serde::Deserialize)]
+struct EnqueueBody {
+    id: Option<SongId>,
+    position: Option<usize>,
+    query: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct PlaylistBody {
+    name: String,
+}
+
+/// Starts the HTTP API if it's turned on and has a token.
+pub fn start(settings: &HttpSettings, remote: Arc<Remote>) {
+    if !settings.enabled {
+        return;
+    }
+    if let Some(problem) = settings.problem() {
+        eprintln!("HTTP API not started: {problem}");
+        return;
+    }
+
+    let listener = match TcpListener::bind(settings.socket_addr()) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("HTTP API not started on {}: {e}", settings.socket_addr());
+            return;
+        }
+    };
+
+    let settings = settings.clone();
+    http::serve(listener, move |request| {
+        if let Some(refusal) = settings.refusal(request) {
+            return HttpReply::Response(refusal);
+        }
+        route(request, &remote)
+    });
+}
+
+fn route(request: &HttpRequest, remote: &Remote) -> HttpReply {
+    let segments = request.segments();
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["api", "events"]) if request.is_websocket() => return events(remote),
+
+        ("GET", ["api", "status"]) => forward(remote, Request::Status),
+        ("POST", ["api", "play"]) => forward(remote, Request::Play),
+        ("POST", ["api", "pause"]) => forward(remote, Request::Pause),
+        ("POST", ["api", "toggle"]) => forward(remote, Request::PlayPause),
+        ("POST", ["api", "next"]) => forward(remote, Request::Next),
+        ("POST", ["api", "previous"]) => forward(remote, Request::Previous),
+        ("POST", ["api", "seek"]) => seek(request, remote),
+        ("POST", ["api", "volume"]) => match request.json::<VolumeBody>() {
+            Ok(body) => forward(
+                remote,
+                Request::Volume {
+                    volume: body.volume,
+                },
+            ),
+            Err(e) => HttpResponse::error(400, e),
+        },
+
+        ("GET", ["api", "library"]) => library(request, remote),
+        ("POST", ["api", "library", id, "play"]) => match id.parse() {
+            Ok(id) => command(remote, PlayerCommand::PlaySong(id)),
+            Err(_) => HttpResponse::error(400, "Track id should be a number"),
+        },
+        ("GET", ["api", "search"]) => search(request, remote),
+
+        ("GET", ["api", "queue"]) => queue(remote),
+        ("POST", ["api", "queue"]) => enqueue(request, remote),
+        ("DELETE", ["api", "queue", position]) => match position.parse() {
+            Ok(position) => command(remote, PlayerCommand::Dequeue(position)),
+            Err(_) => HttpResponse::error(400, "Queue position should be a number"),
+        },
+        ("POST", ["api", "queue", position, "play"]) => match position.parse() {
+            Ok(position) => command(remote, PlayerCommand::SkipToQueued(position)),
+            Err(_) => HttpResponse::error(400, "Queue position should be a number"),
+        },
+
+        ("GET", ["api", "playlists"]) => playlists(remote),
+        ("POST", ["api", "playlist"]) => match request.json::<PlaylistBody>() {
+            Ok(body) => forward(remote, Request::Playlist { name: body.name }),
+            Err(e) => HttpResponse::error(400, e),
+        },
+
+        _ => HttpResponse::not_found(),
+    };
+
+    HttpReply::Response(response)
+}
+
+/// Answers through the same handler as the control socket, so both APIs behave alike.
+fn forward(remote: &Remote, request: Request) -> HttpResponse {
+    match remote.handle(request) {
+        Reply::Once(response @ Response::Error { .. }) => {
+            HttpResponse::json_with_status(400, &response)
+        }
+        Reply::Once(response) => HttpResponse::json(&response),
+        Reply::Stream(_) => HttpResponse::error(400, "Use /api/events for streams"),
+    }
+}
+
+fn command(remote: &Remote, command: PlayerCommand) -> HttpResponse {
+    remote.player.send(command);
+    HttpResponse::json(&Response::Ok)
+}
+
+fn seek(request: &HttpRequest, remote: &Remote) -> HttpResponse {
+    match request.json::<SeekBody>() {
+        Ok(SeekBody {
+            position_ms: Some(position_ms),
+            ..
+        }) => forward(remote, Request::Seek { position_ms }),
+        Ok(SeekBody {
+            offset_ms: Some(offset_ms),
+            ..
+        }) => forward(remote, Request::SeekBy { offset_ms }),
+        Ok(_) => HttpResponse::error(400, "Give position_ms or offset_ms"),
+        Err(e) => HttpResponse::error(400, e),
+    }
+}
+
+fn library(request: &HttpRequest, remote: &Remote) -> HttpResponse {
+    let number = |name: &str, default: usize| {
+        request
+            .query
+            .get(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    let offset = number("offset", 0);
+    let limit = number("limit", DEFAULT_LIMIT);
+
+    let songs = Arc::clone(&remote.state.lock().expect("Player state poisoned!").songs);
+    let tracks: Vec<LibraryTrack> = songs
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|song| LibraryTrack {
+            id: song.id,
+            track: TrackReport::from(song),
+        })
+        .collect();
+
+    HttpResponse::json(&serde_json::json!({ "total": songs.len(), "tracks": tracks }))
+}
+
+fn search(request: &HttpRequest, remote: &Remote) -> HttpResponse {
+    let Some(query) = request.query.get("q") else {
+        return HttpResponse::error(400, "Missing ?q=");
+    };
+
+    let songs = Arc::clone(&remote.state.lock().expect("Player state poisoned!").songs);
+    let mut search = Search {
+        query: query.clone(),
+        ..Search::default()
+    };
+    let tracks: Vec<LibraryTrack> = search
+        .search_query(&songs)
+        .into_iter()
+        .filter_map(|(id, _)| songs.get(id))
+        .map(|song| LibraryTrack {
+            id: song.id,
+            track: TrackReport::from(song),
+        })
+        .collect();
+
+    HttpResponse::json(&tracks)
+}
+
+fn queue(remote: &Remote) -> HttpResponse {
+    let state = remote.state.lock().expect("Player state poisoned!");
+    let tracks: Vec<QueuedTrack> = state
+        .queue
+        .iter()
+        .enumerate()
+        .filter_map(|(position, &id)| {
+            let song = state.songs.get(id)?;
+            Some(QueuedTrack {
+                position,
+                id,
+                track: TrackReport::from(song),
+            })
+        })
+        .collect();
+
+    HttpResponse::json(&tracks)
+}
+
+fn enqueue(request: &HttpRequest, remote: &Remote) -> HttpResponse {
+    let body = match request.json::<EnqueueBody>() {
+        Ok(body) => body,
+        Err(e) => return HttpResponse::error(400, e),
+    };
+
+    match body {
+        EnqueueBody {
+            id: Some(id),
+            position,
+            ..
+        } => {
+            let known = remote
+                .state
+                .lock()
+                .expect("Player state poisoned!")
+                .songs
+                .contains(id);
+            if !known {
+                return HttpResponse::error(400, format!("No track with id {id}"));
+            }
+            command(
+                remote,
+                PlayerCommand::EnqueueAt(position.unwrap_or(usize::MAX), id),
+            )
+        }
+        EnqueueBody {
+            query: Some(query), ..
+        } => forward(remote, Request::Enqueue { query }),
+        _ => HttpResponse::error(400, "Give a track id or a search query"),
+    }
+}
+
+fn playlists(remote: &Remote) -> HttpResponse {
+    let snapshot = remote.playlists.lock().expect("Playlists poisoned!");
+    let names: Vec<&str> = snapshot
+        .playlists
+        .iter()
+        .map(|playlist| playlist.name.as_str())
+        .collect();
+    let active = snapshot
+        .playlists
+        .get(snapshot.active)
+        .map(|playlist| playlist.name.as_str());
+
+    HttpResponse::json(&serde_json::json!({ "playlists": names, "active": active }))
+}
+
+/// Current status first, then every player event plus the position once a second.
+///
+/// The position goes out while paused too, so a client that's gone is noticed within a
+/// second even when nothing else happens.
+fn events(remote: &Remote) -> HttpReply {
+    let status = remote.handle(Request::Status);
+    let events = remote.player.subscribe();
+    let state = Arc::clone(&remote.state);
+
+    HttpReply::WebSocket(Box::new(move |tx| {
+        let send = |response: &Response| {
+            tx.send(serde_json::to_string(response).expect("Can't export event!"))
+                .is_ok()
+        };
+
+        if let Reply::Once(status) = status
+            && !send(&status)
+        {
+            return;
+        }
+
+        let mut tick = Instant::now() + POSITION_INTERVAL;
+        loop {
+            let report = match events.recv_timeout(tick.saturating_duration_since(Instant::now())) {
+                Ok(event) => match state.lock() {
+                    Ok(state) => EventReport::new(&event, &state),
+                    Err(_) => return,
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    tick += POSITION_INTERVAL;
+                    let position = match state.lock() {
+                        Ok(state) => state.position(),
+                        Err(_) => return,
+                    };
+                    EventReport::Position {
+                        position_ms: position.as_millis() as u64,
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            if !send(&Response::Event(report)) {
+                return;
+            }
+        }
+    }))
+}
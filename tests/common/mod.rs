@@ -0,0 +1,60 @@
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use lofty::config::WriteOptions;
+use lofty::prelude::*;
+use lofty::tag::{Tag, TagType};
+use sanctum::output::WavWriter;
+
+pub const SAMPLE_RATE: u32 = 8000;
+
+/// Fresh, empty folder for one test to generate its library into.
+pub fn library_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("sanctum-integration").join(name);
+
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).expect("Can't clean fixture folder!");
+    }
+    std::fs::create_dir_all(&dir).expect("Can't create fixture folder!");
+
+    dir
+}
+
+/// Writes a mono WAV holding a constant tone and tags it like a real track.
+pub fn write_track(
+    dir: &Path,
+    file_name: &str,
+    title: &str,
+    artist: &str,
+    album: &str,
+    seconds: u32,
+) -> PathBuf {
+    let path = dir.join(file_name);
+
+    let mut writer = WavWriter::create(&path, 1, SAMPLE_RATE).expect("Can't write fixture!");
+    writer
+        .write(&vec![0.25; (SAMPLE_RATE * seconds) as usize])
+        .expect("Can't write fixture samples!");
+    drop(writer);
+
+    let mut tag = Tag::new(TagType::RiffInfo);
+    tag.set_title(title.to_string());
+    tag.set_artist(artist.to_string());
+    tag.set_album(album.to_string());
+    tag.save_to_path(&path, WriteOptions::default())
+        .expect("Can't tag fixture!");
+
+    path
+}
+
+/// Three short tracks with distinct titles, artists and lengths.
+pub fn sample_library(name: &str) -> PathBuf {
+    let dir = library_dir(name);
+
+    write_track(&dir, "01.wav", "Morning Light", "Aurora Vale", "Dawn", 3);
+    write_track(&dir, "02.wav", "Crimson Tide", "Blue Harbor", "Oceans", 1);
+    write_track(&dir, "03.wav", "Silent Echo", "Aurora Vale", "Dawn", 2);
+
+    dir
+}
@@ -0,0 +1,284 @@
+mod common;
+
+use sanctum::config::{CONFIG_VERSION, back_up_broken};
+use sanctum::keymap::Action;
+use sanctum::theme::load_themes;
+use sanctum::utils::write_atomic;
+use sanctum::{Config, PlaybackMode, Playlist, Session};
+
+#[test]
+fn a_missing_config_means_first_run() {
+    let dir = common::library_dir("config-missing");
+    assert!(Config::load(&dir.join("config.json")).unwrap().is_none());
+}
+
+#[test]
+fn fresh_configs_save_into_new_folders_and_load_back() {
+    let dir = common::library_dir("config-fresh");
+    let music = dir.join("Music");
+    std::fs::create_dir_all(&music).unwrap();
+
+    let playlist = Playlist::from_folder(&music);
+    assert_eq!(playlist.name, "Music");
+
+    let path = dir.join("sanctum").join("config.json");
+    Config::new(vec![playlist]).save(&path).unwrap();
+
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert_eq!(config.get_playlists()[0].path, music.display().to_string());
+    assert_eq!(config.get_volume(), 50);
+    assert!(!config.get_http().enabled);
+    assert!(config.take_session().is_none());
+}
+
+#[test]
+fn the_example_config_is_current() {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("config.json.example");
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert!(config.repair().is_empty());
+    assert!(config.take_session().is_none());
+
+    // Saving it again gives the same file, so it has every field and nothing older
+    let dir = common::library_dir("config-example");
+    config.save(&dir.join("config.json")).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("config.json")).unwrap(),
+        std::fs::read_to_string(&path).unwrap().trim_end()
+    );
+}
+
+#[test]
+fn unversioned_configs_migrate_to_the_current_layout() {
+    let dir = common::library_dir("config-legacy");
+
+    let path = dir.join("config.json");
+    std::fs::write(
+        &path,
+        r#"{
+  "current_playlist": 0,
+  "playlists": [{ "name": "All", "path": "/music", "sort_order": { "Title": { "reverse": false } } }],
+  "last_track_index": 4,
+  "volume": 70,
+  "cache_path": "cache"
+}"#,
+    )
+    .unwrap();
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert_eq!(config.get_volume(), 70);
+
+    // The session part moved out to its own file
+    let session = config.take_session().unwrap();
+    assert_eq!(session.playlist, 0);
+    assert_eq!(session.track_index, Some(4));
+    assert_eq!(session.mode, PlaybackMode::Normal);
+
+    // Old files sat in the working directory, so their relative cache folder did too
+    let cache = std::path::Path::new(&config.cache_path);
+    assert!(cache.is_absolute());
+    assert_eq!(cache, std::env::current_dir().unwrap().join("cache"));
+
+    config.save(&path).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["version"], CONFIG_VERSION);
+    assert!(saved.get("last_track_index").is_none());
+    assert!(saved.get("session").is_none());
+}
+
+#[test]
+fn errors_point_at_the_line_and_field() {
+    let dir = common::library_dir("config-errors");
+    let path = dir.join("config.json");
+
+    std::fs::write(&path, "{\n  \"version\": 3,\n  nope\n}").unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert_eq!(error.line, Some(3));
+
+    std::fs::write(
+        &path,
+        "{\n  \"version\": 3,\n  \"playlists\": [{ \"name\": \"All\", \"path\": 7 }]\n}",
+    )
+    .unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.field.as_deref(), Some("playlists[0].path"));
+    let shown = error.to_string();
+    assert!(shown.contains("config.json:3:"), "{shown}");
+    assert!(!shown.contains(" at line "), "{shown}");
+
+    std::fs::write(&path, r#"{ "version": 3, "volum": 40 }"#).unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert!(error.message.contains("volum"), "{error}");
+
+    std::fs::write(&path, r#"{ "version": 99 }"#).unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert!(error.message.contains("newer"), "{error}");
+}
+
+#[test]
+fn out_of_range_values_are_pulled_back() {
+    let dir = common::library_dir("config-repair");
+    let path = dir.join("config.json");
+    std::fs::write(
+        &path,
+        r#"{ "version": 2, "current_playlist": 5, "last_track_index": 9, "volume": 250,
+             "mode": "Repeat", "playlists": [{ "name": "All", "path": "/music" }] }"#,
+    )
+    .unwrap();
+
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert_eq!(config.get_volume(), 100);
+    assert!(!config.cache_path.is_empty());
+
+    let mut session = config.take_session().unwrap();
+    session.repair(config.get_playlists().len());
+    assert_eq!(session.playlist, 0);
+    assert_eq!(session.track_index, None);
+    assert_eq!(session.mode, PlaybackMode::Repeat);
+}
+
+#[test]
+fn sessions_round_trip_and_shrug_off_damage() {
+    let dir = common::library_dir("config-session");
+    let path = dir.join("state").join("session.json");
+    assert_eq!(Session::load(&path), Session::default());
+
+    let session = Session {
+        playlist: 1,
+        track: Some("/music/07.flac".to_string()),
+        track_index: None,
+        position_ms: 81_250,
+        queue: vec!["/music/03.flac".to_string(), "/music/01.flac".to_string()],
+        mode: PlaybackMode::Shuffled,
+        shuffle_order: vec![
+            "/music/03.flac".to_string(),
+            "/music/07.flac".to_string(),
+            "/music/01.flac".to_string(),
+        ],
+    };
+    session.save(&path).unwrap();
+    assert_eq!(Session::load(&path), session);
+
+    std::fs::write(&path, "{ half a sess").unwrap();
+    assert_eq!(Session::load(&path), Session::default());
+}
+
+#[test]
+fn atomic_writes_replace_the_whole_file() {
+    let dir = common::library_dir("config-atomic");
+    let path = dir.join("config.json");
+    std::fs::write(&path, "old contents that are longer").unwrap();
+
+    write_atomic(&path, b"new").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+
+    // Nothing temporary is left next to it
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+}
+
+#[test]
+fn broken_configs_are_backed_up() {
+    let dir = common::library_dir("config-backup");
+    let path = dir.join("config.json");
+    std::fs::write(&path, "{ nope").unwrap();
+
+    let backup = back_up_broken(&path).unwrap();
+    assert!(!path.exists());
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), "{ nope");
+    assert!(
+        backup
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("config.json.broken-")
+    );
+}
+
+#[test]
+fn key_bindings_merge_over_the_defaults() {
+    let dir = common::library_dir("config-keys");
+    let path = dir.join("config.json");
+    std::fs::write(
+        &path,
+        r#"{ "version": 3, "keys": { "next": ["Ctrl+N"], "search": ["Space"] } }"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap().unwrap();
+    let keys = config.get_keys();
+    assert_eq!(keys.action(&"Ctrl+N".parse().unwrap()), Some(Action::Next));
+    assert!(keys.action(&"Ctrl+Right".parse().unwrap()).is_none());
+    assert_eq!(
+        keys.action(&"Ctrl+S".parse().unwrap()),
+        Some(Action::ToggleShuffle)
+    );
+
+    // Space was play/pause first, so search loses it
+    assert_eq!(
+        keys.action(&"Space".parse().unwrap()),
+        Some(Action::PlayPause)
+    );
+    assert!(keys.bindings(Action::Search).is_empty());
+
+    std::fs::write(
+        &path,
+        r#"{ "version": 3, "keys": { "next": ["Ctrl+Nope"] } }"#,
+    )
+    .unwrap();
+    let error = Config::load(&path).unwrap_err();
+    assert_eq!(error.field.as_deref(), Some("keys.next[0]"));
+}
+
+#[test]
+fn user_themes_join_the_built_ins() {
+    let dir = common::library_dir("config-themes");
+    std::fs::write(
+        dir.join("nord.json"),
+        r##"{ "name": "Nord", "accent": "#88c0d0", "background": "#2e3440" }"##,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("dark.json"),
+        r##"{ "name": "Dark", "text_size": 20 }"##,
+    )
+    .unwrap();
+    std::fs::write(dir.join("broken.json"), r#"{ "accent": 5 }"#).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a theme").unwrap();
+
+    let themes = load_themes(&dir);
+    let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+    assert_eq!(names, ["Dark", "Light", "Nord"]);
+    assert_eq!(themes[0].text_size, 20.);
+    assert!(themes[2].dark);
+
+    // The pick is remembered by name
+    let path = dir.join("config.json");
+    let mut config = Config::default();
+    assert_eq!(config.get_theme(), "Dark");
+    config.set_theme("Nord".to_string());
+    config.save(&path).unwrap();
+    assert_eq!(Config::load(&path).unwrap().unwrap().get_theme(), "Nord");
+}
+
+#[test]
+fn turning_the_http_api_on_makes_up_a_token_once() {
+    let dir = common::library_dir("config-http-token");
+    let path = dir.join("config.json");
+
+    let mut config = Config::default();
+    assert!(!config.ensure_http_token());
+    assert!(config.get_http().token.is_none());
+
+    std::fs::write(&path, r#"{ "version": 3, "http": { "enabled": true } }"#).unwrap();
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert!(config.ensure_http_token());
+    let token = config.get_http().token.clone().unwrap();
+    assert!(token.len() >= 32);
+    assert!(config.get_http().problem().is_none());
+
+    config.save(&path).unwrap();
+    let mut config = Config::load(&path).unwrap().unwrap();
+    assert!(!config.ensure_http_token());
+    assert_eq!(config.get_http().token.as_deref(), Some(token.as_str()));
+}
@@ -0,0 +1,297 @@
+use sanctum::http::{HttpReply, HttpResponse, HttpSettings, serve};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::{Message, WebSocket};
+
+// Feeds that noticed their client leaving, by the name the test gave them
+static STOPPED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let settings = HttpSettings {
+        token: Some("secret".to_string()),
+        ..HttpSettings::default()
+    };
+    serve(listener, move |request| {
+        if let Some(refusal) = settings.refusal(request) {
+            return HttpReply::Response(refusal);
+        }
+
+        match request.segments().as_slice() {
+            ["api", "events"] if request.is_websocket() => HttpReply::WebSocket(Box::new(|tx| {
+                let _ = tx.send(r#"{"event":"volume","volume":30}"#.to_string());
+            })),
+            // Stays open for a while without sending anything
+            ["api", "idle"] if request.is_websocket() => HttpReply::WebSocket(Box::new(|_| {
+                std::thread::sleep(Duration::from_secs(5));
+            })),
+            // Ticks until the client leaves, then says so through the test's channel
+            ["api", "ticks"] if request.is_websocket() => {
+                let stopped = request.query.get("stopped").cloned().unwrap_or_default();
+                HttpReply::WebSocket(Box::new(move |tx| {
+                    while tx.send("tick".to_string()).is_ok() {
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                    STOPPED.lock().unwrap().push(stopped);
+                }))
+            }
+            ["api", "echo"] => HttpReply::Response(HttpResponse::json(&request.query)),
+            ["api", "body"] => {
+                HttpReply::Response(HttpResponse::json(&String::from_utf8_lossy(&request.body)))
+            }
+            _ => HttpReply::Response(HttpResponse::not_found()),
+        }
+    });
+
+    address
+}
+
+fn get(address: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn connect(address: SocketAddr, path: &str) -> WebSocket<TcpStream> {
+    let stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let separator = if path.contains('?') { '&' } else { '?' };
+    let (socket, response) = tungstenite::client(
+        format!("ws://{address}{path}{separator}token=secret"),
+        stream,
+    )
+    .unwrap();
+    assert_eq!(response.status(), 101);
+    socket
+}
+
+// A client frame, masked unless told otherwise
+fn frame(fin: bool, opcode: u8, payload: &[u8], masked: bool) -> Vec<u8> {
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+    frame.push(if masked { 0x80 } else { 0 } | payload.len() as u8);
+    if masked {
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    frame
+}
+
+#[test]
+fn plain_requests_get_json_back() {
+    let address = server();
+
+    let response = get(
+        address,
+        "GET /api/echo?q=a%20b HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/json"));
+    assert!(response.ends_with(r#"{"q":"a b"}"#));
+
+    let response = get(address, "GET /api/echo HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+
+    let response = get(
+        address,
+        "GET /nowhere?token=secret HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let response = get(address, "nonsense\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+#[test]
+fn oversized_and_unframed_requests_are_refused() {
+    let address = server();
+
+    let long = "a".repeat(32 * 1024);
+    let response = get(
+        address,
+        &format!("GET / HTTP/1.1\r\nX-Long: {long}\r\n\r\n"),
+    );
+    assert!(response.starts_with("HTTP/1.1 431 "));
+
+    // Refused from the length alone, the body is never sent
+    let response = get(
+        address,
+        "POST /api/body?token=secret HTTP/1.1\r\nContent-Length: 2000000\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 413 "));
+
+    let response = get(
+        address,
+        "POST /api/body?token=secret HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n{}\r\n0\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 411 "));
+
+    let response = get(
+        address,
+        "POST /api/body?token=secret HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\r\n{\"not\": \"read\"}",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(r#""""#));
+}
+
+#[test]
+fn websockets_upgrade_and_stream_text_frames() {
+    let address = server();
+    let mut socket = connect(address, "/api/events");
+
+    assert_eq!(
+        socket.read().unwrap(),
+        Message::text(r#"{"event":"volume","volume":30}"#)
+    );
+
+    // The sender is gone, so the server closes after the last message
+    assert!(matches!(socket.read().unwrap(), Message::Close(_)));
+}
+
+#[test]
+fn fragmented_client_messages_and_pings_are_handled() {
+    let address = server();
+    let mut socket = connect(address, "/api/idle");
+
+    let mut raw = frame(false, 0x1, b"hel", true);
+    raw.extend(frame(true, 0x0, b"lo", true));
+    raw.extend(frame(true, 0x9, b"still there?", true));
+    socket.get_mut().write_all(&raw).unwrap();
+
+    assert_eq!(
+        socket.read().unwrap(),
+        Message::Pong(b"still there?".to_vec().into())
+    );
+    socket.close(None).unwrap();
+}
+
+#[test]
+fn unmasked_client_frames_drop_the_connection() {
+    let address = server();
+    let mut socket = connect(address, "/api/idle");
+
+    let raw = frame(true, 0x1, b"hello", false);
+    socket.get_mut().write_all(&raw).unwrap();
+
+    match socket.read() {
+        Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {}
+        Err(tungstenite::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+            panic!("The server kept the connection open")
+        }
+        Ok(message) => panic!("Unexpected {message:?}"),
+        Err(_) => {}
+    }
+}
+
+#[test]
+fn feeds_stop_once_their_client_leaves() {
+    let address = server();
+    let mut socket = connect(address, "/api/ticks?stopped=closed");
+    assert_eq!(socket.read().unwrap(), Message::text("tick"));
+    socket.close(None).unwrap();
+    drop(socket);
+
+    // Dropped without a goodbye this time
+    let mut socket = connect(address, "/api/ticks?stopped=dropped");
+    assert_eq!(socket.read().unwrap(), Message::text("tick"));
+    drop(socket);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let stopped = STOPPED.lock().unwrap().clone();
+        if stopped.contains(&"closed".to_string()) && stopped.contains(&"dropped".to_string()) {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Feeds still running: {stopped:?}"
+        );
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn cross_site_requests_are_refused() {
+    let address = server();
+    let post = |headers: &str| {
+        get(
+            address,
+            &format!(
+                "POST /api/body?token=secret HTTP/1.1\r\n{headers}Content-Length: 2\r\n\r\n{{}}"
+            ),
+        )
+    };
+    let json = "Content-Type: application/json\r\n";
+
+    let response = post(&format!("Host: 127.0.0.1:{}\r\n{json}", address.port()));
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+
+    // DNS rebinding: a page's own name resolving to us
+    let response = post(&format!(
+        "Host: attacker.example:{}\r\n{json}",
+        address.port()
+    ));
+    assert!(response.starts_with("HTTP/1.1 403 "));
+    let response = post(json);
+    assert!(response.starts_with("HTTP/1.1 403 "));
+
+    let response = post(&format!(
+        "Host: localhost\r\nOrigin: http://attacker.example\r\n{json}"
+    ));
+    assert!(response.starts_with("HTTP/1.1 403 "));
+    let response = post(&format!("Host: localhost\r\nOrigin: null\r\n{json}"));
+    assert!(response.starts_with("HTTP/1.1 403 "));
+    let response = post(&format!(
+        "Host: localhost:7837\r\nOrigin: http://localhost:7837\r\n{json}"
+    ));
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    // Forms and fetch without preflight can only send these
+    for content_type in ["text/plain", "application/x-www-form-urlencoded"] {
+        let response = post(&format!(
+            "Host: localhost\r\nContent-Type: {content_type}\r\n"
+        ));
+        assert!(response.starts_with("HTTP/1.1 415 "), "{content_type}");
+    }
+    let response = post("Host: localhost\r\n");
+    assert!(response.starts_with("HTTP/1.1 415 "));
+}
+
+#[test]
+fn websockets_from_other_sites_are_refused() {
+    let address = server();
+    let mut request = format!("ws://{address}/api/events?token=secret")
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("Origin", "http://attacker.example".parse().unwrap());
+
+    let stream = TcpStream::connect(address).unwrap();
+    match tungstenite::client(request, stream) {
+        Err(tungstenite::HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+            assert_eq!(response.status(), 403)
+        }
+        other => panic!(
+            "Upgrade wasn't refused: {:?}",
+            other.map(|(_, response)| response)
+        ),
+    }
+}
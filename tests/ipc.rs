@@ -0,0 +1,184 @@
+use sanctum::ipc::{
+    Client, EventReport, Reply, Request, Response, StatusReport, TIMEOUT, forward, listen,
+    parse_args, serve,
+};
+use sanctum::{PlayerEvent, PlayerState};
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::time::Instant;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn socket(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join("sanctum-integration")
+        .join(format!("{name}.sock"))
+}
+
+#[test]
+fn command_line_becomes_requests() {
+    let file = std::env::temp_dir().join("sanctum-ipc-args.wav");
+    std::fs::write(&file, b"").unwrap();
+    let file = file.canonicalize().unwrap().display().to_string();
+
+    let requests = parse_args(args(&["--next", &file, "--playlist", "Chill", "--enqueue"]));
+    assert_eq!(
+        requests,
+        Ok(vec![
+            Request::Open {
+                paths: vec![file],
+                enqueue: true
+            },
+            Request::Next,
+            Request::Playlist {
+                name: "Chill".to_string()
+            },
+        ])
+    );
+
+    assert!(parse_args(args(&["--playlist"])).is_err());
+    assert!(parse_args(args(&["--shuffle-everything"])).is_err());
+    assert!(parse_args(args(&["/definitely/not/here.flac"])).is_err());
+}
+
+#[test]
+fn requests_reach_the_running_instance() {
+    let path = socket("forward");
+    assert_eq!(forward(&path, &[Request::Next]).unwrap(), None);
+
+    let listener = listen(&path).unwrap();
+    serve(listener, |request| {
+        Reply::Once(match request {
+            Request::Playlist { name } => Response::error(format!("No playlist called {name}")),
+            _ => Response::Ok,
+        })
+    });
+
+    let responses = forward(
+        &path,
+        &[
+            Request::PlayPause,
+            Request::Playlist {
+                name: "Missing".to_string(),
+            },
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        responses,
+        Some(vec![
+            Response::Ok,
+            Response::error("No playlist called Missing")
+        ])
+    );
+}
+
+#[test]
+fn stale_sockets_are_replaced() {
+    let path = socket("stale");
+    drop(listen(&path).unwrap());
+
+    // Nobody's listening on the leftover file, so it counts as no instance
+    assert_eq!(forward(&path, &[Request::Raise]).unwrap(), None);
+    assert!(listen(&path).is_ok());
+}
+
+#[test]
+fn only_one_of_two_launches_gets_the_socket() {
+    let path = socket("race");
+    let launches: Vec<_> = (0..8)
+        .map(|_| {
+            let path = path.clone();
+            std::thread::spawn(move || listen(&path))
+        })
+        .collect();
+    let results: Vec<_> = launches
+        .into_iter()
+        .map(|launch| launch.join().unwrap())
+        .collect();
+
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    for error in results.iter().filter_map(|result| result.as_ref().err()) {
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+    }
+}
+
+#[test]
+fn players_that_never_answer_time_out() {
+    // Held like onboarding does, bound but not served yet
+    let path = socket("silent");
+    let _listener = listen(&path).unwrap();
+
+    let started = Instant::now();
+    let error = forward(&path, &[Request::Raise]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < TIMEOUT * 2);
+}
+
+#[test]
+fn requests_are_plain_json() {
+    let json = serde_json::to_string(&Request::Open {
+        paths: vec!["/music/a.flac".to_string()],
+        enqueue: false,
+    })
+    .unwrap();
+    assert_eq!(
+        json,
+        r#"{"command":"open","paths":["/music/a.flac"],"enqueue":false}"#
+    );
+
+    let json = serde_json::to_string(&Response::error("nope")).unwrap();
+    assert_eq!(json, r#"{"status":"error","message":"nope"}"#);
+}
+
+#[test]
+fn watch_streams_until_the_sender_is_done() {
+    let path = socket("watch");
+    serve(listen(&path).unwrap(), |request| match request {
+        Request::Watch => {
+            let (tx, rx) = channel();
+            let state = PlayerState::default();
+            for event in [PlayerEvent::VolumeChanged(30), PlayerEvent::QueueChanged] {
+                tx.send(Response::Event(EventReport::new(&event, &state)))
+                    .unwrap();
+            }
+            Reply::Stream(rx)
+        }
+        _ => Reply::Once(Response::Ok),
+    });
+
+    let mut client = Client::connect(&path).unwrap().unwrap();
+    client.send(&Request::Watch).unwrap();
+    assert_eq!(
+        client.receive().unwrap(),
+        Response::Event(EventReport::Volume { volume: 30 })
+    );
+    assert_eq!(
+        client.receive().unwrap(),
+        Response::Event(EventReport::Queue { queued: 0 })
+    );
+
+    // The same connection takes requests again once the stream is over
+    assert_eq!(client.request(&Request::Next).unwrap(), Response::Ok);
+}
+
+#[test]
+fn status_reports_flatten_into_the_response() {
+    let status = StatusReport::new(&PlayerState::default(), Some("Chill".to_string()));
+    let json = serde_json::to_value(Response::Player(status)).unwrap();
+
+    assert_eq!(json["status"], "player");
+    assert_eq!(json["state"], "stopped");
+    assert_eq!(json["track"], serde_json::Value::Null);
+    assert_eq!(json["mode"], "Normal");
+    assert_eq!(json["playlist"], "Chill");
+
+    let event = Response::Event(EventReport::Seeked { position_ms: 1500 });
+    assert_eq!(
+        serde_json::to_string(&event).unwrap(),
+        r#"{"status":"event","event":"seeked","position_ms":1500}"#
+    );
+}
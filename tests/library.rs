@@ -0,0 +1,244 @@
+mod common;
+
+use lofty::config::WriteOptions;
+use lofty::prelude::*;
+use lofty::tag::{ItemKey, Tag, TagType};
+
+use sanctum::output::WavWriter;
+use sanctum::peaks::{PEAK_BUCKETS, cached_peaks, compute_peaks, load_peaks, peaks_path};
+use sanctum::utils::{file_uri_to_path, path_to_file_uri};
+use sanctum::{Library, Playlist, Search, SongId, Sort, load_song, load_songs, sort_songs};
+use std::time::Duration;
+
+fn playlist(sort_order: Sort) -> Playlist {
+    Playlist {
+        name: "Test".to_string(),
+        path: String::new(),
+        sort_order,
+    }
+}
+
+fn titles(view: &[SongId], songs: &Library) -> Vec<String> {
+    view.iter()
+        .map(|&id| songs.get(id).unwrap().title.clone())
+        .collect()
+}
+
+#[test]
+fn durations_keep_their_milliseconds() {
+    let dir = common::library_dir("scanner-fraction");
+    let path = dir.join("fraction.wav");
+    let mut writer = WavWriter::create(&path, 1, common::SAMPLE_RATE).unwrap();
+    writer
+        .write(&vec![0.25; (common::SAMPLE_RATE * 3 / 2) as usize])
+        .unwrap();
+    drop(writer);
+
+    let song = load_song(path).unwrap();
+    assert_eq!(song.duration, Duration::from_millis(1500));
+}
+
+#[test]
+fn scanner_reads_tags_and_durations() {
+    let dir = common::sample_library("scanner");
+    let mut songs = load_songs(dir.display().to_string()).unwrap();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    assert_eq!(songs.len(), 3);
+    assert_eq!(songs[0].title, "Morning Light");
+    assert_eq!(songs[0].artist, "Aurora Vale");
+    assert_eq!(songs[0].album, "Dawn");
+    assert_eq!(songs[0].duration, Duration::from_secs(3));
+    assert_eq!(songs[1].duration, Duration::from_secs(1));
+    assert_eq!(songs[1].search_key, "crimson tide blue harbor oceans");
+}
+
+#[test]
+fn scanner_skips_files_that_are_not_audio() {
+    let dir = common::sample_library("scanner-junk");
+    std::fs::write(dir.join("notes.txt"), "not a song").unwrap();
+
+    assert_eq!(load_songs(dir.display().to_string()).unwrap().len(), 3);
+}
+
+#[test]
+fn missing_folders_are_an_error_not_a_crash() {
+    let dir = common::library_dir("scanner-missing").join("unplugged");
+
+    let error = load_songs(dir.display().to_string()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn untagged_tracks_fall_back_to_file_name() {
+    let dir = common::library_dir("untagged");
+    let mut writer =
+        sanctum::output::WavWriter::create(dir.join("Field Recording.wav"), 1, 8000).unwrap();
+    writer.write(&[0.; 8000]).unwrap();
+    drop(writer);
+
+    let songs = load_songs(dir.display().to_string()).unwrap();
+
+    assert_eq!(songs.len(), 1);
+    assert_eq!(songs[0].title, "Field Recording");
+    assert_eq!(songs[0].artist, "Unknown");
+}
+
+#[test]
+fn single_files_load_like_the_scanner_reads_them() {
+    let dir = common::sample_library("single");
+    let mut songs = load_songs(dir.display().to_string()).unwrap();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let song = load_song(songs[0].path.clone().into()).unwrap();
+    assert_eq!(song.title, songs[0].title);
+    assert_eq!(song.search_key, songs[0].search_key);
+    assert_eq!(song.duration, songs[0].duration);
+
+    assert!(load_song(dir.join("missing.wav")).is_err());
+}
+
+#[test]
+fn file_uris_decode_to_local_paths() {
+    assert_eq!(
+        file_uri_to_path("file:///music/My%20Song%E2%80%94Live.flac"),
+        Some("/music/My Song\u{2014}Live.flac".into())
+    );
+    assert_eq!(
+        file_uri_to_path("file://localhost/music/a.mp3"),
+        Some("/music/a.mp3".into())
+    );
+    assert_eq!(file_uri_to_path("file:///100%"), Some("/100%".into()));
+    assert_eq!(file_uri_to_path("file://server/share/a.mp3"), None);
+    assert_eq!(file_uri_to_path("https://example.com/a.mp3"), None);
+}
+
+#[test]
+fn file_uris_round_trip() {
+    let path = "/music/Ünïcode & Spaces #1.flac";
+    let uri = path_to_file_uri(path);
+
+    assert_eq!(
+        uri,
+        "file:///music/%C3%9Cn%C3%AFcode%20%26%20Spaces%20%231.flac"
+    );
+    assert_eq!(file_uri_to_path(&uri), Some(path.into()));
+}
+
+#[test]
+fn extended_tags_are_read() {
+    let dir = common::library_dir("extended-tags");
+    let path = common::write_track(&dir, "01.wav", "Opening", "Solo", "Suite", 1);
+
+    let mut tag = Tag::new(TagType::RiffInfo);
+    tag.set_title("Opening".to_string());
+    tag.set_track(4);
+    tag.set_genre("Ambient".to_string());
+    tag.insert_text(ItemKey::Composer, "Composer Name".to_string());
+    tag.insert_text(ItemKey::Popularimeter, "80".to_string());
+    tag.insert_text(ItemKey::RecordingDate, "2019".to_string());
+    tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+    let song = load_song(path).unwrap();
+    assert_eq!(song.track_number, Some(4));
+    assert_eq!(song.genres, vec!["Ambient"]);
+    assert_eq!(song.composers, vec!["Composer Name"]);
+    assert_eq!(song.released.as_deref(), Some("2019"));
+    assert_eq!(song.rating, Some(0.8));
+    assert_eq!(song.album_artist, None);
+}
+
+#[test]
+fn playlists_sort_by_column_and_direction() {
+    let dir = common::sample_library("sorting");
+    let songs = Library::new(load_songs(dir.display().to_string()).unwrap());
+    let mut view: Vec<SongId> = songs.ids().collect();
+
+    sort_songs(playlist(Sort::Title { reverse: false }), &mut view, &songs);
+    assert_eq!(
+        titles(&view, &songs),
+        vec!["Crimson Tide", "Morning Light", "Silent Echo"]
+    );
+
+    sort_songs(playlist(Sort::Time { reverse: true }), &mut view, &songs);
+    assert_eq!(
+        titles(&view, &songs),
+        vec!["Morning Light", "Silent Echo", "Crimson Tide"]
+    );
+
+    sort_songs(playlist(Sort::Artist { reverse: false }), &mut view, &songs);
+    assert_eq!(songs.get(view[2]).unwrap().artist, "Blue Harbor");
+}
+
+#[test]
+fn search_ranks_fuzzy_matches() {
+    let dir = common::sample_library("search");
+    let songs = load_songs(dir.display().to_string()).unwrap();
+    let mut search = Search {
+        query: "crimsn".to_string(),
+        ..Default::default()
+    };
+
+    search.handle_query(&songs);
+    let best = search.results[0].0;
+    assert_eq!(
+        songs.iter().find(|song| song.id == best).unwrap().title,
+        "Crimson Tide"
+    );
+
+    search.query = "aurora".to_string();
+    search.handle_query(&songs);
+    assert_eq!(search.results.len(), 2);
+
+    search.query = "x".to_string();
+    search.handle_query(&songs);
+    assert!(search.results.is_empty());
+}
+
+#[test]
+fn waveform_peaks_are_computed_once_and_cached() {
+    let dir = common::sample_library("peaks");
+    let cache_dir = common::library_dir("peaks-cache").display().to_string();
+    let song = load_songs(dir.display().to_string())
+        .unwrap()
+        .into_iter()
+        .find(|song| song.title == "Morning Light")
+        .unwrap();
+
+    let peaks = compute_peaks(&song, 64).unwrap();
+    assert_eq!(peaks.len(), 64);
+    assert!(peaks.iter().all(|peak| (peak - 0.25).abs() < 0.01));
+
+    let path = peaks_path(&cache_dir, &song.path);
+    assert!(!std::path::Path::new(&path).exists());
+
+    let computed = cached_peaks(&song, &cache_dir).unwrap();
+    assert_eq!(computed.len(), PEAK_BUCKETS);
+
+    let stored = load_peaks(&path).unwrap();
+    assert!(
+        computed
+            .iter()
+            .zip(&stored)
+            .all(|(a, b)| (a - b).abs() < 0.01)
+    );
+    assert_eq!(cached_peaks(&song, &cache_dir), Some(stored));
+}
+
+#[test]
+fn song_ids_survive_rescans() {
+    let dir = common::sample_library("song-ids");
+    let first = Library::new(load_songs(dir.display().to_string()).unwrap());
+
+    common::write_track(&dir, "00.wav", "Prelude", "Blue Harbor", "Oceans", 1);
+    let mut rescanned = load_songs(dir.display().to_string()).unwrap();
+    rescanned.reverse();
+    let second = Library::new(rescanned);
+
+    assert_eq!(second.len(), 4);
+    for song in first.iter() {
+        assert_eq!(second.get(song.id).unwrap().path, song.path);
+        assert_eq!(second.find(&song.path), Some(song.id));
+    }
+    assert_eq!(first.find(&dir.join("00.wav").display().to_string()), None);
+}
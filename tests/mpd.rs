@@ -0,0 +1,274 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sanctum::{
+    Library, NullOutput, Player, PlayerEvent, PlayerHandle, PlayerState, load_songs, mpd,
+};
+
+struct Client {
+    stream: TcpStream,
+    lines: Lines<BufReader<TcpStream>>,
+}
+
+impl Client {
+    fn connect(address: std::net::SocketAddr) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        assert!(lines.next().unwrap().unwrap().starts_with("OK MPD "));
+        Self { stream, lines }
+    }
+
+    /// Sends a command and collects lines up to and including the final OK or ACK.
+    fn command(&mut self, command: &str) -> Vec<String> {
+        writeln!(self.stream, "{command}").unwrap();
+
+        let mut response = Vec::new();
+        for line in self.lines.by_ref() {
+            let line = line.unwrap();
+            let done = line == "OK" || line.starts_with("ACK ");
+            response.push(line);
+            if done {
+                break;
+            }
+        }
+        response
+    }
+}
+
+fn value<'a>(response: &'a [String], key: &str) -> Option<&'a str> {
+    response
+        .iter()
+        .find_map(|line| line.strip_prefix(&format!("{key}: ")))
+}
+
+fn wait_for(events: &Receiver<PlayerEvent>, wanted: impl Fn(&PlayerEvent) -> bool) {
+    loop {
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        if wanted(&event) {
+            return;
+        }
+    }
+}
+
+fn server(
+    name: &str,
+    password: Option<&str>,
+) -> (std::net::SocketAddr, PlayerHandle, Arc<Library>) {
+    let dir = common::sample_library(name);
+    let mut songs = load_songs(dir.display().to_string()).unwrap();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+    let songs = Arc::new(Library::new(songs));
+
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let handle = Player::spawn(
+        || Box::new(NullOutput::default()),
+        None,
+        50,
+        Arc::clone(&songs),
+        Arc::clone(&state),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    mpd::serve(
+        listener,
+        handle.clone(),
+        state,
+        password.map(str::to_string),
+    );
+
+    (address, handle, songs)
+}
+
+#[test]
+fn clients_see_the_library_and_queue() {
+    let (address, handle, _) = server("mpd-library", None);
+    let events = handle.subscribe();
+    let mut client = Client::connect(address);
+
+    let status = client.command("status");
+    assert_eq!(value(&status, "volume"), Some("50"));
+    assert_eq!(value(&status, "playlistlength"), Some("1"));
+    assert_eq!(value(&status, "consume"), Some("1"));
+    assert_eq!(status.last().unwrap(), "OK");
+
+    let current = client.command("currentsong");
+    assert_eq!(value(&current, "file"), Some("01.wav"));
+    assert_eq!(value(&current, "Title"), Some("Morning Light"));
+    assert_eq!(value(&current, "Pos"), Some("0"));
+    let current_id = value(&current, "Id").unwrap().to_string();
+
+    let found = client.command(r#"search artist "aurora vale""#);
+    let files: Vec<&str> = found
+        .iter()
+        .filter_map(|line| line.strip_prefix("file: "))
+        .collect();
+    assert_eq!(files, ["01.wav", "03.wav"]);
+    assert_eq!(client.command(r#"find title "silent echo""#), ["OK"]);
+
+    let listed = client.command("lsinfo");
+    assert_eq!(
+        listed
+            .iter()
+            .filter(|line| line.starts_with("file: "))
+            .count(),
+        3
+    );
+
+    let added = client.command(r#"addid "03.wav""#);
+    assert_eq!(added.last().unwrap(), "OK");
+    let added_id = value(&added, "Id").unwrap().to_string();
+    wait_for(&events, |event| *event == PlayerEvent::QueueChanged);
+    let queue = client.command("playlistinfo");
+    let queued: Vec<&str> = queue
+        .iter()
+        .filter_map(|line| line.strip_prefix("Id: "))
+        .collect();
+    assert_eq!(queued, [current_id.as_str(), added_id.as_str()]);
+
+    assert_eq!(
+        client.command("add nowhere.wav"),
+        ["ACK [50@0] {add} No such song"]
+    );
+    assert_eq!(
+        client.command("bogus"),
+        [r#"ACK [5@0] {bogus} unknown command "bogus""#]
+    );
+}
+
+#[test]
+fn playback_commands_reach_the_player() {
+    let (address, handle, songs) = server("mpd-playback", None);
+    let events = handle.subscribe();
+    let mut client = Client::connect(address);
+
+    assert_eq!(client.command("setvol 80"), ["OK"]);
+    wait_for(&events, |event| *event == PlayerEvent::VolumeChanged(80));
+    assert_eq!(
+        client.command("setvol 120"),
+        ["ACK [2@0] {setvol} Invalid volume value"]
+    );
+
+    // Sent back to back, before the player reports any of them
+    writeln!(client.stream, "volume +5\nvolume +5\nvolume -3").unwrap();
+    let replies: Vec<String> = client.lines.by_ref().take(3).map(Result::unwrap).collect();
+    assert_eq!(replies, ["OK", "OK", "OK"]);
+    wait_for(&events, |event| *event == PlayerEvent::VolumeChanged(87));
+    assert_eq!(client.command("volume +50"), ["OK"]);
+    wait_for(&events, |event| *event == PlayerEvent::VolumeChanged(100));
+
+    assert_eq!(client.command("play 0"), ["OK"]);
+    wait_for(&events, |event| {
+        *event == PlayerEvent::StatusChanged(mpris_server::PlaybackStatus::Playing)
+    });
+    assert_eq!(value(&client.command("status"), "state"), Some("play"));
+
+    assert_eq!(client.command("next"), ["OK"]);
+    wait_for(&events, |event| {
+        *event == PlayerEvent::TrackChanged(Some(songs[1].id))
+    });
+
+    // Command lists stop at the first failure and say which command it was
+    writeln!(
+        client.stream,
+        "command_list_ok_begin\nrandom 1\npause 7\nstop\ncommand_list_end"
+    )
+    .unwrap();
+    let response: Vec<String> = client.lines.by_ref().take(2).map(Result::unwrap).collect();
+    assert_eq!(response[0], "list_OK");
+    assert_eq!(response[1], "ACK [2@1] {pause} Boolean (0/1) expected: 7");
+}
+
+#[test]
+fn idle_waits_for_changes() {
+    let (address, _handle, _) = server("mpd-idle", None);
+    let mut client = Client::connect(address);
+    let mut other = Client::connect(address);
+
+    writeln!(client.stream, "idle mixer").unwrap();
+    assert_eq!(other.command("setvol 20"), ["OK"]);
+    assert_eq!(client.lines.next().unwrap().unwrap(), "changed: mixer");
+    assert_eq!(client.lines.next().unwrap().unwrap(), "OK");
+
+    // noidle answers straight away when nothing it waits on changed
+    writeln!(client.stream, "idle options").unwrap();
+    assert_eq!(client.command("noidle"), ["OK"]);
+}
+
+#[test]
+fn a_password_gates_everything_but_the_basics() {
+    let (address, _handle, _) = server("mpd-password", Some("hunter2"));
+    let mut client = Client::connect(address);
+
+    assert_eq!(client.command("ping"), ["OK"]);
+    assert_eq!(
+        client.command("status"),
+        [r#"ACK [4@0] {status} you don't have permission for "status""#]
+    );
+    assert_eq!(
+        client.command("password nope"),
+        ["ACK [3@0] {password} incorrect password"]
+    );
+    assert_eq!(client.command("password hunter2"), ["OK"]);
+    assert_eq!(client.command("status").last().unwrap(), "OK");
+}
+
+#[test]
+fn songs_queued_twice_are_told_apart() {
+    let (address, handle, _) = server("mpd-entries", None);
+    let events = handle.subscribe();
+    let mut client = Client::connect(address);
+
+    // The playing song, queued again behind itself
+    let current_id = value(&client.command("currentsong"), "Id")
+        .unwrap()
+        .to_string();
+    let added = client.command(r#"addid "01.wav""#);
+    let added_id = value(&added, "Id").unwrap().to_string();
+    assert_ne!(added_id, current_id);
+    wait_for(&events, |event| *event == PlayerEvent::QueueChanged);
+
+    let copy = client.command(&format!("playlistid {added_id}"));
+    assert_eq!(value(&copy, "file"), Some("01.wav"));
+    assert_eq!(value(&copy, "Pos"), Some("1"));
+
+    // Deleting what was just added leaves the playing entry alone
+    assert_eq!(client.command(&format!("deleteid {added_id}")), ["OK"]);
+    wait_for(&events, |event| *event == PlayerEvent::QueueChanged);
+    let status = client.command("status");
+    assert_eq!(value(&status, "playlistlength"), Some("1"));
+    assert_eq!(value(&status, "songid"), Some(current_id.as_str()));
+    assert_eq!(
+        client.command(&format!("playlistid {added_id}")),
+        ["ACK [50@0] {playlistid} No such song"]
+    );
+}
+
+#[test]
+fn endless_lines_are_cut_off() {
+    let (address, _handle, _) = server("mpd-long", None);
+    let mut client = Client::connect(address);
+
+    let long = format!("find title \"{}\"", "a".repeat(64 * 1024));
+    assert_eq!(client.command(&long), ["ACK [2@0] {} Line too long"]);
+    // And the connection is closed, possibly reset over the rest of the line
+    assert!(!matches!(client.lines.next(), Some(Ok(_))));
+
+    let mut client = Client::connect(address);
+    writeln!(client.stream, "command_list_begin").unwrap();
+    for _ in 0..5000 {
+        writeln!(client.stream, "ping").unwrap();
+    }
+    assert_eq!(
+        client.lines.next().unwrap().unwrap(),
+        "ACK [2@0] {} Command list too long"
+    );
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use std::sync::mpsc::{Receiver, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sanctum::session::{Autosave, AutosaveUpdate, Autosaver, SETTLE};
+use sanctum::{
+    CaptureOutput, Config, Library, NullOutput, PlaybackMode, Player, PlayerCommand, PlayerEvent,
+    PlayerState, Session, load_songs,
+};
+
+fn wait_for(events: &Receiver<PlayerEvent>, wanted: PlayerEvent) {
+    loop {
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap_or_else(|_| panic!("Never saw {wanted:?}"));
+
+        if event == wanted {
+            return;
+        }
+    }
+}
+
+#[test]
+fn engine_thread_follows_commands() {
+    let dir = common::sample_library("engine-thread");
+    let songs = Arc::new(Library::new(load_songs(dir.display().to_string()).unwrap()));
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+
+    let handle = Player::spawn(
+        || Box::new(NullOutput::default()),
+        None,
+        50,
+        Arc::clone(&songs),
+        Arc::clone(&state),
+    );
+    let events = handle.subscribe();
+
+    // Cued but paused until asked
+    assert!(!state.lock().unwrap().is_playing());
+    assert_eq!(state.lock().unwrap().volume, 50);
+
+    handle.send(PlayerCommand::PlaySong(songs[2].id));
+    wait_for(&events, PlayerEvent::TrackChanged(Some(songs[2].id)));
+
+    handle.send(PlayerCommand::ToggleShuffle);
+    wait_for(&events, PlayerEvent::ModeChanged(PlaybackMode::Shuffled));
+
+    handle.send(PlayerCommand::SetVolume(80));
+    wait_for(&events, PlayerEvent::VolumeChanged(80));
+
+    handle.send(PlayerCommand::Pause);
+    wait_for(
+        &events,
+        PlayerEvent::StatusChanged(mpris_server::PlaybackStatus::Paused),
+    );
+
+    let snapshot = state.lock().unwrap().clone();
+    assert_eq!(snapshot.current, Some(songs[2].id));
+    assert_eq!(snapshot.current_song().unwrap().path, songs[2].path);
+    assert!(snapshot.is_shuffled());
+}
+
+#[test]
+fn capture_renders_real_files_back_to_back() {
+    let dir = common::sample_library("engine-capture");
+    let mut songs = load_songs(dir.display().to_string()).unwrap();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let capture_path = dir.join("capture.wav");
+    let output = CaptureOutput::create(&capture_path, 1, common::SAMPLE_RATE).unwrap();
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let songs = Arc::new(Library::new(songs));
+    let (mut player, _) = Player::new(Box::new(output.clone()), None, Arc::clone(&songs), state);
+
+    player.apply(PlayerCommand::PlaySong(songs[0].id));
+
+    // 3s + 1s tracks, then a second into the third one
+    for _ in 0..50 {
+        player.process();
+        output.advance(Duration::from_millis(100)).unwrap();
+    }
+    player.process();
+
+    assert_eq!(player.current, Some(songs[2].id));
+    assert_eq!(output.captured(), Duration::from_secs(5));
+    assert_eq!(player.track_pos, Duration::from_secs(1));
+}
+
+#[test]
+fn sessions_restore_by_path_after_the_folder_changes() {
+    let dir = common::library_dir("session-restore");
+    common::write_track(&dir, "01.wav", "Morning Light", "Aurora Vale", "Dawn", 3);
+    common::write_track(&dir, "02.wav", "Crimson Tide", "Blue Harbor", "Oceans", 4);
+    common::write_track(&dir, "03.wav", "Silent Echo", "Aurora Vale", "Dawn", 2);
+    let mut songs = load_songs(dir.display().to_string()).unwrap();
+    songs.sort_by(|a, b| a.path.cmp(&b.path));
+    let songs = Arc::new(Library::new(songs));
+
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let (mut player, _) = Player::new(
+        Box::new(NullOutput::default()),
+        None,
+        Arc::clone(&songs),
+        Arc::clone(&state),
+    );
+    player.apply(PlayerCommand::PlaySong(songs[1].id));
+    player.apply(PlayerCommand::Pause);
+    player.apply(PlayerCommand::Seek(Duration::from_millis(2500)));
+    player.apply(PlayerCommand::Enqueue(songs[2].id));
+    player.apply(PlayerCommand::Enqueue(songs[0].id));
+    player.apply(PlayerCommand::SetRepeat(true));
+    player.update_state();
+
+    let session = Session::capture(&state.lock().unwrap(), 0);
+    assert_eq!(session.track.as_deref(), Some(songs[1].path.as_str()));
+    assert_eq!(session.position_ms, 2500);
+
+    // The folder now lists the songs the other way round, so positions moved
+    let mut reversed = songs.to_vec();
+    reversed.reverse();
+    let reversed = Arc::new(Library::new(reversed));
+
+    let start = session.track_in(&reversed);
+    assert_eq!(reversed.get(start.unwrap()).unwrap().path, songs[1].path);
+
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let (mut restored, _) = Player::new(
+        Box::new(NullOutput::default()),
+        start,
+        Arc::clone(&reversed),
+        state,
+    );
+    restored.cue();
+    for command in session.commands(&reversed) {
+        restored.apply(command);
+    }
+
+    assert_eq!(restored.current, start);
+    assert_eq!(restored.track_pos, Duration::from_millis(2500));
+    assert!(restored.is_repeat());
+    let queued: Vec<&str> = restored
+        .queue
+        .iter()
+        .map(|&id| reversed.get(id).unwrap().path.as_str())
+        .collect();
+    assert_eq!(queued, [songs[2].path.as_str(), songs[0].path.as_str()]);
+}
+
+#[test]
+fn autosave_keeps_going_without_the_ui() {
+    let dir = common::sample_library("autosave");
+    let songs = Arc::new(Library::new(load_songs(dir.display().to_string()).unwrap()));
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let handle = Player::spawn(
+        || Box::new(NullOutput::default()),
+        songs.first(),
+        50,
+        Arc::clone(&songs),
+        Arc::clone(&state),
+    );
+
+    let config_path = dir.join("config.json");
+    let session_path = dir.join("session.json");
+    let saver = Autosaver {
+        config: Config::default(),
+        config_path: config_path.clone(),
+        session_path: session_path.clone(),
+        playlist: 0,
+        state: Arc::clone(&state),
+        autosave: Autosave::new(Instant::now()),
+    }
+    .spawn(handle.subscribe());
+
+    // Nobody draws a frame here, the writes still land once things settle
+    handle.send(PlayerCommand::SetVolume(30));
+    handle.send(PlayerCommand::SetRepeat(true));
+    let started = Instant::now();
+    while !(config_path.exists() && session_path.exists()) {
+        assert!(started.elapsed() < SETTLE * 3, "Never saved");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(
+        Config::load(&config_path).unwrap().unwrap().get_volume(),
+        30
+    );
+    assert_eq!(Session::load(&session_path).mode, PlaybackMode::Repeat);
+
+    let (done, saved) = channel();
+    saver.send(AutosaveUpdate::Playlist(0)).unwrap();
+    saver.send(AutosaveUpdate::Flush(done)).unwrap();
+    saved.recv_timeout(Duration::from_secs(1)).unwrap();
+}